@@ -1,3 +1,4 @@
+use rome::net::RomeChannels;
 use crate::{BatteryLevel, hal::{BatteryReader, Encoder}};
 
 
@@ -22,3 +23,22 @@ impl<T: Default + Copy> Encoder<T> for MockEncoder<T> {
     }
 }
 
+
+
+/// Start a ROME server for a mock board, in place of the BLE link
+///
+/// The server address can be overridden with the `ROME_ADDR` environment variable
+/// (see [rome::net::server()] for the format).
+pub fn rome_server(device_name: &str, default_addr: &str) -> Option<RomeChannels> {
+    let addr = rome::net::addr_from_env(default_addr);
+    match rome::net::server(&addr) {
+        Ok(channels) => {
+            log::info!("ROME server for {device_name} listening on {addr}");
+            Some(channels)
+        }
+        Err(err) => {
+            log::error!("Cannot start ROME server on {addr}: {err}");
+            None
+        }
+    }
+}
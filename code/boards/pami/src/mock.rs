@@ -8,7 +8,7 @@ use embedded_graphics::{
     mock_display::MockDisplay,
 };
 use flume::{Receiver, Sender};
-use board_common::mock::{MockBatteryReader, MockEncoder};
+use board_common::mock::{MockBatteryReader, MockEncoder, rome_server};
 use tca6408::TCA6408;
 use vlx::{DistanceData, VlxError, VlxSensor, ZoneAlarm};
 use crate::{PamiBoard, PamiButtons, PamiButtonsState, PamiLeds, PamiMotors, PamiPwmController};
@@ -16,6 +16,11 @@ use crate::{PamiBoard, PamiButtons, PamiButtonsState, PamiLeds, PamiMotors, Pami
 
 pub struct MockPamiBoard;
 
+impl MockPamiBoard {
+    /// Default address of the ROME server
+    pub const ROME_ADDR: &str = "tcp:127.0.0.1:7002";
+}
+
 pub type PamiDisplay = MockDisplay<BinaryColor>;
 
 impl PamiBoard for MockPamiBoard {
@@ -81,8 +86,8 @@ impl PamiBoard for MockPamiBoard {
         None
     }
 
-    fn rome<F: Fn([u8; 6], u32) + Send + Sync +'static>(&mut self, _device_name: String, _passkey_notifier: F) -> Option<(Sender<Box<[u8]>>, Receiver<Box<[u8]>>)> {
-        None
+    fn rome<F: Fn([u8; 6], u32) + Send + Sync +'static>(&mut self, device_name: String, _passkey_notifier: F) -> Option<(Sender<Box<[u8]>>, Receiver<Box<[u8]>>)> {
+        rome_server(&device_name, Self::ROME_ADDR)
     }
 }
 
@@ -7,7 +7,7 @@ use embedded_hal_mock::eh1::{
     spi::{Mock as SpiMock},
 };
 use flume::{Receiver, Sender};
-use board_common::mock::{MockBatteryReader, MockEncoder};
+use board_common::mock::{MockBatteryReader, MockEncoder, rome_server};
 use crate::{OtaHandler, SabotterBoard, SabotterLeds, SabotterMotor, SabotterUart};
 
 
@@ -21,6 +21,11 @@ impl SabotterUart for MockUartLidar {
 
 pub struct MockSabotterBoard;
 
+impl MockSabotterBoard {
+    /// Default address of the ROME server
+    pub const ROME_ADDR: &str = "tcp:127.0.0.1:7001";
+}
+
 impl SabotterBoard for MockSabotterBoard {
     type I2c = I2cMock;
     type OutputPin = PinMock;
@@ -60,8 +65,8 @@ impl SabotterBoard for MockSabotterBoard {
         None
     }
 
-    fn rome(&mut self, _device_name: String, _other_ota_handlers: Vec<Box<dyn OtaHandler>>) -> Option<(Sender<Box<[u8]>>, Receiver<Box<[u8]>>)> {
-        None
+    fn rome(&mut self, device_name: String, _other_ota_handlers: Vec<Box<dyn OtaHandler>>) -> Option<(Sender<Box<[u8]>>, Receiver<Box<[u8]>>)> {
+        rome_server(&device_name, Self::ROME_ADDR)
     }
 }
 
@@ -1,3 +1,4 @@
+use std::time::Duration;
 use asserv::holonomic::{conf::*};
 use board_sabotter::SabotterBoard;
 use galipeur::routines::GalipeurRoutines;
@@ -22,7 +23,7 @@ fn main() {
             max_i: 1000,
             max_out: 0,
             out_shift: 0,
-        },
+        }.into(),
         pid_y: PidConf {
             gain_p: 50,
             gain_i: 1,
@@ -31,7 +32,7 @@ fn main() {
             max_i: 1000,
             max_out: 0,
             out_shift: 0,
-        },
+        }.into(),
         pid_a: PidConf {
             gain_p: 50,
             gain_i: 1,
@@ -40,22 +41,23 @@ fn main() {
             max_i: 1000,
             max_out: 150000,
             out_shift: 0,
-        },
+        }.into(),
         trajectory: TrajectoryConf {
-            a_speed: 3.14 * 200.0,
+            a_speed: 3.14 * 2.0,
             a_acc: 3.14 * 10.0,
-            xy_cruise_speed: 10.0,
-            xy_cruise_acc: 0.2,
-            xy_steering_speed: 4.0,
-            xy_steering_acc: 0.2,
-            xy_stop_speed: 3.0,
-            xy_stop_acc: 0.1,
+            xy_cruise_speed: 1000.0,
+            xy_cruise_acc: 2000.0,
+            xy_jerk: 0.0,
+            xy_steering_speed: 400.0,
+            xy_steering_acc: 2000.0,
+            xy_stop_speed: 300.0,
+            xy_stop_acc: 1000.0,
             xy_steering_window: 50.0,
             xy_stop_window: 10.0,
             a_stop_window: 0.1,
             autoset_speed: 0.0,
-            autoset_wait: 0,
-            autoset_duration: 0,
+            autoset_wait: Duration::ZERO,
+            autoset_duration: Duration::ZERO,
         },
         motors: MotorsConf {
             velocities_to_consigns: [
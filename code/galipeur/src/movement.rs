@@ -4,7 +4,7 @@ use board_sabotter::{Encoder, SabotterBoard, SabotterMotor};
 use embedded_hal::pwm::SetDutyCycle;
 use sch16t::Sch16t;
 
-pub const ASSERV_PERIOD: Duration = Duration::from_millis(15);
+pub const ASSERV_PERIOD: Duration = Duration::from_millis(10);
 
 
 pub struct MovementLowLevelHardware<B: SabotterBoard> {
@@ -6,9 +6,11 @@ use board_common::Periodicity;
 use board_sabotter::SabotterBoard;
 use cancaner::CanMessage;
 use flume::{Receiver, Sender};
+use rome::MessageId;
+use rome::telemetry::TmScheduler;
 use sch16t::Sch16t;
 use crate::led::{LedMessage, Leds};
-use crate::movement::MovementLowLevelHardware;
+use crate::movement::{ASSERV_PERIOD, MovementLowLevelHardware};
 use crate::meca::Meca;
 use crate::can::{GalipeurCan, ota_relay::CanOtaRelayHandler};
 use crate::strat::Strat;
@@ -39,9 +41,11 @@ pub struct GalipeurRoutines<B: SabotterBoard> {
     lidar_tm_ground: bool,
     lidar_tm_top: bool,
 
+    // Telemetry periods, can be changed by ROME peers
+    tm_scheduler: TmScheduler,
+
     // Periodicity states
     asserv_periodicity: Periodicity,
-    asserv_tm_periodicity: Periodicity,
     meca_periodicity: Periodicity,
     meca_tm_periodicity: Periodicity,
     lidar_tm_periodicity: Periodicity,
@@ -80,11 +84,17 @@ impl<B: SabotterBoard + 'static> GalipeurRoutines<B> {
         let sensors = Sensors::new(board, can_interface.clone(), led_sender.clone(), top_lidar_conf);
 
         // Setup asserv
-        let asserv = Arc::new(Mutex::new(Asserv::new(asserv_hardware)));
+        let asserv = Arc::new(Mutex::new(Asserv::new(asserv_hardware, ASSERV_PERIOD)));
 
         // Setup strat
         Strat::init(board, led_sender.clone(), sensors.clone(), meca.clone() ,asserv.clone());
 
+        // Setup telemetry
+        let mut tm_scheduler = TmScheduler::new();
+        tm_scheduler.register(MessageId::AsservTmStatus, Some(Duration::from_millis(500)));
+        tm_scheduler.register(MessageId::AsservHoloTmStatus, Some(Duration::from_millis(500)));
+        tm_scheduler.register(MessageId::AsservHoloTmPath, Some(Duration::from_millis(500)));
+
         Self {
             asserv,
             meca,
@@ -101,8 +111,9 @@ impl<B: SabotterBoard + 'static> GalipeurRoutines<B> {
             lidar_tm_ground: false,
             lidar_tm_top: false,
 
-            asserv_periodicity: Periodicity::new(Duration::from_millis(10)),
-            asserv_tm_periodicity: Periodicity::new(Duration::from_millis(500)),
+            tm_scheduler,
+
+            asserv_periodicity: Periodicity::new(ASSERV_PERIOD),
             meca_periodicity: Periodicity::new(Duration::from_millis(1000)),
             meca_tm_periodicity: Periodicity::new(Duration::from_millis(1000)),
             lidar_tm_periodicity: Periodicity::new(Duration::from_millis(2000)),
@@ -136,8 +147,8 @@ impl<B: SabotterBoard + 'static> GalipeurRoutines<B> {
         //    match rome::Message::decode(&data) {
         //        Err(err) => log::error!("ROME RX error: {err:?}"),
         //        Ok(message) => {
-        //            if !self.on_rome_message(&message) && !self.asserv.on_rome_message(&message) {
-        //                log::warn!("ROME: ignored message: {}", message.message_id());
+        //            if rome::dispatch(&message, &mut [&mut *self.asserv.lock().unwrap(), &mut self.tm_scheduler]) == rome::Handled::No {
+        //                log::warn!("ROME: ignored message: {message}");
         //            }
         //        },
         //    }
@@ -145,20 +156,22 @@ impl<B: SabotterBoard + 'static> GalipeurRoutines<B> {
         
         // Update asserv, send asserv telemetry
         if self.asserv_periodicity.update(now) {
-            self.asserv.lock().unwrap().update();
+            self.asserv.lock().unwrap().update(self.asserv_periodicity.period());
         }
-        //if self.asserv_tm_periodicity.update(now) {
-        //    if let Err(err) = self.rome_tx.send(self.asserv.asserv_tm_status().encode()) {
-        //        log::error!("ROME send error: {:?}", err);
-        //    }
-        //    if let Err(err) = self.rome_tx.send(self.asserv.asserv_holo_tm_status().encode()) {
+        //for message_id in self.tm_scheduler.poll(*now) {
+        //    let asserv = self.asserv.lock().unwrap();
+        //    let message = match message_id {
+        //        MessageId::AsservTmStatus => asserv.asserv_tm_status(),
+        //        MessageId::AsservHoloTmStatus => asserv.asserv_holo_tm_status(),
+        //        MessageId::AsservHoloTmPath => match asserv.asserv_holo_tm_path() {
+        //            Some(message) => message,
+        //            None => continue,
+        //        },
+        //        _ => continue,
+        //    };
+        //    if let Err(err) = self.rome_tx.send(message.encode()) {
         //        log::error!("ROME send error: {:?}", err);
         //    }
-        //    if let Some(message) = self.asserv.asserv_holo_tm_path() {
-        //        if let Err(err) = self.rome_tx.send(message.encode()) {
-        //            log::error!("ROME send error: {:?}", err);
-        //        }
-        //    }
         //}
 
         // Update meca, send meca telemetry
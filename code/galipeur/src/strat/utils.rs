@@ -1,6 +1,6 @@
 use std::sync::{Arc, Mutex};
 
-use asserv::{holonomic::{Asserv, RobotSide, TableSide}, maths::XY};
+use asserv::{holonomic::{Asserv, RobotSide, TableSide, Waypoint}, maths::XY};
 use board_sabotter::SabotterBoard;
 
 use crate::{movement::MovementLowLevelHardware, strat::errors::StrategyError};
@@ -52,6 +52,11 @@ impl<B: SabotterBoard> AsservHelper<B> {
         self.wait()
     }
 
+    pub fn run_path_xya(&self, path: &[Waypoint]) -> Result<(), StrategyError> {
+        self.asserv.lock().unwrap().run_path_xya(path);
+        self.wait()
+    }
+
     fn wait(&self) -> Result<(), StrategyError> {
         //TODO damien use passive waiting with Sender/Receiver
         loop {
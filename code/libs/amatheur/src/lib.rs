@@ -50,6 +50,28 @@ pub fn mult_matrix33_vec(m: &Matrix33, v: &[f32; 3]) -> [f32; 3] {
     result
 }
 
+/// Invert a 3x3 matrix, return `None` if it is not invertible
+pub fn invert_matrix33(m: &Matrix33) -> Option<Matrix33> {
+    // Cofactor of item at row `i`, column `j`
+    let cofactor = |i: usize, j: usize| {
+        let (i1, i2) = ((i + 1) % 3, (i + 2) % 3);
+        let (j1, j2) = ((j + 1) % 3, (j + 2) % 3);
+        m[i1 * 3 + j1] * m[i2 * 3 + j2] - m[i1 * 3 + j2] * m[i2 * 3 + j1]
+    };
+    let det = m[0] * cofactor(0, 0) + m[1] * cofactor(0, 1) + m[2] * cofactor(0, 2);
+    if det == 0.0 {
+        return None;
+    }
+    let mut result = [0.0; 9];
+    for i in 0..3 {
+        for j in 0..3 {
+            // Inverse is the transposed matrix of cofactors, divided by determinant
+            result[j * 3 + i] = cofactor(i, j) / det;
+        }
+    }
+    Some(result)
+}
+
 
 /// 2D vector for linear coordinates
 #[derive(Default, Clone, Copy, Debug)]
@@ -74,6 +96,11 @@ impl XY {
         self.x * self.x + self.y * self.y
     }
 
+    /// Return the dot product with another vector
+    pub fn dot(&self, other: &XY) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
     /// Return a unit vectory of same direction, or (0, 0)
     #[cfg(feature = "std")]
     pub fn unit(&self) -> Self {
@@ -47,3 +47,77 @@ impl PidConf {
     }
 }
 
+
+
+/// Configuration of a floating-point PID, in physical units
+///
+/// Values are in units of the controlled axis (mm or rad), and seconds.
+/// The output of a PID with only a proportional gain of `gain_p / 2^out_shift`
+/// is the same as an integer PID.
+#[derive(Clone)]
+pub struct FloatPidConf {
+    /// Proportional gain
+    pub kp: f32,
+    /// Integral gain, per second
+    pub ki: f32,
+    /// Derivative gain, in seconds
+    pub kd: f32,
+    /// Time constant of the derivative low-pass filter, in seconds (0 to disable filtering)
+    pub d_filter: f32,
+    /// Back-calculation gain of the integral anti-windup, per second (0 to disable it)
+    ///
+    /// When the output is limited, the integral is decreased by `kaw` times the excess.
+    pub kaw: f32,
+    /// Feed-forward gain on target velocity, in seconds
+    pub kff_v: f32,
+    /// Feed-forward gain on target acceleration, in seconds²
+    pub kff_a: f32,
+    /// Maximum absolute output value
+    pub max_out: f32,
+    /// Maximum output change, per second
+    pub max_out_rate: f32,
+}
+
+impl Default for FloatPidConf {
+    fn default() -> Self {
+        Self {
+            kp: 1.0,
+            ki: 0.0,
+            kd: 0.0,
+            d_filter: 0.0,
+            kaw: 0.0,
+            kff_v: 0.0,
+            kff_a: 0.0,
+            max_out: f32::INFINITY,
+            max_out_rate: f32::INFINITY,
+        }
+    }
+}
+
+
+/// Controller configuration of an asserv axis
+#[derive(Clone)]
+pub enum ControllerConf {
+    /// Integer PID, in internal units
+    Pid(PidConf),
+    /// Floating-point PID, in physical units
+    FloatPid(FloatPidConf),
+}
+
+impl Default for ControllerConf {
+    fn default() -> Self {
+        Self::Pid(PidConf::default())
+    }
+}
+
+impl From<PidConf> for ControllerConf {
+    fn from(conf: PidConf) -> Self {
+        Self::Pid(conf)
+    }
+}
+
+impl From<FloatPidConf> for ControllerConf {
+    fn from(conf: FloatPidConf) -> Self {
+        Self::FloatPid(conf)
+    }
+}
@@ -1,4 +1,4 @@
-pub use crate::conf::PidConf;
+pub use crate::conf::{ControllerConf, FloatPidConf, PidConf};
 
 
 /// Implement asserv hardware behavior
@@ -20,8 +20,8 @@ pub trait AsservHardware {
 /// Having everything in one struct helps to not forget a value.
 #[derive(Clone, Default)]
 pub struct AsservConf {
-    pub pid_dist: PidConf,
-    pub pid_angle: PidConf,
+    pub pid_dist: ControllerConf,
+    pub pid_angle: ControllerConf,
     pub motors: MotorsConf,
     pub trajectory: TrajectoryConf,
 }
@@ -37,10 +37,14 @@ pub struct TrajectoryConf {
     pub a_speed: f32,
     /// Maximum angular acceleration
     pub a_acc: f32,
+    /// Maximum angular jerk, 0 for trapezoidal speed profiles
+    pub a_jerk: f32,
     /// Maximum linear speed
     pub xy_speed: f32,
     /// Maximum linear acceleration
     pub xy_acc: f32,
+    /// Maximum linear jerk, 0 for trapezoidal speed profiles
+    pub xy_jerk: f32,
     /// Tolerance linear distance
     pub xy_stop_window: f32,
     /// Tolerance angle when aiming before linear move
@@ -55,6 +59,16 @@ pub struct TrajectoryConf {
     pub xy_idle_speed: f32,
     /// Angular speed under which robot is considered idle
     pub a_idle_speed: f32,
+    /// Lookahead distance when following a path, must be positive
+    pub xy_path_lookahead: f32,
+    /// Maximum lateral acceleration when following a path, 0 to not limit speed in curves
+    pub xy_path_lateral_acc: f32,
+    /// Speed for autoset moves, towards the wall
+    pub autoset_speed: f32,
+    /// Distance to move away from the wall at the end of autoset
+    pub autoset_backoff: f32,
+    /// Maximum duration of each autoset move, autoset is aborted when it expires
+    pub autoset_timeout: f32,
 }
 
 
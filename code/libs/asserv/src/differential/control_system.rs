@@ -1,9 +1,36 @@
 use core::time::Duration;
 use crate::maths::XYA;
+use crate::trace::{TraceRecorder, trace_sample};
 use super::motor_filter::MotorFilter;
 use super::AsservHardware;
 
 
+trace_sample! {
+    /// Trace sample of the differential asserv, recorded on each update
+    ///
+    /// PID inputs and outputs are in controller units: internal units for
+    /// integer PIDs, mm or rad for floating-point PIDs.
+    pub struct AsservSample {
+        x,
+        y,
+        a,
+        dist,
+        target_dist,
+        target_a,
+        /// Distance target, after the ramp
+        ramp_dist,
+        /// Angle target, after the ramp
+        ramp_a,
+        pid_in_dist,
+        pid_in_a,
+        pid_out_dist,
+        pid_out_a,
+        consign_left,
+        consign_right,
+    }
+}
+
+
 pub struct ControlSystem<H: AsservHardware> {
     pub(crate) hardware: H,
     pub(crate) motor_filter: MotorFilter,
@@ -20,6 +47,8 @@ pub struct ControlSystem<H: AsservHardware> {
 
     tick_to_mm: f32,
     tick_to_rad: f32,
+
+    trace: Option<TraceRecorder<AsservSample>>,
 }
 
 impl<H: AsservHardware> ControlSystem<H> {
@@ -36,12 +65,13 @@ impl<H: AsservHardware> ControlSystem<H> {
             // Note: those values will never be correct
             tick_to_mm: 1.0,
             tick_to_rad: 1.0,
+            trace: None,
         }
     }
 
     pub(crate) fn update(&mut self, elapsed: &Duration) {
         self.update_position(elapsed);
-        self.update_motors();
+        self.update_motors(elapsed);
     }
 
     fn update_position(&mut self, elapsed: &Duration) {
@@ -62,12 +92,14 @@ impl<H: AsservHardware> ControlSystem<H> {
         self.position.y += self.position.a.sin() * d_dist;
     }
 
-    fn update_motors(&mut self) {
+    fn update_motors(&mut self, elapsed: &Duration) {
         let speeds = if self.hardware.emergency_stop_active() {
+            // Robot is not driven, ramps must not resume from their previous speed
             self.reset_targets();
+            self.motor_filter.reset(self.dist, self.position.a);
             [0.0, 0.0]
         } else {
-            let (dist_speed, angle_speed) = self.motor_filter.filter(self.dist, self.position.a, self.target_dist, self.target_angle);
+            let (dist_speed, angle_speed) = self.motor_filter.filter(self.dist, self.position.a, self.target_dist, self.target_angle, elapsed.as_secs_f32());
 
             // Assign the control loop output to the motors
             let left_speed = dist_speed - angle_speed;
@@ -77,6 +109,29 @@ impl<H: AsservHardware> ControlSystem<H> {
         };
 
         self.hardware.set_motor_consigns(speeds);
+        self.record_trace(speeds);
+    }
+
+    fn record_trace(&mut self, consigns: [f32; 2]) {
+        if let Some(trace) = &mut self.trace {
+            let filter = self.motor_filter.last_state();
+            trace.record(AsservSample {
+                x: self.position.x,
+                y: self.position.y,
+                a: self.position.a,
+                dist: self.dist,
+                target_dist: self.target_dist,
+                target_a: self.target_angle,
+                ramp_dist: filter.ramp_dist,
+                ramp_a: filter.ramp_angle,
+                pid_in_dist: filter.pid_in_dist,
+                pid_in_a: filter.pid_in_angle,
+                pid_out_dist: filter.pid_out_dist,
+                pid_out_a: filter.pid_out_angle,
+                consign_left: consigns[0],
+                consign_right: consigns[1],
+            });
+        }
     }
 
     /// Return current position
@@ -94,6 +149,11 @@ impl<H: AsservHardware> ControlSystem<H> {
         (self.speed_dist, self.speed_angle)
     }
 
+    /// Return distance target after the ramp, as used by the last update
+    pub fn ramp_dist(&self) -> f32 {
+        self.motor_filter.last_state().ramp_dist
+    }
+
     /// Reset robot position, and target (but not consigns)
     ///
     /// This method should only be called when robot is not moving.
@@ -104,7 +164,7 @@ impl<H: AsservHardware> ControlSystem<H> {
         self.target_angle = self.position.a;
         self.speed_dist = 0.0;
         self.speed_angle = 0.0;
-        self.motor_filter.reset();
+        self.motor_filter.reset(self.dist, self.position.a);
     }
 
     /// Reset current targets to current position
@@ -133,6 +193,36 @@ impl<H: AsservHardware> ControlSystem<H> {
         self.motor_filter.set_angle_ramp_conf(speed, acc, step_secs);
     }
 
+    /// Set linear jerk, 0 to disable jerk limitation
+    pub fn set_xy_jerk(&mut self, jerk: f32, step_secs: f32) {
+        self.motor_filter.set_dist_ramp_jerk(jerk, step_secs);
+    }
+
+    /// Set angular jerk, 0 to disable jerk limitation
+    pub fn set_a_jerk(&mut self, jerk: f32, step_secs: f32) {
+        self.motor_filter.set_angle_ramp_jerk(jerk, step_secs);
+    }
+
+    /// Record a trace of the last `capacity` updates, drop the previous one
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(TraceRecorder::new(capacity));
+    }
+
+    /// Stop trace recording, drop recorded samples
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Return the trace recorder, if enabled
+    pub fn trace(&self) -> Option<&TraceRecorder<AsservSample>> {
+        self.trace.as_ref()
+    }
+
+    /// Return the trace recorder, if enabled
+    pub fn trace_mut(&mut self) -> Option<&mut TraceRecorder<AsservSample>> {
+        self.trace.as_mut()
+    }
+
     /// Set encoder tick ratios
     pub fn set_encoder_conversion(&mut self, tick_to_mm: f32, tick_to_rad: f32) {
         self.tick_to_mm = tick_to_mm;
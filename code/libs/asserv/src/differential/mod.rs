@@ -3,11 +3,15 @@ mod control_system;
 mod motor_filter;
 #[cfg(feature = "rome")]
 pub mod rome;
+#[cfg(feature = "sim")]
+pub mod sim;
 
 use core::time::Duration;
 use crate::maths::{XY, XYA, normalize_radians_pi_pi};
+pub use crate::TableSide;
 use conf::*;
 use control_system::ControlSystem;
+pub use control_system::AsservSample;
 
 
 #[derive(Default)]
@@ -19,6 +23,40 @@ struct AsservInternalConf {
     pub a_stop_window: f32,
     pub xy_idle_speed: f32,
     pub a_idle_speed: f32,
+    pub a_speed: f32,
+    pub a_acc: f32,
+    pub xy_acc: f32,
+    pub xy_path_lookahead: f32,
+    pub xy_path_lateral_acc: f32,
+    pub autoset_speed: f32,
+    pub autoset_backoff: f32,
+    pub autoset_timeout: f32,
+}
+
+/// Maximum number of points for a trajectory path
+pub const TRAJECTORY_MAX_POINTS: u8 = 15;
+
+/// Duration of autoset move at full speed lagging behind the target, to consider the robot blocked (seconds)
+const AUTOSET_BLOCKED_DELAY: f32 = 0.2;
+
+
+/// Driving direction of XY orders
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveDirection {
+    /// Aim at the target with the front of the robot, drive forward
+    Forward,
+    /// Aim at the target with the rear of the robot, drive backward
+    Backward,
+    /// Use the direction requiring the smallest rotation when aiming
+    Shortest,
+}
+
+
+/// Robot side pushed against the wall on autoset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RobotSide {
+    Front,
+    Back,
 }
 
 
@@ -29,9 +67,123 @@ enum TrajectoryOrder {
     /// Order finished, waiting for motors to stop
     Stop,
     /// Goto XY order is active
-    Xy { xy: XY, aiming: bool, stopping: bool },
+    Xy { xy: XY, direction: MoveDirection, aiming: bool, stopping: bool },
     /// Goto angle order is active
     Angle(f32),
+    /// Path order is active
+    Path(std::cell::RefCell<PathData>),
+    /// Autoset order is active
+    Autoset(AutosetData),
+}
+
+/// Information needed for autoset movement
+#[derive(Clone, Copy)]
+struct AutosetData {
+    robot_side: RobotSide,
+    table_side: TableSide,
+    target: XY,  // Only one coordinate will be used
+    state: AutosetState,
+}
+
+impl AutosetData {
+    /// Known angle at the end of autoset move
+    fn target_a(&self) -> f32 {
+        // Angle of the wall normal, towards the wall
+        let a = match self.table_side {
+            TableSide::Left => core::f32::consts::PI,
+            TableSide::Right => 0.0,
+            TableSide::Up => core::f32::consts::FRAC_PI_2,
+            TableSide::Down => -core::f32::consts::FRAC_PI_2,
+        };
+        match self.robot_side {
+            RobotSide::Front => a,
+            RobotSide::Back => normalize_radians_pi_pi(a + core::f32::consts::PI),
+        }
+    }
+
+    /// Sign of the distance driven towards the wall
+    fn direction(&self) -> f32 {
+        match self.robot_side {
+            RobotSide::Front => 1.0,
+            RobotSide::Back => -1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum AutosetState {
+    /// Turn the robot side towards the wall
+    Aiming,
+    /// Drive slowly into the wall, until the robot is stopped
+    Moving { carrot: f32, elapsed: f32 },
+    /// Move away from the wall, after position has been reset
+    BackingOff { elapsed: f32 },
+}
+
+/// Information needed for path movement
+///
+/// The robot follows the path with a pure pursuit: it aims at a point of the
+/// path, at a lookahead distance from its projection on the path.
+/// Robot only stops on the last point.
+struct PathData {
+    /// Robot position when the path was started, start of the first segment
+    origin: XY,
+    points: [XY; TRAJECTORY_MAX_POINTS as usize],
+    size: u8,
+    /// Index of the end point of the current segment
+    index: u8,
+    aiming: bool,
+    stopping: bool,
+    /// Remaining distance along the path, from the robot projection
+    remaining: f32,
+}
+
+impl PathData {
+    /// Return start and end points of a segment
+    fn segment(&self, index: u8) -> (XY, XY) {
+        let start = if index == 0 { self.origin } else { self.points[index as usize - 1] };
+        (start, self.points[index as usize])
+    }
+
+    fn last_point(&self) -> &XY {
+        &self.points[self.size as usize - 1]
+    }
+
+    fn is_last_segment(&self) -> bool {
+        self.index + 1 >= self.size
+    }
+
+    /// Switch to the next segments, if the robot is past the end of the current one
+    fn update_index(&mut self, position: &XY) {
+        while !self.is_last_segment() {
+            let (start, end) = self.segment(self.index);
+            let (current, t) = project_on_segment(position, &start, &end);
+            let (start, end) = self.segment(self.index + 1);
+            let (next, _) = project_on_segment(position, &start, &end);
+            // Robot may never reach the end of the segment, when cutting a corner
+            if t >= 1.0 || (position - &next).length2() < (position - &current).length2() {
+                self.index += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Return the point of the path at given distance after a point of the current segment
+    ///
+    /// Stop on the last point.
+    fn point_ahead(&self, from: &XY, mut distance: f32) -> XY {
+        let mut from = *from;
+        for point in &self.points[self.index as usize .. self.size as usize] {
+            let length = (point - &from).length();
+            if distance < length {
+                return from + (point - &from) * (distance / length);
+            }
+            distance -= length;
+            from = *point;
+        }
+        from
+    }
 }
 
 
@@ -71,30 +223,60 @@ impl<H: AsservHardware> Asserv<H> {
 
     /// Fully configure the asserv and fully reset position()
     pub fn set_conf(&mut self, conf: AsservConf) {
-        self.set_dist_pid_conf(conf.pid_dist);
-        self.set_angle_pid_conf(conf.pid_angle);
+        self.set_dist_controller_conf(conf.pid_dist);
+        self.set_angle_controller_conf(conf.pid_angle);
         self.set_trajectory_conf(conf.trajectory);
         self.set_motors_conf(conf.motors);
         self.reset_position(XYA::new(0.0, 0.0, 0.0));
     }
 
-    /// Set dist PID configuration
+    /// Set dist controller configuration, select the controller type
+    pub fn set_dist_controller_conf(&mut self, conf: ControllerConf) {
+        self.cs.motor_filter.set_dist_controller_conf(conf);
+    }
+
+    /// Set angle controller configuration, select the controller type
+    pub fn set_angle_controller_conf(&mut self, conf: ControllerConf) {
+        self.cs.motor_filter.set_angle_controller_conf(conf);
+    }
+
+    /// Set dist integer PID configuration
+    ///
+    /// The controller type is not changed.
     pub fn set_dist_pid_conf(&mut self, conf: PidConf) {
         *self.cs.motor_filter.pid_dist_conf_mut() = conf;
     }
 
-    /// Set angle PID configuration
+    /// Set angle integer PID configuration
+    ///
+    /// The controller type is not changed.
     pub fn set_angle_pid_conf(&mut self, conf: PidConf) {
         *self.cs.motor_filter.pid_angle_conf_mut() = conf;
     }
 
+    /// Get dist integer PID configuration
+    pub fn dist_pid_conf(&self) -> &PidConf {
+        self.cs.motor_filter.pid_dist_conf()
+    }
+
+    /// Get angle integer PID configuration
+    pub fn angle_pid_conf(&self) -> &PidConf {
+        self.cs.motor_filter.pid_angle_conf()
+    }
+
     /// Set trajectory configuration
     pub fn set_trajectory_conf(&mut self, conf: TrajectoryConf) {
         self.set_a_speed(conf.a_speed, conf.a_acc);
         self.set_xy_speed(conf.xy_speed, conf.xy_acc);
+        self.set_a_jerk(conf.a_jerk);
+        self.set_xy_jerk(conf.xy_jerk);
         self.set_xy_order_windows(conf.xy_stop_window, conf.xy_aim_angle_window, conf.xy_cruise_angle_window, conf.xy_approach_window);
         self.set_angle_order_window(conf.a_stop_window);
         self.set_idle_speed(conf.xy_idle_speed, conf.a_idle_speed);
+        self.set_path_conf(conf.xy_path_lookahead, conf.xy_path_lateral_acc);
+        self.set_autoset_speed(conf.autoset_speed);
+        self.set_autoset_backoff(conf.autoset_backoff);
+        self.set_autoset_timeout(conf.autoset_timeout);
     }
 
     /// Set motors configuration
@@ -137,6 +319,11 @@ impl<H: AsservHardware> Asserv<H> {
         matches!(self.order, TrajectoryOrder::Idle)
     }
 
+    /// Return true if an autoset is in progress
+    pub fn autoset_in_progress(&self) -> bool {
+        matches!(self.order, TrajectoryOrder::Autoset(_))
+    }
+
 
     //
     // Movement orders
@@ -144,9 +331,14 @@ impl<H: AsservHardware> Asserv<H> {
 
     /// Go to given linear position, reset angle target
     pub fn goto_xy(&mut self, x: f32, y: f32) {
+        self.goto_xy_dir(x, y, MoveDirection::Forward);
+    }
+
+    /// Go to given linear position, driving in given direction, reset angle target
+    pub fn goto_xy_dir(&mut self, x: f32, y: f32, direction: MoveDirection) {
         let xy = XY::new(x, y);
         self.cs.reset_targets();
-        self.order = TrajectoryOrder::Xy{ xy, aiming: true, stopping: true };
+        self.order = TrajectoryOrder::Xy{ xy, direction, aiming: true, stopping: true };
     }
 
     /// Same as [goto_xy()] but position is relative to current one
@@ -168,19 +360,78 @@ impl<H: AsservHardware> Asserv<H> {
         self.goto_a(self.cs.position().a + da);
     }
 
+    /// Start an autoset procedure
+    ///
+    /// Robot turns its side towards the wall, then drives slowly into it.
+    /// When the robot is stopped by the wall, angle and the coordinate against
+    /// the wall are reset, then robot moves away from the wall.
+    /// Only the `target` coordinate against the table side is used, the other one is kept.
+    ///
+    /// If a move lasts longer than the autoset timeout (for instance, the wall is
+    /// not reached), autoset is aborted and the robot stops.
+    /// Position is not reset if the wall has not been reached.
+    pub fn autoset(&mut self, robot_side: RobotSide, table_side: TableSide, target: XY) {
+        self.cs.reset_targets();
+        self.order = TrajectoryOrder::Autoset(AutosetData {
+            robot_side,
+            table_side,
+            target,
+            state: AutosetState::Aiming,
+        });
+    }
+
+    /// Load and run a trajectory path, reset angle target
+    ///
+    /// Intermediate points are not reached exactly: robot blends through them,
+    /// without stopping, slowing down in curves.
+    pub fn run_path(&mut self, path: &[XY]) {
+        self.cs.reset_targets();
+        if path.is_empty() {
+            // Empty path: stop to current position
+            self.order = TrajectoryOrder::Stop;
+            return;
+        }
+        // Truncate path len if needed
+        let n = (TRAJECTORY_MAX_POINTS as usize).min(path.len());
+        let mut path_data = PathData {
+            origin: self.cs.position().xy(),
+            points: Default::default(),
+            size: n as u8,
+            index: 0,
+            aiming: true,
+            stopping: true,
+            remaining: 0.0,
+        };
+        path_data.points[0..n].copy_from_slice(&path[0..n]);
+        self.order = TrajectoryOrder::Path(path_data.into());
+    }
+
 
     //
     // Configuration setters
     //
 
     pub fn set_a_speed(&mut self, speed: f32, acc: f32) {
+        self.conf.a_speed = speed;
+        self.conf.a_acc = acc;
         self.cs.set_a_speed(speed, acc, self.update_period_secs);
     }
 
     pub fn set_xy_speed(&mut self, speed: f32, acc: f32) {
+        self.conf.xy_acc = acc;
         self.cs.set_xy_speed(speed, acc, self.update_period_secs);
     }
 
+    /// Set angular jerk, 0 to use trapezoidal speed profiles
+    pub fn set_a_jerk(&mut self, jerk: f32) {
+        self.cs.set_a_jerk(jerk, self.update_period_secs);
+    }
+
+    /// Set linear jerk, 0 to use trapezoidal speed profiles
+    pub fn set_xy_jerk(&mut self, jerk: f32) {
+        self.cs.set_xy_jerk(jerk, self.update_period_secs);
+    }
+
     pub fn set_xy_order_windows(&mut self, xy_win: f32, aim_da: f32, cruise_da: f32, approach_win: f32) {
         self.conf.xy_stop_window = xy_win;
         self.conf.xy_aim_angle_window = aim_da;
@@ -197,9 +448,28 @@ impl<H: AsservHardware> Asserv<H> {
         self.conf.a_idle_speed = a;
     }
 
+    pub fn set_path_conf(&mut self, lookahead: f32, lateral_acc: f32) {
+        self.conf.xy_path_lookahead = lookahead;
+        self.conf.xy_path_lateral_acc = lateral_acc;
+    }
+
+    /// Set speed of autoset moves, towards the wall
+    pub fn set_autoset_speed(&mut self, speed: f32) {
+        self.conf.autoset_speed = speed;
+    }
+
+    /// Set distance to move away from the wall, at the end of autoset
+    pub fn set_autoset_backoff(&mut self, distance: f32) {
+        self.conf.autoset_backoff = distance;
+    }
+
+    /// Set maximum duration of each autoset move, in seconds
+    pub fn set_autoset_timeout(&mut self, timeout: f32) {
+        self.conf.autoset_timeout = timeout;
+    }
+
     /// Reset position, target, consigns
     pub fn reset_position(&mut self, xya: XYA) {
-        self.cs.motor_filter.reset();
         self.cs.reset_position(xya);
     }
 
@@ -222,24 +492,38 @@ impl<H: AsservHardware> Asserv<H> {
                 }
             }
 
-            TrajectoryOrder::Xy { xy, aiming, stopping: true } => {
+            TrajectoryOrder::Xy { xy, direction, aiming, stopping: true } => {
                 if self.idle_speeds() {
-                    self.order = TrajectoryOrder::Xy { xy: *xy, aiming: *aiming, stopping: false };
+                    self.order = TrajectoryOrder::Xy { xy: *xy, direction: *direction, aiming: *aiming, stopping: false };
                 }
             }
 
-            TrajectoryOrder::Xy { xy, aiming, stopping: false } => {
+            TrajectoryOrder::Xy { xy, direction, aiming, stopping: false } => {
                 let dxy = xy - &self.cs.position().xy();
                 let angle_to_target = dxy.angle();
                 let current_a = self.cs.position().a;
-                let da = normalize_radians_pi_pi(angle_to_target - current_a );
+                let da_forward = normalize_radians_pi_pi(angle_to_target - current_a);
+                let da_backward = normalize_radians_pi_pi(angle_to_target + core::f32::consts::PI - current_a);
+                let backward = match direction {
+                    MoveDirection::Forward => false,
+                    MoveDirection::Backward => true,
+                    MoveDirection::Shortest => da_backward.abs() < da_forward.abs(),
+                };
+                // Angle of the robot side facing the target, signed distance
+                let (da, len) = if backward {
+                    (da_backward, -dxy.length())
+                } else {
+                    (da_forward, dxy.length())
+                };
+                // Keep the same direction until target is reached
+                let direction = if backward { MoveDirection::Backward } else { MoveDirection::Forward };
 
                 if *aiming {
                     if da.abs() <= self.conf.xy_aim_angle_window {
                         // Aiming complete
                         self.cs.reset_targets();
-                        self.cs.set_target_dist(self.cs.dist() + dxy.length());
-                        self.order = TrajectoryOrder::Xy { xy: *xy, aiming: false, stopping: true };
+                        self.cs.set_target_dist(self.cs.dist() + len);
+                        self.order = TrajectoryOrder::Xy { xy: *xy, direction, aiming: false, stopping: true };
                     } else {
                         self.cs.set_target_a(current_a + da);
                     }
@@ -250,17 +534,119 @@ impl<H: AsservHardware> Asserv<H> {
                     // Start aiming
                     self.cs.reset_targets();
                     self.cs.set_target_a(current_a + da);
-                    self.order = TrajectoryOrder::Xy { xy: *xy, aiming: true, stopping: true };
+                    self.order = TrajectoryOrder::Xy { xy: *xy, direction, aiming: true, stopping: true };
                 } else {
                     // Update targets
-                    let len = dxy.length();
-                    if len > self.conf.xy_approach_window {
+                    if len.abs() > self.conf.xy_approach_window {
                         self.cs.set_target_a(current_a + da);
                     }
                     self.cs.set_target_dist(self.cs.dist() + len);
                 }
             },
 
+            TrajectoryOrder::Path(path_data) => {
+                let mut path = path_data.borrow_mut();
+                if path.stopping {
+                    if self.idle_speeds() {
+                        path.stopping = false;
+                    }
+                    return;
+                }
+
+                let position = self.cs.position().xy();
+                path.update_index(&position);
+                let (start, end) = path.segment(path.index);
+                let (projection, _) = project_on_segment(&position, &start, &end);
+                let (target_dist, remaining) = self.path_target_dist(&path, &projection);
+                let lookahead = path.point_ahead(&projection, self.conf.xy_path_lookahead);
+
+                let dxy = lookahead - position;
+                let current_a = self.cs.position().a;
+                let da = normalize_radians_pi_pi(dxy.angle() - current_a);
+                let last_dxy = path.last_point() - &position;
+
+                if path.aiming {
+                    if da.abs() <= self.conf.xy_aim_angle_window {
+                        // Aiming complete
+                        self.cs.reset_targets();
+                        self.cs.set_target_dist(self.cs.dist() + target_dist);
+                        path.aiming = false;
+                        path.stopping = true;
+                    } else {
+                        self.cs.set_target_a(current_a + da);
+                    }
+                } else if path.is_last_segment() && in_window_xy(&last_dxy, self.conf.xy_stop_window) {
+                    // Last point reached
+                    drop(path);
+                    self.order = TrajectoryOrder::Stop;
+                    return;
+                } else if da.abs() > self.conf.xy_cruise_angle_window {
+                    // Start aiming
+                    self.cs.reset_targets();
+                    self.cs.set_target_a(current_a + da);
+                    path.aiming = true;
+                    path.stopping = true;
+                } else {
+                    // Update targets
+                    if last_dxy.length() > self.conf.xy_approach_window {
+                        self.cs.set_target_a(current_a + da);
+                    }
+                    self.cs.set_target_dist(self.cs.dist() + target_dist);
+                }
+                path.remaining = remaining;
+            },
+
+            TrajectoryOrder::Autoset(autoset) => {
+                let mut autoset = *autoset;
+                let direction = autoset.direction();
+                match autoset.state {
+                    AutosetState::Aiming => {
+                        let current_a = self.cs.position().a;
+                        let da = normalize_radians_pi_pi(autoset.target_a() - current_a);
+                        self.cs.set_target_a(current_a + da);
+                        if da.abs() < self.conf.a_stop_window && self.idle_speeds() {
+                            autoset.state = AutosetState::Moving { carrot: self.cs.dist(), elapsed: 0.0 };
+                        }
+                    }
+                    AutosetState::Moving { elapsed, .. } | AutosetState::BackingOff { elapsed } if elapsed >= self.conf.autoset_timeout => {
+                        // Wall not reached, or cannot move away from it: abort
+                        self.order = TrajectoryOrder::Stop;
+                        return;
+                    }
+                    AutosetState::Moving { carrot, elapsed } => {
+                        // Advance slowly, robot is blocked when it lags behind the ramped target without moving
+                        let carrot = carrot + direction * self.conf.autoset_speed * self.update_period_secs;
+                        self.cs.set_target_dist(carrot);
+                        let lag = (self.cs.ramp_dist() - self.cs.dist()) * direction;
+                        let (dist_speed, _) = self.cs.speeds();
+                        if lag > self.conf.autoset_speed * AUTOSET_BLOCKED_DELAY && dist_speed.abs() < self.conf.autoset_speed / 2.0 {
+                            // Against the wall: reset position, keep the coordinate along the wall
+                            let position = *self.cs.position();
+                            let a = position.a + normalize_radians_pi_pi(autoset.target_a() - position.a);
+                            let position = match autoset.table_side {
+                                TableSide::Left | TableSide::Right => XYA::new(autoset.target.x, position.y, a),
+                                TableSide::Up | TableSide::Down => XYA::new(position.x, autoset.target.y, a),
+                            };
+                            self.reset_position(position);
+                            self.cs.set_target_dist(-direction * self.conf.autoset_backoff);
+                            autoset.state = AutosetState::BackingOff { elapsed: 0.0 };
+                        } else {
+                            autoset.state = AutosetState::Moving { carrot, elapsed: elapsed + self.update_period_secs };
+                        }
+                    }
+                    AutosetState::BackingOff { elapsed } => {
+                        let remaining = self.cs.dist() + direction * self.conf.autoset_backoff;
+                        if remaining.abs() < self.conf.xy_stop_window {
+                            // Autoset done
+                            self.order = TrajectoryOrder::Stop;
+                            return;
+                        }
+                        autoset.state = AutosetState::BackingOff { elapsed: elapsed + self.update_period_secs };
+                    }
+                }
+                self.order = TrajectoryOrder::Autoset(autoset);
+            },
+
             TrajectoryOrder::Angle(a) => {
                 // Advance towards target
                 self.cs.set_target_a(*a);
@@ -273,14 +659,78 @@ impl<H: AsservHardware> Asserv<H> {
         }
     }
 
+    /// Return the distance target of a path order and the remaining distance, relative to the robot projection
+    ///
+    /// Linear ramp stops on the last point. Speed is limited before each corner:
+    /// target is set before the corner, at the braking distance from the corner speed.
+    fn path_target_dist(&self, path: &PathData, projection: &XY) -> (f32, f32) {
+        let lookahead = self.conf.xy_path_lookahead;
+        // Distance to the next point, along the path
+        let mut distance = (path.points[path.index as usize] - *projection).length();
+        let mut target = f32::INFINITY;
+        for index in path.index .. path.size - 1 {
+            let speed = self.corner_speed(path, index);
+            // Robot starts turning when the lookahead point passes the corner
+            let braking_distance = speed * speed / (2.0 * self.conf.xy_acc);
+            target = target.min((distance - lookahead).max(0.0) + braking_distance);
+            let (start, end) = path.segment(index + 1);
+            distance += (end - start).length();
+        }
+        (target.min(distance), distance)
+    }
+
+    /// Return the maximum speed at the end of a path segment, in the curve to the next segment
+    ///
+    /// Robot must be able to turn while moving along the curve, about the lookahead distance.
+    /// If set, speed is also limited by the maximum lateral acceleration.
+    fn corner_speed(&self, path: &PathData, index: u8) -> f32 {
+        let (start, corner) = path.segment(index);
+        let (_, end) = path.segment(index + 1);
+        let turn = normalize_radians_pi_pi((end - corner).angle() - (corner - start).angle()).abs();
+        if turn == 0.0 {
+            return f32::INFINITY;
+        }
+        let (a_speed, a_acc) = (self.conf.a_speed, self.conf.a_acc);
+        // Minimum duration of the turn, with a trapezoidal angular speed profile
+        let turn_duration = if turn * a_acc >= a_speed * a_speed {
+            turn / a_speed + a_speed / a_acc
+        } else {
+            2.0 * (turn / a_acc).sqrt()
+        };
+        let lookahead = self.conf.xy_path_lookahead;
+        let speed = lookahead / turn_duration;
+        let lateral_acc = self.conf.xy_path_lateral_acc;
+        if lateral_acc > 0.0 {
+            // Curvature of the pure pursuit arc when the lookahead point is past the corner
+            let curvature = 2.0 * (turn / 2.0).sin() / lookahead;
+            speed.min((lateral_acc / curvature).sqrt())
+        } else {
+            speed
+        }
+    }
+
     /// Return true if speeds are low enough to be idle
     fn idle_speeds(&self) -> bool {
         let (dist_speed, a_speed) = self.cs.speeds();
-        dist_speed < self.conf.xy_idle_speed && a_speed < self.conf.a_idle_speed
+        dist_speed.abs() < self.conf.xy_idle_speed && a_speed.abs() < self.conf.a_idle_speed
     }
 }
 
 
+/// Project a point on a segment, return the projection and its position on the segment
+///
+/// Position is 0 at the start of the segment, 1 at its end; the projection is
+/// clamped to the segment, but not the returned position.
+fn project_on_segment(point: &XY, start: &XY, end: &XY) -> (XY, f32) {
+    let direction = end - start;
+    let length2 = direction.length2();
+    if length2 == 0.0 {
+        return (*end, 1.0);
+    }
+    let t = (point - start).dot(&direction) / length2;
+    (start + &(direction * t.clamp(0.0, 1.0)), t)
+}
+
 /// Return true if given XY difference is within a tolerance window
 fn in_window_xy(dxy: &XY, window: f32) -> bool {
     // Coarse inegality to save computing time
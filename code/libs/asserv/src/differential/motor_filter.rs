@@ -1,57 +1,101 @@
-use crate::conf::PidConf;
-use crate::pid::PidFilter;
-use crate::ramp::RampFilter;
+use crate::conf::{ControllerConf, PidConf};
+use crate::pid::AxisController;
+use crate::scurve::ProfileFilter;
 
 
 pub struct MotorFilter {
-    ramp_dist: RampFilter,
-    ramp_angle: RampFilter,
-    pid_dist: PidFilter,
-    pid_angle: PidFilter,
+    ramp_dist: ProfileFilter,
+    ramp_angle: ProfileFilter,
+    pid_dist: AxisController,
+    pid_angle: AxisController,
+    last: FilterState,
+}
+
+/// Intermediate values of the last filter update, for traces
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FilterState {
+    pub ramp_dist: f32,
+    pub ramp_angle: f32,
+    /// PID values, in controller units
+    pub pid_in_dist: f32,
+    pub pid_in_angle: f32,
+    pub pid_out_dist: f32,
+    pub pid_out_angle: f32,
 }
 
 impl MotorFilter {
     pub(crate) fn new() -> Self {
         Self {
-            ramp_dist: RampFilter::default(),
-            ramp_angle: RampFilter::default(),
-            pid_dist: PidFilter::default(),
-            pid_angle: PidFilter::default(),
+            ramp_dist: ProfileFilter::default(),
+            ramp_angle: ProfileFilter::default(),
+            pid_dist: AxisController::new(RCS_MM_TO_CSUNIT),
+            pid_angle: AxisController::new(RCS_RAD_TO_CSUNIT),
+            last: FilterState::default(),
         }
     }
 
     /// Filter using position (current) and target (consign), return filtered `(dist, angle)` speed
-    pub(crate) fn filter(&mut self, dist: f32, angle: f32, dist_target: f32, dist_angle: f32) -> (f32, f32) {
+    ///
+    /// `dt` is the time elapsed since the previous update, in seconds.
+    pub(crate) fn filter(&mut self, dist: f32, angle: f32, dist_target: f32, dist_angle: f32, dt: f32) -> (f32, f32) {
         let dist_target = self.ramp_dist.filter(dist_target);
         let angle_target = self.ramp_angle.filter(dist_angle);
 
-        let dist_error = rcs_mm_to_cs_unit(dist_target - dist);
-        let angle_error = rcs_rad_to_cs_unit(angle_target - angle);
-
-        let dist_speed = self.pid_dist.filter(dist_error);
-        let angle_speed = self.pid_angle.filter(angle_error);
-        (
-            cs_unit_to_rcs_mm(dist_speed),
-            cs_unit_to_rcs_rad(angle_speed),
-        )
+        let (pid_in_dist, pid_out_dist, dist_speed) = self.pid_dist.filter(dist, dist_target, dt);
+        let (pid_in_angle, pid_out_angle, angle_speed) = self.pid_angle.filter(angle, angle_target, dt);
+        self.last = FilterState {
+            ramp_dist: dist_target,
+            ramp_angle: angle_target,
+            pid_in_dist,
+            pid_in_angle,
+            pid_out_dist,
+            pid_out_angle,
+        };
+        (dist_speed, angle_speed)
     }
 
-    /// Reset filters
-    pub(crate) fn reset(&mut self) {
+    /// Reset filters, ramps restart from given current position
+    pub(crate) fn reset(&mut self, dist: f32, angle: f32) {
         self.pid_dist.reset();
         self.pid_angle.reset();
-        self.ramp_dist.reset_finished_to(0.0);
-        self.ramp_angle.reset_finished_to(0.0);
+        self.ramp_dist.reset_finished_to(dist);
+        self.ramp_angle.reset_finished_to(angle);
+        self.last = FilterState { ramp_dist: dist, ramp_angle: angle, ..Default::default() };
+    }
+
+    /// Return intermediate values of the last update
+    pub(crate) fn last_state(&self) -> &FilterState {
+        &self.last
     }
 
-    /// Provide access to distance PID configurations
+    /// Distance integer PID configuration
+    pub fn pid_dist_conf(&self) -> &PidConf {
+        self.pid_dist.pid_conf()
+    }
+
+    /// Angle integer PID configuration
+    pub fn pid_angle_conf(&self) -> &PidConf {
+        self.pid_angle.pid_conf()
+    }
+
+    /// Provide access to distance integer PID configurations
     pub fn pid_dist_conf_mut(&mut self) -> &mut PidConf {
-        &mut self.pid_dist.conf
+        self.pid_dist.pid_conf_mut()
     }
 
-    /// Provide access to angle PID configurations
+    /// Provide access to angle integer PID configurations
     pub fn pid_angle_conf_mut(&mut self) -> &mut PidConf {
-        &mut self.pid_angle.conf
+        self.pid_angle.pid_conf_mut()
+    }
+
+    /// Set distance controller configuration
+    pub(crate) fn set_dist_controller_conf(&mut self, conf: ControllerConf) {
+        self.pid_dist.set_conf(conf);
+    }
+
+    /// Set angle controller configuration
+    pub(crate) fn set_angle_controller_conf(&mut self, conf: ControllerConf) {
+        self.pid_angle.set_conf(conf);
     }
 
     /// Set linear ramp configuration
@@ -63,19 +107,18 @@ impl MotorFilter {
     pub(crate) fn set_angle_ramp_conf(&mut self, speed: f32, acc: f32, step_secs: f32) {
         self.ramp_angle.configure_scaled(speed, acc, step_secs);
     }
+
+    /// Set linear ramp jerk, 0 for a trapezoidal profile
+    pub(crate) fn set_dist_ramp_jerk(&mut self, jerk: f32, step_secs: f32) {
+        self.ramp_dist.set_jerk_scaled(jerk, step_secs);
+    }
+
+    /// Set angle ramp jerk, 0 for a trapezoidal profile
+    pub(crate) fn set_angle_ramp_jerk(&mut self, jerk: f32, step_secs: f32) {
+        self.ramp_angle.set_jerk_scaled(jerk, step_secs);
+    }
 }
 
 
 pub const RCS_MM_TO_CSUNIT: f32 = 1000.0;
 pub const RCS_RAD_TO_CSUNIT: f32 = 10000.0;
-
-#[inline]
-pub const fn rcs_mm_to_cs_unit(v: f32) -> i32 { (v * RCS_MM_TO_CSUNIT) as i32 }
-#[inline]
-pub const fn cs_unit_to_rcs_mm(v: i32) -> f32 { v as f32 / RCS_MM_TO_CSUNIT }
-
-#[inline]
-pub const fn rcs_rad_to_cs_unit(v: f32) -> i32 { (v * RCS_RAD_TO_CSUNIT) as i32 }
-#[inline]
-pub const fn cs_unit_to_rcs_rad(v: i32) -> f32 { v as f32 / RCS_RAD_TO_CSUNIT }
-
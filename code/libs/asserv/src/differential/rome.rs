@@ -1,74 +1,124 @@
-use rome::{Message, params};
-use super::{Asserv, AsservHardware, TrajectoryOrder};
+use rome::{Handled, Message, RomeHandler, params};
+use super::{Asserv, AsservHardware, MoveDirection, RobotSide, TrajectoryOrder};
 use super::conf::*;
 use crate::rome::AsservRome;
-use crate::maths::XYA;
+use crate::maths::{XY, XYA};
 
 
-impl<H: AsservHardware> AsservRome for Asserv<H> {
-    fn on_rome_message(&mut self, message: &Message) -> bool {
-        match *message {
-            // Common messages
-            Message::AsservSetPosition { x, y, a } => {
-                log::info!("ROME: reset_position: {x},{y},{a}");
-                self.reset_position(XYA::new(x, y, a));
-            }
-            Message::AsservGotoXy { x, y } => {
-                log::info!("ROME: goto_xy: {x},{y}");
-                self.goto_xy(x, y);
-            }
-            Message::AsservGotoXyRel { dx, dy } => {
-                log::info!("ROME: goto_xyrel: {dx},{dy}");
-                self.goto_xy_rel(dx, dy);
-            }
-            Message::AsservGotoA { a } => {
-                log::info!("ROME: goto_a: {a}");
-                self.goto_a(a);
-            }
-            Message::AsservGotoARel { da } => {
-                log::info!("ROME: goto_a_rel: {da}");
-                self.goto_a_rel(da);
-            }
-            // Common messages not (yet) implemented
-            Message::AsservGotoXya { .. } => {
-                log::error!("ROME: AsservGotoXya is not implemented");
-            }
-            Message::AsservActivate(_) => {
-                log::error!("ROME: AsservActivate is not implemented");
-            }
-            // AsservDiff messages
-            Message::AsservDiffSetPidConf { pid, gain_p, gain_i, gain_d, max_in, max_i, max_out } => {
-                log::info!("ROME: set PID conf ({pid:?})");
-                let conf = PidConf { gain_p, gain_i, gain_d, max_in, max_i, max_out, out_shift: 0 };
-                match pid {
-                    params::AsservDiffSetPidConfPid::Dist => self.set_dist_pid_conf(conf),
-                    params::AsservDiffSetPidConfPid::Angle => self.set_angle_pid_conf(conf),
-                }
-            }
-            Message::AsservDiffSetTrajectoryConf {
-                a_speed, a_acc, xy_speed, xy_acc, xy_stop_window, xy_aim_angle_window, xy_cruise_angle_window,
-                xy_approach_window, a_stop_window, xy_idle_speed, a_idle_speed,
-            } => {
-                log::info!("ROME: set trajectory conf");
-                let conf = TrajectoryConf {
-                    a_speed, a_acc, xy_speed, xy_acc, xy_stop_window, xy_aim_angle_window, xy_cruise_angle_window,
-                    xy_approach_window, a_stop_window, xy_idle_speed, a_idle_speed,
-                };
-                self.set_trajectory_conf(conf);
-            }
-            Message::AsservDiffSetMotorsConf { tick_to_mm, tick_to_rad } => {
-                log::info!("ROME: set motors conf");
-                let conf = MotorsConf { tick_to_mm, tick_to_rad };
-                self.set_motors_conf(conf);
-            }
-            // Non-asserv messages, not handled
-            _ => {
-                return false;
-            }
+impl<H: AsservHardware> RomeHandler for Asserv<H> {
+    // Common messages
+
+    fn on_asserv_set_position(&mut self, x: f32, y: f32, a: f32) -> Handled {
+        log::info!("ROME: reset_position: {x},{y},{a}");
+        self.reset_position(XYA::new(x, y, a));
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_xy(&mut self, x: f32, y: f32) -> Handled {
+        log::info!("ROME: goto_xy: {x},{y}");
+        self.goto_xy(x, y);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_xy_rel(&mut self, dx: f32, dy: f32) -> Handled {
+        log::info!("ROME: goto_xyrel: {dx},{dy}");
+        self.goto_xy_rel(dx, dy);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_a(&mut self, a: f32) -> Handled {
+        log::info!("ROME: goto_a: {a}");
+        self.goto_a(a);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_a_rel(&mut self, da: f32) -> Handled {
+        log::info!("ROME: goto_a_rel: {da}");
+        self.goto_a_rel(da);
+        Handled::Yes
+    }
+
+    fn on_asserv_run_path(&mut self, points: &rome::heapless::Vec<params::XY, 15>) -> Handled {
+        log::info!("ROME: run_path: {} points", points.len());
+        self.run_path(&crate::rome::path_points(points));
+        Handled::Yes
+    }
+
+    fn on_asserv_trace_freeze(&mut self, freeze: bool) -> Handled {
+        log::info!("ROME: trace freeze: {freeze}");
+        crate::rome::freeze_trace(self.cs.trace_mut(), freeze)
+    }
+
+    // Common messages not (yet) implemented
+
+    fn on_asserv_goto_xya(&mut self, _: f32, _: f32, _: f32) -> Handled {
+        log::error!("ROME: AsservGotoXya is not implemented");
+        Handled::Yes
+    }
+
+    fn on_asserv_activate(&mut self, _: bool) -> Handled {
+        log::error!("ROME: AsservActivate is not implemented");
+        Handled::Yes
+    }
+
+    // AsservDiff messages
+
+    fn on_asserv_diff_set_pid_conf(&mut self, pid: params::AsservDiffSetPidConfPid, gain_p: i16, gain_i: i16, gain_d: i16, max_in: i32, max_i: i32, max_out: i32, out_shift: u8) -> Handled {
+        log::info!("ROME: set PID conf ({pid:?})");
+        let conf = PidConf { gain_p, gain_i, gain_d, max_in, max_i, max_out, out_shift };
+        match pid {
+            params::AsservDiffSetPidConfPid::Dist => self.set_dist_pid_conf(conf),
+            params::AsservDiffSetPidConfPid::Angle => self.set_angle_pid_conf(conf),
         }
-        true
+        Handled::Yes
+    }
+
+    fn on_asserv_diff_set_trajectory_conf(
+        &mut self,
+        a_speed: f32, a_acc: f32, xy_speed: f32, xy_acc: f32, xy_stop_window: f32, xy_aim_angle_window: f32, xy_cruise_angle_window: f32,
+        xy_approach_window: f32, a_stop_window: f32, xy_idle_speed: f32, a_idle_speed: f32, a_jerk: f32, xy_jerk: f32,
+        xy_path_lookahead: f32, xy_path_lateral_acc: f32, autoset_speed: f32, autoset_backoff: f32, autoset_timeout: f32,
+    ) -> Handled {
+        log::info!("ROME: set trajectory conf");
+        let conf = TrajectoryConf {
+            a_speed, a_acc, a_jerk, xy_speed, xy_acc, xy_jerk, xy_stop_window, xy_aim_angle_window, xy_cruise_angle_window,
+            xy_approach_window, a_stop_window, xy_idle_speed, a_idle_speed, xy_path_lookahead, xy_path_lateral_acc,
+            autoset_speed, autoset_backoff, autoset_timeout,
+        };
+        self.set_trajectory_conf(conf);
+        Handled::Yes
+    }
+
+    fn on_asserv_diff_set_motors_conf(&mut self, tick_to_mm: f32, tick_to_rad: f32) -> Handled {
+        log::info!("ROME: set motors conf");
+        let conf = MotorsConf { tick_to_mm, tick_to_rad };
+        self.set_motors_conf(conf);
+        Handled::Yes
+    }
+
+    fn on_asserv_diff_goto_xy_dir(&mut self, x: f32, y: f32, direction: params::AsservDiffGotoXyDirDirection) -> Handled {
+        log::info!("ROME: goto_xy_dir: {x},{y} ({direction:?})");
+        let direction = match direction {
+            params::AsservDiffGotoXyDirDirection::Forward => MoveDirection::Forward,
+            params::AsservDiffGotoXyDirDirection::Backward => MoveDirection::Backward,
+            params::AsservDiffGotoXyDirDirection::Shortest => MoveDirection::Shortest,
+        };
+        self.goto_xy_dir(x, y, direction);
+        Handled::Yes
     }
 
+    fn on_asserv_diff_autoset(&mut self, robot_side: params::AsservDiffAutosetRobotSide, table_side: params::TableSide, target_x: f32, target_y: f32) -> Handled {
+        log::info!("ROME: autoset");
+        let robot_side = match robot_side {
+            params::AsservDiffAutosetRobotSide::Front => RobotSide::Front,
+            params::AsservDiffAutosetRobotSide::Back => RobotSide::Back,
+        };
+        self.autoset(robot_side, table_side.into(), XY::new(target_x, target_y));
+        Handled::Yes
+    }
+}
+
+impl<H: AsservHardware> AsservRome for Asserv<H> {
     fn asserv_tm_status(&self) -> Message {
         let position = self.cs.position();
         Message::AsservTmStatus {
@@ -88,6 +138,8 @@ impl<H: AsservHardware> AsservRome for Asserv<H> {
 pub trait AsservDiffRome {
     /// Create an `AsservDiffTmStatus` message from current asserv state
     fn asserv_diff_tm_status(&self) -> Message;
+    /// Create an `AsservDiffTmPath` message, return `None` if no path is active
+    fn asserv_diff_tm_path(&self) -> Option<Message>;
 }
 
 impl<H: AsservHardware> AsservDiffRome for Asserv<H> {
@@ -97,6 +149,8 @@ impl<H: AsservHardware> AsservDiffRome for Asserv<H> {
             TrajectoryOrder::Stop => params::AsservDiffTmStatusStatus::Stop,
             TrajectoryOrder::Xy { .. } => params::AsservDiffTmStatusStatus::Xy,
             TrajectoryOrder::Angle(_) => params::AsservDiffTmStatusStatus::Angle,
+            TrajectoryOrder::Path(_) => params::AsservDiffTmStatusStatus::Path,
+            TrajectoryOrder::Autoset(_) => params::AsservDiffTmStatusStatus::Autoset,
         };
         let (vdist, va) = self.cs.speeds();
         Message::AsservDiffTmStatus {
@@ -106,5 +160,17 @@ impl<H: AsservHardware> AsservDiffRome for Asserv<H> {
             va,
         }
     }
-}
 
+    fn asserv_diff_tm_path(&self) -> Option<Message> {
+        if let TrajectoryOrder::Path(path_data) = &self.order {
+            let path_data = path_data.borrow();
+            Some(Message::AsservDiffTmPath {
+                remaining: path_data.remaining,
+                path_index: path_data.index,
+                path_size: path_data.size,
+            })
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Simulated hardware for the differential asserv
+//!
+//! [SimHardware] implements a kinematic model of a two-wheel differential base.
+//! Each motor is modeled as a first-order system: the wheel speed converges to
+//! a speed proportional to the (saturated) consign, with a time constant.
+//! Encoders count integer ticks, with the same conversion as
+//! [MotorsConf::from_dimensions()].
+//!
+//! The simulation is advanced with [SimHardware::step()], which must be called
+//! before each asserv update, with the same elapsed time.
+//!
+//! Table walls can be enabled: the robot is modeled as a disc, its wheels stall
+//! when it is pushed against a wall.
+
+use core::time::Duration;
+use crate::maths::{XY, XYA};
+use crate::sim::Table;
+use super::conf::{AsservHardware, MotorsConf};
+
+
+/// Simulation configuration
+#[derive(Clone)]
+pub struct SimConf {
+    /// Distance between the two wheels, in mm
+    pub wheel_distance: f32,
+    /// Wheel diameter, in mm
+    pub wheel_diameter: f32,
+    /// Number of encoder ticks per wheel turn
+    pub encoder_ticks: usize,
+    /// Maximum absolute value of motor consigns (PWM saturation)
+    pub max_consign: f32,
+    /// Wheel speed for a unit consign, in mm/s
+    pub speed_per_consign: f32,
+    /// Motor time constant
+    pub time_constant: Duration,
+    /// Table walls, `None` to move freely
+    pub table: Option<Table>,
+    /// Radius of the robot, used for collisions with walls
+    pub robot_radius: f32,
+}
+
+impl SimConf {
+    /// Return the motors configuration matching the simulated robot
+    pub const fn motors_conf(&self) -> MotorsConf {
+        MotorsConf::from_dimensions(self.wheel_distance, self.wheel_diameter, self.encoder_ticks)
+    }
+}
+
+impl Default for SimConf {
+    /// Default values are similar to a PAMI
+    fn default() -> Self {
+        Self {
+            wheel_distance: 75.0,
+            wheel_diameter: 30.0,
+            encoder_ticks: 256,
+            max_consign: 1000.0,
+            speed_per_consign: 1.0,
+            time_constant: Duration::from_millis(50),
+            table: None,
+            robot_radius: 0.0,
+        }
+    }
+}
+
+
+/// Simulated robot, implementing [AsservHardware]
+pub struct SimHardware {
+    conf: SimConf,
+    /// Wheel displacement for one encoder tick, in mm
+    mm_per_tick: f32,
+
+    /// Actual robot position
+    position: XYA,
+    /// Actual wheel speeds, in mm/s
+    speeds: [f32; 2],
+    consigns: [f32; 2],
+    emergency_stop: bool,
+    /// Encoder values, not rounded
+    encoders: [f32; 2],
+    /// Last encoder values returned by `get_motor_offsets()`
+    last_encoders: [f32; 2],
+}
+
+impl SimHardware {
+    /// Create a simulated robot at given position
+    pub fn new(conf: SimConf, position: XYA) -> Self {
+        let mm_per_tick = core::f32::consts::PI * conf.wheel_diameter / conf.encoder_ticks as f32;
+        Self {
+            conf,
+            mm_per_tick,
+            position,
+            speeds: [0.0; 2],
+            consigns: [0.0; 2],
+            emergency_stop: false,
+            encoders: [0.0; 2],
+            last_encoders: [0.0; 2],
+        }
+    }
+
+    /// Return the actual robot position
+    pub fn position(&self) -> &XYA {
+        &self.position
+    }
+
+    /// Return actual wheel speeds (left, right), in mm/s
+    pub fn wheel_speeds(&self) -> [f32; 2] {
+        self.speeds
+    }
+
+    /// Return the last motor consigns
+    pub fn consigns(&self) -> [f32; 2] {
+        self.consigns
+    }
+
+    /// Return true if the robot is against a wall
+    pub fn is_blocked(&self) -> bool {
+        match &self.conf.table {
+            Some(table) => {
+                let xy = self.position.xy();
+                let constrained = table.constrain(xy, self.conf.robot_radius + 0.5);
+                constrained.x != xy.x || constrained.y != xy.y
+            }
+            None => false,
+        }
+    }
+
+    /// Set the emergency stop input
+    ///
+    /// While active, motors are not powered.
+    pub fn set_emergency_stop(&mut self, active: bool) {
+        self.emergency_stop = active;
+    }
+
+    /// Move wheels by given distances (left, right), in mm, as if the robot was pushed
+    ///
+    /// Encoders are updated accordingly.
+    pub fn push(&mut self, distances: [f32; 2]) {
+        self.move_wheels(distances);
+    }
+
+    /// Advance the simulation by `elapsed` time
+    pub fn step(&mut self, elapsed: &Duration) {
+        let dt = elapsed.as_secs_f32();
+        let consigns = if self.emergency_stop {
+            [0.0; 2]
+        } else {
+            self.consigns.map(|v| v.clamp(-self.conf.max_consign, self.conf.max_consign))
+        };
+        // Exact response of a first-order system to a step
+        let response = 1.0 - (-dt / self.conf.time_constant.as_secs_f32()).exp();
+        for (speed, consign) in self.speeds.iter_mut().zip(consigns) {
+            *speed += (consign * self.conf.speed_per_consign - *speed) * response;
+        }
+        self.move_wheels(self.speeds.map(|v| v * dt));
+    }
+
+    /// Apply wheel displacements to position and encoders
+    fn move_wheels(&mut self, [mut left, mut right]: [f32; 2]) {
+        let d = (left + right) / 2.0;
+        let mut da = (right - left) / self.conf.wheel_distance;
+        // Move along the average heading
+        let a = self.position.a + da / 2.0;
+        let mut xy = self.position.xy() + XY::new(a.cos(), a.sin()) * d;
+        if let Some(table) = &self.conf.table {
+            let constrained = table.constrain(xy, self.conf.robot_radius);
+            if constrained.x != xy.x || constrained.y != xy.y {
+                // Robot is held by the wall, wheels only follow the actual displacement
+                let heading = XY::new(self.position.a.cos(), self.position.a.sin());
+                let actual = (constrained - self.position.xy()).dot(&heading);
+                (left, right, da) = (actual, actual, 0.0);
+                xy = constrained;
+            }
+        }
+        self.position = xy.with_a(self.position.a + da);
+        self.encoders[0] += left / self.mm_per_tick;
+        self.encoders[1] += right / self.mm_per_tick;
+    }
+}
+
+impl AsservHardware for SimHardware {
+    fn emergency_stop_active(&mut self) -> bool {
+        self.emergency_stop
+    }
+
+    fn set_motor_consigns(&mut self, values: [f32; 2]) {
+        self.consigns = values;
+    }
+
+    fn get_motor_offsets(&mut self) -> [f32; 2] {
+        // Encoders return integer values
+        let encoders = self.encoders.map(f32::round);
+        let offsets = [encoders[0] - self.last_encoders[0], encoders[1] - self.last_encoders[1]];
+        self.last_encoders = encoders;
+        offsets
+    }
+}
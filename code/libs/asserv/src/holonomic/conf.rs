@@ -1,4 +1,5 @@
-pub use crate::conf::PidConf;
+use core::time::Duration;
+pub use crate::conf::{ControllerConf, FloatPidConf, PidConf};
 use crate::maths::{Matrix33, MATRIX33_IDENTITY};
 
 
@@ -23,14 +24,18 @@ pub trait AsservHardware {
 /// Having everything in one struct helps to not forget a value.
 #[derive(Clone, Default)]
 pub struct AsservConf {
-    pub pid_x: PidConf,
-    pub pid_y: PidConf,
-    pub pid_a: PidConf,
+    pub pid_x: ControllerConf,
+    pub pid_y: ControllerConf,
+    pub pid_a: ControllerConf,
     pub trajectory: TrajectoryConf,
     pub motors: MotorsConf,
 }
 
 
+/// Trajectory configuration
+///
+/// All values are in mm, rads and seconds.
+/// Asserv's `update_period` is used to scale them to raw values internally.
 #[derive(Clone, Default)]
 pub struct TrajectoryConf {
     /// Maximum angular speed
@@ -41,6 +46,8 @@ pub struct TrajectoryConf {
     pub xy_cruise_speed: f32,
     /// Maximum linear acceleration, between trajectory points
     pub xy_cruise_acc: f32,
+    /// Maximum linear jerk, 0 for trapezoidal speed profiles
+    pub xy_jerk: f32,
     /// Speed near intermediate trajectory points
     pub xy_steering_speed: f32,
     /// Acceleration near intermediate trajectory points
@@ -57,10 +64,10 @@ pub struct TrajectoryConf {
     pub a_stop_window: f32,
     /// Speed for autoset moves
     pub autoset_speed: f32,
-    /// Waiting time (update ticks) before and after autoset move
-    pub autoset_wait: u8,
-    /// Autoset move duration (update ticks)
-    pub autoset_duration: u8,
+    /// Waiting time before and after autoset move
+    pub autoset_wait: Duration,
+    /// Autoset move duration
+    pub autoset_duration: Duration,
 }
 
 
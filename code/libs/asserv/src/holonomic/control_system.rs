@@ -1,6 +1,35 @@
+use core::time::Duration;
 use crate::maths::{mult_matrix33_vec, Matrix33, MATRIX33_IDENTITY, XY, XYA};
+use crate::trace::{TraceRecorder, trace_sample};
 use super::conf::AsservHardware;
-use super::motor_filter::MotorFilter;
+use super::motor_filter::{MotorFilter, RCS_RAD_TO_CSUNIT};
+
+
+trace_sample! {
+    /// Trace sample of the holonomic asserv, recorded on each update
+    ///
+    /// PID inputs and outputs are in controller units: internal units for
+    /// integer PIDs, mm or rad for floating-point PIDs.
+    pub struct AsservSample {
+        x,
+        y,
+        a,
+        carrot_x,
+        carrot_y,
+        target_a,
+        /// Angle target, after the quadramp
+        qramp_a,
+        pid_in_x,
+        pid_in_y,
+        pid_in_a,
+        pid_out_x,
+        pid_out_y,
+        pid_out_a,
+        consign_0,
+        consign_1,
+        consign_2,
+    }
+}
 
 
 pub struct ControlSystem<H: AsservHardware> {
@@ -12,6 +41,9 @@ pub struct ControlSystem<H: AsservHardware> {
     motors_reactivated: bool,
     motors_velocities_to_consigns: Matrix33,
     motors_encoders_to_position: Matrix33,
+    /// Last motor consigns
+    consigns: [f32; 3],
+    trace: Option<TraceRecorder<AsservSample>>,
 }
 
 
@@ -26,12 +58,15 @@ impl<H: AsservHardware> ControlSystem<H> {
             motors_reactivated: false,
             motors_velocities_to_consigns: MATRIX33_IDENTITY,
             motors_encoders_to_position: MATRIX33_IDENTITY,
+            consigns: [0.0; 3],
+            trace: None,
         }
     }
 
-    pub(crate) fn update(&mut self) {
+    pub(crate) fn update(&mut self, elapsed: &Duration) {
         self.update_position();
-        self.update_motors();
+        self.update_motors(elapsed);
+        self.record_trace();
     }
 
     fn update_position(&mut self) {
@@ -54,7 +89,7 @@ impl<H: AsservHardware> ControlSystem<H> {
         self.position.a += gyro_offset;
     }
 
-    fn update_motors(&mut self) {
+    fn update_motors(&mut self, elapsed: &Duration) {
         if !self.motor_control {
             return;
         }
@@ -66,7 +101,7 @@ impl<H: AsservHardware> ControlSystem<H> {
         }
 
         // Compute control system first level (x,y,a)
-        let velocity = self.motor_filter.filter(&self.position, &self.target);
+        let velocity = self.motor_filter.filter(&self.position, &self.target, elapsed.as_secs_f32());
 
         // Transform output velocity vector from table coords to robot coords
         let alpha = -self.position.a;
@@ -79,6 +114,30 @@ impl<H: AsservHardware> ControlSystem<H> {
         self.set_motors_from_velocities(vx_r, vy_r, velocity.a);
     }
 
+    fn record_trace(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            let filter = self.motor_filter.last_state();
+            trace.record(AsservSample {
+                x: self.position.x,
+                y: self.position.y,
+                a: self.position.a,
+                carrot_x: self.target.x,
+                carrot_y: self.target.y,
+                target_a: self.target.a,
+                qramp_a: filter.qramp_a,
+                pid_in_x: filter.pid_in[0],
+                pid_in_y: filter.pid_in[1],
+                pid_in_a: filter.pid_in[2],
+                pid_out_x: filter.pid_out[0],
+                pid_out_y: filter.pid_out[1],
+                pid_out_a: filter.pid_out[2],
+                consign_0: self.consigns[0],
+                consign_1: self.consigns[1],
+                consign_2: self.consigns[2],
+            });
+        }
+    }
+
     /// Return current position
     pub fn position(&self) -> &XYA {
         &self.position
@@ -118,6 +177,7 @@ impl<H: AsservHardware> ControlSystem<H> {
     pub fn set_motors_from_velocities(&mut self, vx: f32, vy: f32, va: f32) {
         let values = mult_matrix33_vec(&self.motors_velocities_to_consigns, &[vx, vy, va]);
         self.hardware.set_motor_consigns(values);
+        self.consigns = values;
     }
 
     /// Enable motor control
@@ -139,8 +199,31 @@ impl<H: AsservHardware> ControlSystem<H> {
     }
 
     /// Set angular speed and acceleration
-    pub fn set_a_speed(&mut self, speed: f32, acc: f32) {
-        self.motor_filter.set_qramp_a_vars(speed as u32, acc as u32);
+    pub fn set_a_speed(&mut self, speed: f32, acc: f32, step_secs: f32) {
+        // Quadramp uses integer values, in CS units per update
+        let speed = speed * step_secs * RCS_RAD_TO_CSUNIT;
+        let acc = acc * step_secs * step_secs * RCS_RAD_TO_CSUNIT;
+        self.motor_filter.set_qramp_a_vars(speed.round() as u32, acc.round() as u32);
+    }
+
+    /// Record a trace of the last `capacity` updates, drop the previous one
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.trace = Some(TraceRecorder::new(capacity));
+    }
+
+    /// Stop trace recording, drop recorded samples
+    pub fn disable_trace(&mut self) {
+        self.trace = None;
+    }
+
+    /// Return the trace recorder, if enabled
+    pub fn trace(&self) -> Option<&TraceRecorder<AsservSample>> {
+        self.trace.as_ref()
+    }
+
+    /// Return the trace recorder, if enabled
+    pub fn trace_mut(&mut self) -> Option<&mut TraceRecorder<AsservSample>> {
+        self.trace.as_mut()
     }
 
     /// Set matrix that converts velocities to motor consigns
@@ -3,19 +3,26 @@ mod control_system;
 mod motor_filter;
 #[cfg(feature = "rome")]
 pub mod rome;
+#[cfg(feature = "sim")]
+pub mod sim;
 
+use core::time::Duration;
 use crate::maths::{XY, XYA, normalize_radians_pi_pi};
+use crate::scurve;
 use conf::*;
 use control_system::ControlSystem;
+pub use control_system::AsservSample;
 
 
 // This struct is very similar to `conf::TrajectoryConf`.
 // Field names are different (mostly historical).
 // And angular speed/acc are not stored in it (they are set on quadramp).
+// Speeds and accelerations are per update, delays are in update ticks.
 #[derive(Default)]
 struct AsservInternalConf {
     pub cruise_speed: f32,
     pub cruise_acc: f32,
+    pub jerk: f32,
     pub steering_speed: f32,
     pub steering_acc: f32,
     pub stop_speed: f32,
@@ -24,22 +31,15 @@ struct AsservInternalConf {
     pub xy_stop_window: f32,
     pub a_stop_window: f32,
     pub autoset_speed: f32,
-    pub autoset_wait: u8,
-    pub autoset_duration: u8,
+    pub autoset_wait: u32,
+    pub autoset_duration: u32,
 }
 
 /// Maximum number of points for a trajectory path
 pub const TRAJECTORY_MAX_POINTS: u8 = 15;
 
 
-/// Game table side
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum TableSide {
-    Left,
-    Right,
-    Up,
-    Down,
-}
+pub use crate::TableSide;
 
 
 /// Robot side, relative to the Y axis
@@ -68,18 +68,54 @@ struct AutosetConfig {
 }
 
 /// Trajectory order being processed
+// A single order is stored, don't allocate path data
+#[allow(clippy::large_enum_variant)]
 enum TrajectoryOrder {
     Idle,
     Path(std::cell::RefCell<PathData>),
     Autoset(std::cell::RefCell<AutosetData>),
 }
 
+/// Path point, with an optional heading
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Waypoint {
+    pub xy: XY,
+    /// Angle to reach on this point, interpolated along the path from the previous heading
+    pub a: Option<f32>,
+}
+
+impl Waypoint {
+    pub const fn new(x: f32, y: f32, a: Option<f32>) -> Self {
+        Self { xy: XY::new(x, y), a }
+    }
+}
+
+impl From<XY> for Waypoint {
+    fn from(xy: XY) -> Self {
+        Self { xy, a: None }
+    }
+}
+
+impl From<XYA> for Waypoint {
+    fn from(xya: XYA) -> Self {
+        Self { xy: xya.xy(), a: Some(xya.a) }
+    }
+}
+
+
 /// Information needed for path movement
 struct PathData {
     points: [XY; TRAJECTORY_MAX_POINTS as usize],
     size: u8,
     index: u8,
     carrot_speed: f32,
+    carrot_acc: f32,
+    /// Robot position when the path was started, start of the first segment
+    origin: XY,
+    /// Path length from the origin to each point
+    lengths: [f32; TRAJECTORY_MAX_POINTS as usize],
+    /// Angle synchronization ending on each point with a heading, and its start path length
+    headings: [Option<(f32, SyncedAngleData)>; TRAJECTORY_MAX_POINTS as usize],
 }
 
 impl PathData {
@@ -90,6 +126,36 @@ impl PathData {
     fn is_last_point(&self) -> bool {
         self.index + 1 >= self.size
     }
+
+    /// Return the angle to reach at the end of the path, `None` if the path has no heading
+    fn last_heading(&self) -> Option<f32> {
+        self.headings.iter().rev().find_map(|heading| heading.as_ref()).map(|(_, synced_angle)| synced_angle.destination_a())
+    }
+
+    /// Return the angle target, synchronized with the movement, `None` if the path has no heading
+    ///
+    /// Angle is interpolated along the path length, between points with a heading.
+    /// After the last heading, its angle is kept.
+    fn synced_angle(&self, position: &XY) -> Option<f32> {
+        let index = self.index as usize;
+        let (start, start_length) = match index {
+            0 => (self.origin, 0.0),
+            _ => (self.points[index - 1], self.lengths[index - 1]),
+        };
+        // Path length travelled by the robot: its position projected on the current segment
+        let segment = self.points[index] - start;
+        let segment_length = self.lengths[index] - start_length;
+        let progress = if segment_length > 0.0 {
+            ((position - &start).dot(&segment) / segment_length).clamp(0.0, segment_length)
+        } else {
+            0.0
+        };
+        let travelled = start_length + progress;
+        let (span_start, synced_angle) = self.headings[index .. self.size as usize].iter()
+            .chain(self.headings[..index].iter().rev())
+            .find_map(|heading| heading.as_ref())?;
+        Some(synced_angle.angle_at(travelled - span_start))
+    }
 }
 
 
@@ -154,12 +220,13 @@ impl AutosetData {
 #[derive(Clone, Copy)]
 enum AutosetState {
     Heading,
-    HeadingWait(u8),
-    Move(u8),
-    MoveWait(u8),
+    HeadingWait(u32),
+    Move(u32),
+    MoveWait(u32),
 }
 
 /// Information needed for synced angle movement
+#[derive(Clone, Copy)]
 struct SyncedAngleData {
     total_distance: f32,
     total_angle: f32,  // normalized
@@ -169,18 +236,35 @@ struct SyncedAngleData {
 
 impl SyncedAngleData {
     fn new(origin: &XYA, destination: &XYA) -> Self {
-        let origin_xy = origin.xy();
+        Self::with_distance(origin, destination.a, (destination.xy() - origin.xy()).length())
+    }
+
+    /// Synchronize angle over a given distance, not necessarily in straight line
+    fn with_distance(origin: &XYA, destination_a: f32, total_distance: f32) -> Self {
         Self {
-            total_distance: (destination.xy() - origin_xy).length(),
-            total_angle: normalize_radians_pi_pi(destination.a - origin.a),
-            origin_xy,
+            total_distance,
+            total_angle: normalize_radians_pi_pi(destination_a - origin.a),
+            origin_xy: origin.xy(),
             origin_a: origin.a,
         }
     }
 
+    /// Return the angle to reach at the end, not normalized
+    fn destination_a(&self) -> f32 {
+        self.origin_a + self.total_angle
+    }
+
     fn synced_angle(&self, current_xy: &XY) -> f32 {
-        let d_current = (current_xy - &self.origin_xy).length();
-        let progress = d_current / self.total_distance;
+        self.angle_at((current_xy - &self.origin_xy).length())
+    }
+
+    /// Return the angle after moving by given distance, destination angle is never exceeded
+    fn angle_at(&self, distance: f32) -> f32 {
+        let progress = if self.total_distance > 0.0 {
+            (distance / self.total_distance).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
         self.origin_a + progress * self.total_angle
     }
 }
@@ -198,6 +282,9 @@ impl SyncedAngleData {
 pub struct Asserv<H: AsservHardware> {
     pub cs: ControlSystem<H>,
 
+    // Asserv step duration, used to scale some configuration values
+    update_period_secs: f32,
+
     // Trajectory parameters
     conf: AsservInternalConf,
 
@@ -211,11 +298,12 @@ pub struct Asserv<H: AsservHardware> {
 }
 
 impl<H: AsservHardware> Asserv<H> {
-    /// Create an asserv from hardware components
-    pub fn new(hardware: H) -> Self {
+    /// Create an asserv from hardware components and update period
+    pub fn new(hardware: H, update_period: Duration) -> Self {
         let cs = ControlSystem::new(hardware);
         Self {
             cs,
+            update_period_secs: update_period.as_secs_f32(),
             conf: Default::default(),
             order: TrajectoryOrder::Idle,
             carrot: Default::default(),
@@ -226,28 +314,46 @@ impl<H: AsservHardware> Asserv<H> {
 
     /// Fully configure the asserv and fully reset position()
     pub fn set_conf(&mut self, conf: AsservConf) {
-        {
-            let pid_confs = self.cs.motor_filter.pid_confs_mut();
-            *pid_confs.x = conf.pid_x;
-            *pid_confs.y = conf.pid_y;
-            *pid_confs.a = conf.pid_a;
-        }
+        self.set_x_controller_conf(conf.pid_x);
+        self.set_y_controller_conf(conf.pid_y);
+        self.set_a_controller_conf(conf.pid_a);
         self.set_trajectory_conf(conf.trajectory);
         self.set_motors_conf(conf.motors);
         self.reset_position(XYA::new(0.0, 0.0, 0.0));
     }
 
-    /// Set X coordinate PID configuration
+    /// Set X coordinate controller configuration, select the controller type
+    pub fn set_x_controller_conf(&mut self, conf: ControllerConf) {
+        self.cs.motor_filter.controllers_mut().x.set_conf(conf);
+    }
+
+    /// Set Y coordinate controller configuration, select the controller type
+    pub fn set_y_controller_conf(&mut self, conf: ControllerConf) {
+        self.cs.motor_filter.controllers_mut().y.set_conf(conf);
+    }
+
+    /// Set angle coordinate controller configuration, select the controller type
+    pub fn set_a_controller_conf(&mut self, conf: ControllerConf) {
+        self.cs.motor_filter.controllers_mut().a.set_conf(conf);
+    }
+
+    /// Set X coordinate integer PID configuration
+    ///
+    /// The controller type is not changed.
     pub fn set_x_pid_conf(&mut self, conf: PidConf) {
         *self.cs.motor_filter.pid_confs_mut().x = conf;
     }
 
-    /// Set Y coordinate PID configuration
+    /// Set Y coordinate integer PID configuration
+    ///
+    /// The controller type is not changed.
     pub fn set_y_pid_conf(&mut self, conf: PidConf) {
         *self.cs.motor_filter.pid_confs_mut().y = conf;
     }
 
-    /// Set angle coordinate PID configuration
+    /// Set angle coordinate integer PID configuration
+    ///
+    /// The controller type is not changed.
     pub fn set_a_pid_conf(&mut self, conf: PidConf) {
         *self.cs.motor_filter.pid_confs_mut().a = conf;
     }
@@ -256,6 +362,7 @@ impl<H: AsservHardware> Asserv<H> {
     pub fn set_trajectory_conf(&mut self, conf: TrajectoryConf) {
         self.set_a_speed(conf.a_speed, conf.a_acc);
         self.set_xy_cruise_speed(conf.xy_cruise_speed, conf.xy_cruise_acc);
+        self.set_xy_jerk(conf.xy_jerk);
         self.set_xy_steering_speed(conf.xy_steering_speed, conf.xy_steering_acc);
         self.set_xy_stop_speed(conf.xy_stop_speed, conf.xy_stop_acc);
         self.set_steering_window(conf.xy_steering_window);
@@ -281,11 +388,15 @@ impl<H: AsservHardware> Asserv<H> {
     /// Run a single asserv step
     ///
     /// This method must be called periodically.
-    pub fn update(&mut self) {
+    pub fn update(&mut self, elapsed: &Duration) {
+        if elapsed <= &Duration::ZERO {
+            return;  // Should not happen
+        }
+
         self.update_synced_angle();
         self.update_trajectory();
         // Update control system (position, motors)
-        self.cs.update();
+        self.cs.update(elapsed);
     }
 
 
@@ -315,6 +426,8 @@ impl<H: AsservHardware> Asserv<H> {
     //
 
     /// Start an autoset procedure
+    ///
+    /// Only the `target` coordinate against the table side is used, the other one is kept.
     pub fn autoset(&mut self, robot_side: RobotSide, table_side: TableSide, target: XY) {
         // Set carrot position to current position
         self.set_carrot_xy_consign(self.cs.position().xy());
@@ -329,26 +442,17 @@ impl<H: AsservHardware> Asserv<H> {
         self.order = TrajectoryOrder::Autoset(autoset_data.into());
     }
 
-    /// Load and run a trajectory path
+    /// Load and run a trajectory path, don't change angular target
     pub fn run_path(&mut self, path: &[XY]) {
-        if path.is_empty() {
-            // Empty path: stop to current position
-            self.set_carrot_xy_consign(self.cs.position().xy());
-            self.order = TrajectoryOrder::Idle;
-        } else {
-            // Truncate path len if needed
-            let n = (TRAJECTORY_MAX_POINTS as usize).min(path.len());
-            let mut path_data = PathData {
-                points: Default::default(),
-                size: n as u8,
-                index: 0,
-                carrot_speed: 0.0,
-            };
-            path_data.points[0..n].copy_from_slice(path);
-            self.set_carrot_xy_consign(*path_data.next_point());
-            self.order = TrajectoryOrder::Path(path_data.into());
-        }
+        self.load_path(path.iter().map(|xy| Waypoint::from(*xy)));
+    }
 
+    /// Load and run a trajectory path, with optional headings
+    ///
+    /// Angle target is interpolated along the path length, to reach each heading
+    /// on its point. Angular target is not changed if there is no heading.
+    pub fn run_path_xya(&mut self, path: &[Waypoint]) {
+        self.load_path(path.iter().copied());
     }
 
     /// Go to given angle, don't change linear target
@@ -397,23 +501,36 @@ impl<H: AsservHardware> Asserv<H> {
     // Configuration setters
     //
 
+    /// Set angular speed and acceleration, in rad/s and rad/s²
     pub fn set_a_speed(&mut self, speed: f32, acc: f32) {
-        self.cs.set_a_speed(speed, acc);
+        self.cs.set_a_speed(speed, acc, self.update_period_secs);
     }
 
+    /// Set cruise speed and acceleration, in mm/s and mm/s²
     pub fn set_xy_cruise_speed(&mut self, speed: f32, acc: f32) {
-        self.conf.cruise_speed = speed;
-        self.conf.cruise_acc = acc;
+        let step_secs = self.update_period_secs;
+        self.conf.cruise_speed = speed * step_secs;
+        self.conf.cruise_acc = acc * step_secs * step_secs;
+    }
+
+    /// Set linear jerk in mm/s³, 0 to use trapezoidal speed profiles
+    pub fn set_xy_jerk(&mut self, jerk: f32) {
+        let step_secs = self.update_period_secs;
+        self.conf.jerk = jerk * step_secs * step_secs * step_secs;
     }
 
+    /// Set speed and acceleration near intermediate points, in mm/s and mm/s²
     pub fn set_xy_steering_speed(&mut self, speed: f32, acc: f32) {
-        self.conf.steering_speed = speed;
-        self.conf.steering_acc = acc;
+        let step_secs = self.update_period_secs;
+        self.conf.steering_speed = speed * step_secs;
+        self.conf.steering_acc = acc * step_secs * step_secs;
     }
 
+    /// Set speed and acceleration near the last point, in mm/s and mm/s²
     pub fn set_xy_stop_speed(&mut self, speed: f32, acc: f32) {
-        self.conf.stop_speed = speed;
-        self.conf.stop_acc = acc;
+        let step_secs = self.update_period_secs;
+        self.conf.stop_speed = speed * step_secs;
+        self.conf.stop_acc = acc * step_secs * step_secs;
     }
 
     pub fn set_steering_window(&mut self, xywin: f32) {
@@ -425,13 +542,17 @@ impl<H: AsservHardware> Asserv<H> {
         self.conf.a_stop_window = awin;
     }
 
+    /// Set autoset move speed, in mm/s
     pub fn set_autoset_speed(&mut self, speed: f32) {
-        self.conf.autoset_speed = speed;
+        self.conf.autoset_speed = speed * self.update_period_secs;
     }
 
-    pub fn set_autoset_delays(&mut self, wait: u8, duration: u8) {
-        self.conf.autoset_wait = wait;
-        self.conf.autoset_duration = duration;
+    /// Set autoset waiting time and move duration
+    ///
+    /// Durations are rounded to a whole number of updates.
+    pub fn set_autoset_delays(&mut self, wait: Duration, duration: Duration) {
+        self.conf.autoset_wait = self.update_ticks(&wait);
+        self.conf.autoset_duration = self.update_ticks(&duration);
     }
 
     /// Reset position, reset carrot to current position, reset motor consigns
@@ -447,7 +568,57 @@ impl<H: AsservHardware> Asserv<H> {
     // Internal methods
     //
 
-    /// Apply synced angle constraint
+    /// Return the number of updates for given duration
+    fn update_ticks(&self, duration: &Duration) -> u32 {
+        (duration.as_secs_f32() / self.update_period_secs).round() as u32
+    }
+
+    /// Load and run a path
+    fn load_path(&mut self, path: impl Iterator<Item = Waypoint>) {
+        let position = *self.cs.position();
+        let mut path_data = PathData {
+            points: Default::default(),
+            size: 0,
+            index: 0,
+            carrot_speed: 0.0,
+            carrot_acc: 0.0,
+            origin: position.xy(),
+            lengths: Default::default(),
+            headings: Default::default(),
+        };
+        // Previous point, and previous heading with its path length
+        let mut previous = position.xy();
+        let mut heading_origin = (self.carrot_a, position.xy(), 0.0);
+        // Truncate path len if needed
+        for (i, waypoint) in path.take(TRAJECTORY_MAX_POINTS as usize).enumerate() {
+            let length = (waypoint.xy - previous).length() + if i == 0 { 0.0 } else { path_data.lengths[i - 1] };
+            path_data.points[i] = waypoint.xy;
+            path_data.lengths[i] = length;
+            if let Some(a) = waypoint.a {
+                let (origin_a, origin_xy, origin_length) = heading_origin;
+                let synced_angle = SyncedAngleData::with_distance(&origin_xy.with_a(origin_a), a, length - origin_length);
+                path_data.headings[i] = Some((origin_length, synced_angle));
+                heading_origin = (synced_angle.destination_a(), waypoint.xy, length);
+            }
+            previous = waypoint.xy;
+            path_data.size = i as u8 + 1;
+        }
+
+        if path_data.size == 0 {
+            // Empty path: stop to current position
+            self.set_carrot_xy_consign(position.xy());
+            self.order = TrajectoryOrder::Idle;
+        } else {
+            if path_data.headings.iter().any(Option::is_some) {
+                // Path headings replace synced angle movement
+                self.synced_angle = None;
+            }
+            self.set_carrot_xy_consign(*path_data.next_point());
+            self.order = TrajectoryOrder::Path(path_data.into());
+        }
+    }
+
+    /// Apply synced angle constraint, of synced movement or path headings
     fn update_synced_angle(&mut self) {
         if let Some(synced_angle) = &self.synced_angle {
             let angle = synced_angle.synced_angle(&self.cs.position().xy());
@@ -456,6 +627,14 @@ impl<H: AsservHardware> Asserv<H> {
                 self.synced_angle = None;
             }
         }
+
+        let path_angle = match &self.order {
+            TrajectoryOrder::Path(path_data) => path_data.borrow().synced_angle(&self.cs.position().xy()),
+            _ => None,
+        };
+        if let Some(angle) = path_angle {
+            self.goto_a(angle);
+        }
     }
 
     /// Update trajectory management
@@ -482,7 +661,11 @@ impl<H: AsservHardware> Asserv<H> {
                         // Last point reached: full stop
                         // Set carrot to last position
                         let next_point = *path_data.borrow().next_point();
+                        let last_heading = path_data.borrow().last_heading();
                         self.set_carrot_xy_consign(next_point);
+                        if let Some(a) = last_heading {
+                            self.goto_a(a);
+                        }
                         self.order = TrajectoryOrder::Idle;
                         return;
                     }
@@ -502,19 +685,34 @@ impl<H: AsservHardware> Asserv<H> {
                 let error = point - self.carrot;
                 let sq_error_length = error.x * error.x + error.y * error.y;
 
-                // Compute distance at which constant deceleration will bring robot to desired speed
-                //   dec_distance = 1/2 (speed_1 + speed_0) × (speed_1 - speed_0) / acc
-                //                = average_speed × deceleration_duration
-                let dec_distance = 0.5 * (carrot_speed + max_speed) * (carrot_speed - max_speed) / max_acc;
-
-                if sq_error_length < dec_distance * dec_distance {
-                    // Deceleration phase
-                    carrot_speed = (carrot_speed - max_acc).max(max_speed);
-                } else if carrot_speed < self.conf.cruise_speed {
-                    // Acceleration phase
-                    carrot_speed = (carrot_speed + self.conf.cruise_acc).min(self.conf.cruise_speed);
+                if self.conf.jerk > 0.0 {
+                    // S-curve: speed changes are jerk-limited, so deceleration starts earlier
+                    let jerk = self.conf.jerk;
+                    let dec_distance = scurve::speed_change_distance(carrot_speed, max_speed, max_acc, jerk);
+                    let (target_speed, acc) = if sq_error_length < dec_distance * dec_distance {
+                        (max_speed, max_acc)
+                    } else {
+                        (self.conf.cruise_speed, self.conf.cruise_acc)
+                    };
+                    let mut carrot_acc = path_data.borrow().carrot_acc;
+                    carrot_acc = scurve::speed_acceleration(carrot_speed, carrot_acc, target_speed, acc, jerk);
+                    carrot_speed = (carrot_speed + carrot_acc).clamp(0.0, self.conf.cruise_speed);
+                    path_data.borrow_mut().carrot_acc = carrot_acc;
                 } else {
-                    // Stable phase: nothing to do
+                    // Compute distance at which constant deceleration will bring robot to desired speed
+                    //   dec_distance = 1/2 (speed_1 + speed_0) × (speed_1 - speed_0) / acc
+                    //                = average_speed × deceleration_duration
+                    let dec_distance = 0.5 * (carrot_speed + max_speed) * (carrot_speed - max_speed) / max_acc;
+
+                    if sq_error_length < dec_distance * dec_distance {
+                        // Deceleration phase
+                        carrot_speed = (carrot_speed - max_acc).max(max_speed);
+                    } else if carrot_speed < self.conf.cruise_speed {
+                        // Acceleration phase
+                        carrot_speed = (carrot_speed + self.conf.cruise_acc).min(self.conf.cruise_speed);
+                    } else {
+                        // Stable phase: nothing to do
+                    }
                 }
 
                 // Update carrot position
@@ -545,14 +743,14 @@ impl<H: AsservHardware> Asserv<H> {
                             AutosetState::HeadingWait(count)
                         } else {
                             self.cs.disable_motor_control();
-                            // Set the final target position
+                            // Set the final target position, keep the coordinate along the wall
                             let position = self.cs.position();
                             match autoset_data.table_side {
                                 TableSide::Left | TableSide::Right => {
-                                    autoset_data.target.x = position.x;
+                                    autoset_data.target.y = position.y;
                                 }
                                 TableSide::Up | TableSide::Down => {
-                                    autoset_data.target.y = position.y;
+                                    autoset_data.target.x = position.x;
                                 }
                             }
                             AutosetState::Move(0)
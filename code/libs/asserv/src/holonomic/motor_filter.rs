@@ -1,48 +1,53 @@
 use crate::conf::PidConf;
 use crate::maths::{PackXYA, XYA};
-use crate::pid::PidFilter;
+use crate::pid::AxisController;
 use crate::quadramp::QuadrampFilter;
 
 
 pub struct MotorFilter {
-    pid_x: PidFilter,
-    pid_y: PidFilter,
-    pid_a: PidFilter,
+    pid_x: AxisController,
+    pid_y: AxisController,
+    pid_a: AxisController,
     qramp_a: QuadrampFilter,
+    last: FilterState,
+}
+
+/// Intermediate values of the last filter update, for traces
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FilterState {
+    /// Angle target, after the quadramp
+    pub qramp_a: f32,
+    /// PID values, in controller units
+    pub pid_in: [f32; 3],
+    pub pid_out: [f32; 3],
 }
 
 impl MotorFilter {
     pub(crate) fn new() -> Self {
         Self {
-            pid_x: PidFilter::default(),
-            pid_y: PidFilter::default(),
-            pid_a: PidFilter::default(),
+            pid_x: AxisController::new(RCS_MM_TO_CSUNIT),
+            pid_y: AxisController::new(RCS_MM_TO_CSUNIT),
+            pid_a: AxisController::new(RCS_RAD_TO_CSUNIT),
             qramp_a: QuadrampFilter::default(),
+            last: FilterState::default(),
         }
     }
 
     /// Filter using position (current) and target (consign), return filter output
-    pub(crate) fn filter(&mut self, position: &XYA, target: &XYA) -> XYA {
-        XYA {
-            x: Self::filter_xy(position.x, target.x, &mut self.pid_x),
-            y: Self::filter_xy(position.y, target.y, &mut self.pid_y),
-            a: Self::filter_a(position.a, target.a, &mut self.pid_a, &mut self.qramp_a),
-        }
-    }
-
-    fn filter_xy(position: f32, target: f32, pid: &mut PidFilter) -> f32 {
-        let current = rcs_mm_to_cs_unit(position);
-        let consign = rcs_mm_to_cs_unit(target);
-        let out = pid.filter(consign - current);
-        cs_unit_to_rcs_mm(out)
-    }
-
-    fn filter_a(position: f32, target: f32, pid: &mut PidFilter, qramp: &mut QuadrampFilter) -> f32 {
-        let current = rcs_rad_to_cs_unit(position);
-        let consign = rcs_rad_to_cs_unit(target);
-        let consign = qramp.filter(consign);
-        let out = pid.filter(consign - current);
-        cs_unit_to_rcs_rad(out)
+    ///
+    /// `dt` is the elapsed time since the last update, in seconds.
+    pub(crate) fn filter(&mut self, position: &XYA, target: &XYA, dt: f32) -> XYA {
+        let qramp_a = self.qramp_a.filter(rcs_rad_to_cs_unit(target.a));
+        let qramp_a = cs_unit_to_rcs_rad(qramp_a);
+        let (in_x, out_x, x) = self.pid_x.filter(position.x, target.x, dt);
+        let (in_y, out_y, y) = self.pid_y.filter(position.y, target.y, dt);
+        let (in_a, out_a, a) = self.pid_a.filter(position.a, qramp_a, dt);
+        self.last = FilterState {
+            qramp_a,
+            pid_in: [in_x, in_y, in_a],
+            pid_out: [out_x, out_y, out_a],
+        };
+        XYA { x, y, a }
     }
 
     /// Reset PIDs and angle quadramp
@@ -52,14 +57,29 @@ impl MotorFilter {
         self.pid_a.reset();
         //TODO Previously, was resetting to current state, use `reset_finished()`?
         self.qramp_a.reset_finished_to(0);
+        self.last = FilterState::default();
+    }
+
+    /// Return intermediate values of the last update
+    pub(crate) fn last_state(&self) -> &FilterState {
+        &self.last
     }
 
-    /// Provide access to PID configurations
+    /// Provide access to integer PID configurations
     pub fn pid_confs_mut(&mut self) -> PackXYA<&mut PidConf> {
         PackXYA {
-            x: &mut self.pid_x.conf,
-            y: &mut self.pid_y.conf,
-            a: &mut self.pid_a.conf,
+            x: self.pid_x.pid_conf_mut(),
+            y: self.pid_y.pid_conf_mut(),
+            a: self.pid_a.pid_conf_mut(),
+        }
+    }
+
+    /// Provide access to controllers
+    pub(crate) fn controllers_mut(&mut self) -> PackXYA<&mut AxisController> {
+        PackXYA {
+            x: &mut self.pid_x,
+            y: &mut self.pid_y,
+            a: &mut self.pid_a,
         }
     }
 
@@ -74,13 +94,7 @@ impl MotorFilter {
 pub const RCS_MM_TO_CSUNIT: f32 = 1000.0;
 pub const RCS_RAD_TO_CSUNIT: f32 = 10000.0;
 
-#[inline]
-pub const fn rcs_mm_to_cs_unit(v: f32) -> i32 { (v * RCS_MM_TO_CSUNIT) as i32 }
-#[inline]
-pub const fn cs_unit_to_rcs_mm(v: i32) -> f32 { v as f32 / RCS_MM_TO_CSUNIT }
-
 #[inline]
 pub const fn rcs_rad_to_cs_unit(v: f32) -> i32 { (v * RCS_RAD_TO_CSUNIT) as i32 }
 #[inline]
 pub const fn cs_unit_to_rcs_rad(v: i32) -> f32 { v as f32 / RCS_RAD_TO_CSUNIT }
-
@@ -1,104 +1,141 @@
-use rome::{Message, params};
-use super::{Asserv, AsservHardware, RobotSide, TableSide, TrajectoryOrder};
+use core::time::Duration;
+use rome::{Handled, Message, RomeHandler, params};
+use super::{Asserv, AsservHardware, RobotSide, TrajectoryOrder};
 use super::conf::*;
+use super::motor_filter::RCS_RAD_TO_CSUNIT;
 use crate::rome::AsservRome;
 use crate::maths::{XY, XYA};
 
 
-impl From<params::AsservHoloAutosetTableSide> for TableSide {
-    fn from(value: params::AsservHoloAutosetTableSide) -> Self {
+impl From<params::RobotSide> for RobotSide {
+    fn from(value: params::RobotSide) -> Self {
         match value {
-            params::AsservHoloAutosetTableSide::Left => Self::Left,
-            params::AsservHoloAutosetTableSide::Right => Self::Right,
-            params::AsservHoloAutosetTableSide::Up => Self::Up,
-            params::AsservHoloAutosetTableSide::Down => Self::Down,
+            params::RobotSide::Left => Self::Left,
+            params::RobotSide::Right => Self::Right,
+            params::RobotSide::Back => Self::Back,
         }
     }
 }
 
-impl From<params::AsservHoloAutosetRobotSide> for RobotSide {
-    fn from(value: params::AsservHoloAutosetRobotSide) -> Self {
-        match value {
-            params::AsservHoloAutosetRobotSide::Left => Self::Left,
-            params::AsservHoloAutosetRobotSide::Right => Self::Right,
-            params::AsservHoloAutosetRobotSide::Back => Self::Back,
-        }
+
+impl<H: AsservHardware> RomeHandler for Asserv<H> {
+    // Common messages
+
+    fn on_asserv_set_position(&mut self, x: f32, y: f32, a: f32) -> Handled {
+        log::info!("ROME: reset_position: {x},{y},{a}");
+        self.reset_position(XYA::new(x, y, a));
+        Handled::Yes
     }
-}
 
+    fn on_asserv_goto_xy(&mut self, x: f32, y: f32) -> Handled {
+        log::info!("ROME: goto_xy: {x},{y}");
+        self.goto_xy(x, y);
+        Handled::Yes
+    }
 
-impl<H: AsservHardware> AsservRome for Asserv<H> {
-    fn on_rome_message(&mut self, message: &Message) -> bool {
-        match *message {
-            // Common messages
-            Message::AsservSetPosition { x, y, a } => {
-                log::info!("ROME: reset_position: {x},{y},{a}");
-                self.reset_position(XYA::new(x, y, a));
-            }
-            Message::AsservGotoXy { x, y } => {
-                log::info!("ROME: goto_xy: {x},{y}");
-                self.goto_xy(x, y);
-            }
-            Message::AsservGotoXyRel { dx, dy } => {
-                log::info!("ROME: goto_xyrel: {dx},{dy}");
-                self.goto_xy_rel(dx, dy);
-            }
-            Message::AsservGotoA { a } => {
-                log::info!("ROME: goto_a: {a}");
-                self.goto_a(a);
-            }
-            Message::AsservGotoARel { da } => {
-                log::info!("ROME: goto_a_rel: {da}");
-                self.goto_a_rel(da);
-            }
-            Message::AsservGotoXya { x, y, a } => {
-                log::info!("ROME: goto_xya is not implemented");
-                self.goto_xya(x, y, a);
-            }
-            // Common messages not (yet) implemented
-            Message::AsservActivate(_) => {
-                log::error!("ROME: AsservActivate is not implemented");
-            }
-            // AsservHolo messages
-            Message::AsservHoloAutoset { robot_side, table_side, target_x, target_y } => {
-                log::info!("ROME: autoset");
-                self.autoset(robot_side.into(), table_side.into(), XY::new(target_x, target_y));
-            }
-            Message::AsservHoloSetPidConf { pid, gain_p, gain_i, gain_d, max_in, max_i, max_out } => {
-                log::info!("ROME: set PID conf ({pid:?})");
-                let conf = PidConf { gain_p, gain_i, gain_d, max_in, max_i, max_out, out_shift: 0 };
-                match pid {
-                    params::AsservHoloSetPidConfPid::X => self.set_x_pid_conf(conf),
-                    params::AsservHoloSetPidConfPid::Y => self.set_y_pid_conf(conf),
-                    params::AsservHoloSetPidConfPid::A => self.set_a_pid_conf(conf),
-                }
-            }
-            Message::AsservHoloSetTrajectoryConf {
-                a_speed, a_acc, xy_cruise_speed, xy_cruise_acc, xy_steering_speed, xy_steering_acc,
-                xy_stop_speed, xy_stop_acc, xy_steering_window, xy_stop_window, a_stop_window,
-                autoset_speed, autoset_wait, autoset_duration,
-            } => {
-                log::info!("ROME: set trajectory conf");
-                let conf = TrajectoryConf {
-                    a_speed, a_acc, xy_cruise_speed, xy_cruise_acc, xy_steering_speed, xy_steering_acc,
-                    xy_stop_speed, xy_stop_acc, xy_steering_window, xy_stop_window, a_stop_window,
-                    autoset_speed, autoset_wait, autoset_duration,
-                };
-                self.set_trajectory_conf(conf);
-            }
-            Message::AsservHoloSetMotorsConf { velocities_to_consigns, encoders_to_position } => {
-                log::info!("ROME: set motors conf");
-                let conf = MotorsConf { velocities_to_consigns, encoders_to_position };
-                self.set_motors_conf(conf);
-            }
-            // Non-asserv messages, not handled
-            _ => {
-                return false;
-            }
+    fn on_asserv_goto_xy_rel(&mut self, dx: f32, dy: f32) -> Handled {
+        log::info!("ROME: goto_xyrel: {dx},{dy}");
+        self.goto_xy_rel(dx, dy);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_a(&mut self, a: f32) -> Handled {
+        log::info!("ROME: goto_a: {a}");
+        self.goto_a(a);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_a_rel(&mut self, da: f32) -> Handled {
+        log::info!("ROME: goto_a_rel: {da}");
+        self.goto_a_rel(da);
+        Handled::Yes
+    }
+
+    fn on_asserv_goto_xya(&mut self, x: f32, y: f32, a: f32) -> Handled {
+        log::info!("ROME: goto_xya is not implemented");
+        self.goto_xya(x, y, a);
+        Handled::Yes
+    }
+
+    fn on_asserv_run_path(&mut self, points: &rome::heapless::Vec<params::XY, 15>) -> Handled {
+        log::info!("ROME: run_path: {} points", points.len());
+        self.run_path(&crate::rome::path_points(points));
+        Handled::Yes
+    }
+
+    fn on_asserv_trace_freeze(&mut self, freeze: bool) -> Handled {
+        log::info!("ROME: trace freeze: {freeze}");
+        crate::rome::freeze_trace(self.cs.trace_mut(), freeze)
+    }
+
+    // Common messages not (yet) implemented
+
+    fn on_asserv_activate(&mut self, _: bool) -> Handled {
+        log::error!("ROME: AsservActivate is not implemented");
+        Handled::Yes
+    }
+
+    // AsservHolo messages
+
+    fn on_asserv_holo_autoset(&mut self, robot_side: params::RobotSide, table_side: params::TableSide, target_x: f32, target_y: f32) -> Handled {
+        log::info!("ROME: autoset");
+        self.autoset(robot_side.into(), table_side.into(), XY::new(target_x, target_y));
+        Handled::Yes
+    }
+
+    fn on_asserv_holo_set_pid_conf(&mut self, pid: params::AsservHoloSetPidConfPid, gain_p: i16, gain_i: i16, gain_d: i16, max_in: i32, max_i: i32, max_out: i32, out_shift: u8) -> Handled {
+        log::info!("ROME: set PID conf ({pid:?})");
+        let conf = PidConf { gain_p, gain_i, gain_d, max_in, max_i, max_out, out_shift };
+        match pid {
+            params::AsservHoloSetPidConfPid::X => self.set_x_pid_conf(conf),
+            params::AsservHoloSetPidConfPid::Y => self.set_y_pid_conf(conf),
+            params::AsservHoloSetPidConfPid::A => self.set_a_pid_conf(conf),
         }
-        true
+        Handled::Yes
+    }
+
+    fn on_asserv_holo_set_trajectory_conf(
+        &mut self,
+        a_speed: f32, a_acc: f32, xy_cruise_speed: f32, xy_cruise_acc: f32, xy_steering_speed: f32, xy_steering_acc: f32,
+        xy_stop_speed: f32, xy_stop_acc: f32, xy_steering_window: f32, xy_stop_window: f32, a_stop_window: f32,
+        autoset_speed: f32, autoset_wait: u8, autoset_duration: u8, xy_jerk: f32,
+    ) -> Handled {
+        log::info!("ROME: set trajectory conf");
+        // Message values are per update (angles in CS units), convert them using the update period
+        let step_secs = self.update_period_secs;
+        let speed = |v: f32| v / step_secs;
+        let acc = |v: f32| v / (step_secs * step_secs);
+        let ticks = |n: u8| Duration::from_secs_f32(n as f32 * step_secs);
+        let conf = TrajectoryConf {
+            a_speed: speed(a_speed) / RCS_RAD_TO_CSUNIT,
+            a_acc: acc(a_acc) / RCS_RAD_TO_CSUNIT,
+            xy_cruise_speed: speed(xy_cruise_speed),
+            xy_cruise_acc: acc(xy_cruise_acc),
+            xy_jerk: acc(xy_jerk) / step_secs,
+            xy_steering_speed: speed(xy_steering_speed),
+            xy_steering_acc: acc(xy_steering_acc),
+            xy_stop_speed: speed(xy_stop_speed),
+            xy_stop_acc: acc(xy_stop_acc),
+            xy_steering_window,
+            xy_stop_window,
+            a_stop_window,
+            autoset_speed: speed(autoset_speed),
+            autoset_wait: ticks(autoset_wait),
+            autoset_duration: ticks(autoset_duration),
+        };
+        self.set_trajectory_conf(conf);
+        Handled::Yes
     }
 
+    fn on_asserv_holo_set_motors_conf(&mut self, velocities_to_consigns: [f32; 9], encoders_to_position: [f32; 9]) -> Handled {
+        log::info!("ROME: set motors conf");
+        let conf = MotorsConf { velocities_to_consigns, encoders_to_position };
+        self.set_motors_conf(conf);
+        Handled::Yes
+    }
+}
+
+impl<H: AsservHardware> AsservRome for Asserv<H> {
     fn asserv_tm_status(&self) -> Message {
         let position = self.cs.position();
         Message::AsservTmStatus {
@@ -0,0 +1,188 @@
+//! Simulated hardware for the holonomic asserv
+//!
+//! [SimHardware] implements a kinematic model of a three-wheel omni base.
+//! Motor consigns are converted back to a velocity using the inverse of the
+//! configured `velocities_to_consigns` matrix, and encoder values are derived
+//! from the actual displacement using the inverse of `encoders_to_position`.
+//! As a result, a perfectly calibrated asserv uses the same [MotorsConf] as
+//! the simulation.
+//!
+//! The simulation is advanced with [SimHardware::step()], which must be called
+//! before each asserv update.
+
+use crate::maths::{invert_matrix33, mult_matrix33_vec, Matrix33, XY, XYA};
+use crate::sim::{Noise, Table};
+use super::conf::{AsservHardware, MotorsConf};
+
+
+/// Simulation configuration
+#[derive(Clone)]
+pub struct SimConf {
+    /// Motors configuration of the simulated robot
+    pub motors: MotorsConf,
+    /// Maximum absolute value of motor consigns (PWM saturation)
+    pub max_consign: f32,
+    /// Actual displacement per update (mm or rad) for a unit asserv velocity
+    pub velocity_scale: f32,
+    /// Ratio of the velocity change applied at each update, `1.0` for no inertia
+    pub response: f32,
+    /// Standard deviation of encoder noise, in encoder ticks per update
+    pub encoder_noise: f32,
+    /// Standard deviation of gyroscope noise, in rad per update
+    pub gyro_noise: f32,
+    /// Table walls, `None` to move freely
+    pub table: Option<Table>,
+    /// Radius of the robot, used for collisions with walls
+    pub robot_radius: f32,
+    /// Seed of noise generators
+    pub seed: u32,
+}
+
+impl Default for SimConf {
+    fn default() -> Self {
+        Self {
+            motors: MotorsConf::default(),
+            max_consign: 4095.0,
+            velocity_scale: 1.0,
+            response: 0.5,
+            encoder_noise: 0.0,
+            gyro_noise: 0.0,
+            table: None,
+            robot_radius: 0.0,
+            seed: 1,
+        }
+    }
+}
+
+
+/// Simulated holonomic robot, implementing [AsservHardware]
+pub struct SimHardware {
+    conf: SimConf,
+    consigns_to_velocities: Matrix33,
+    position_to_encoders: Matrix33,
+    noise: Noise,
+
+    /// Actual robot position
+    position: XYA,
+    /// Actual velocity, relative to the robot
+    velocity: [f32; 3],
+    consigns: [f32; 3],
+    motors_break: bool,
+    /// Encoder values, not rounded
+    encoders: [f32; 3],
+    /// Last encoder values returned by `get_motor_offsets()`
+    last_encoders: [f32; 3],
+    /// Gyroscope offset, since last call to `get_gyro_offset()`
+    gyro_offset: f32,
+}
+
+impl SimHardware {
+    /// Create a simulated robot at given position
+    ///
+    /// Panic if a matrix of motors configuration is not invertible.
+    pub fn new(conf: SimConf, position: XYA) -> Self {
+        let consigns_to_velocities = invert_matrix33(&conf.motors.velocities_to_consigns)
+            .expect("velocities_to_consigns matrix must be invertible");
+        let position_to_encoders = invert_matrix33(&conf.motors.encoders_to_position)
+            .expect("encoders_to_position matrix must be invertible");
+        let noise = Noise::new(conf.seed);
+        Self {
+            conf,
+            consigns_to_velocities,
+            position_to_encoders,
+            noise,
+            position,
+            velocity: [0.0; 3],
+            consigns: [0.0; 3],
+            motors_break: false,
+            encoders: [0.0; 3],
+            last_encoders: [0.0; 3],
+            gyro_offset: 0.0,
+        }
+    }
+
+    /// Return the actual robot position
+    pub fn position(&self) -> &XYA {
+        &self.position
+    }
+
+    /// Move the robot, without updating encoders nor gyroscope
+    pub fn set_position(&mut self, position: XYA) {
+        self.position = position;
+    }
+
+    /// Return the last motor consigns
+    pub fn consigns(&self) -> [f32; 3] {
+        self.consigns
+    }
+
+    /// Return true if the robot is against a wall
+    pub fn is_blocked(&self) -> bool {
+        match &self.conf.table {
+            Some(table) => {
+                let xy = self.position.xy();
+                let constrained = table.constrain(xy, self.conf.robot_radius + 0.5);
+                constrained.x != xy.x || constrained.y != xy.y
+            }
+            None => false,
+        }
+    }
+
+    /// Advance the simulation by one update
+    pub fn step(&mut self) {
+        let consigns = if self.motors_break {
+            [0.0; 3]
+        } else {
+            self.consigns.map(|v| v.clamp(-self.conf.max_consign, self.conf.max_consign))
+        };
+        let target = mult_matrix33_vec(&self.consigns_to_velocities, &consigns);
+        for (velocity, target) in self.velocity.iter_mut().zip(target) {
+            *velocity += (target * self.conf.velocity_scale - *velocity) * self.conf.response;
+        }
+
+        // Move in table coordinates, stop on walls
+        let [vx, vy, va] = self.velocity;
+        let (sin_a, cos_a) = self.position.a.sin_cos();
+        let mut xy = XY::new(
+            self.position.x + vx * cos_a - vy * sin_a,
+            self.position.y + vx * sin_a + vy * cos_a,
+        );
+        if let Some(table) = &self.conf.table {
+            xy = table.constrain(xy, self.conf.robot_radius);
+        }
+        let dxy = xy - self.position.xy();
+        self.position = xy.with_a(self.position.a + va);
+
+        // Update encoders from actual displacement, relative to the robot
+        // Encoder offsets are scaled by 1000 by the control system
+        let dx = dxy.x * cos_a + dxy.y * sin_a;
+        let dy = -dxy.x * sin_a + dxy.y * cos_a;
+        let wheels = mult_matrix33_vec(&self.position_to_encoders, &[dx * 1000.0, dy * 1000.0, va * 1000.0]);
+        for (encoder, offset) in self.encoders.iter_mut().zip(wheels) {
+            *encoder += offset + self.noise.gaussian(self.conf.encoder_noise);
+        }
+        self.gyro_offset += va + self.noise.gaussian(self.conf.gyro_noise);
+    }
+}
+
+impl AsservHardware for SimHardware {
+    fn set_motors_break(&mut self, enable: bool) {
+        self.motors_break = enable;
+    }
+
+    fn set_motor_consigns(&mut self, values: [f32; 3]) {
+        self.consigns = values;
+    }
+
+    fn get_motor_offsets(&mut self) -> [f32; 3] {
+        // Encoders return integer values
+        let encoders = self.encoders.map(f32::round);
+        let offsets = [0, 1, 2].map(|i| encoders[i] - self.last_encoders[i]);
+        self.last_encoders = encoders;
+        offsets
+    }
+
+    fn get_gyro_offset(&mut self) -> f32 {
+        core::mem::take(&mut self.gyro_offset)
+    }
+}
@@ -6,4 +6,18 @@ mod quadramp;
 mod ramp;
 #[cfg(feature = "rome")]
 pub mod rome;
+mod scurve;
+#[cfg(feature = "sim")]
+pub mod sim;
+pub mod trace;
 pub use amatheur as maths;
+
+
+/// Game table side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableSide {
+    Left,
+    Right,
+    Up,
+    Down,
+}
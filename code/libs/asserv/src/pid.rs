@@ -1,4 +1,4 @@
-use crate::conf::PidConf;
+use crate::conf::{ControllerConf, FloatPidConf, PidConf};
 
 
 #[derive(Default)]
@@ -60,3 +60,199 @@ impl PidFilter {
     }
 }
 
+
+
+/// Floating-point PID, see [FloatPidConf]
+#[derive(Default)]
+pub struct FloatPidFilter {
+    pub conf: FloatPidConf,
+    /// Previous target and error, `None` after a reset
+    previous: Option<(f32, f32)>,
+    target_velocity: f32,
+    /// Filtered derivative of the error
+    derivative: f32,
+    /// Integral term, gain included
+    integral: f32,
+    output: f32,
+}
+
+impl FloatPidFilter {
+    pub fn reset(&mut self) {
+        self.previous = None;
+        self.target_velocity = 0.0;
+        self.derivative = 0.0;
+        self.integral = 0.0;
+        self.output = 0.0;
+    }
+
+    /// Filter an error, `target` is used for feed-forward terms
+    pub fn filter(&mut self, target: f32, error: f32, dt: f32) -> f32 {
+        let conf = &self.conf;
+
+        // Target velocity and acceleration, error derivative
+        let (target_velocity, target_acceleration, derivate) = match self.previous {
+            Some((previous_target, previous_error)) => {
+                let velocity = (target - previous_target) / dt;
+                (velocity, (velocity - self.target_velocity) / dt, (error - previous_error) / dt)
+            }
+            None => (0.0, 0.0, 0.0),
+        };
+        self.previous = Some((target, error));
+        self.target_velocity = target_velocity;
+
+        // First-order low-pass filter on derivative
+        self.derivative += (derivate - self.derivative) * dt / (conf.d_filter + dt);
+
+        let unlimited = conf.kp * error + self.integral + conf.kd * self.derivative +
+            conf.kff_v * target_velocity + conf.kff_a * target_acceleration;
+        // Don't use `clamp()`, it panics on invalid limits (negative or NaN, NaN limits are ignored)
+        let max_out = conf.max_out.abs();
+        let max_change = conf.max_out_rate.abs() * dt;
+        let output = unlimited
+            .min(max_out).max(-max_out)
+            .min(self.output + max_change).max(self.output - max_change);
+
+        // Back-calculation anti-windup: integral is decreased when output is limited
+        self.integral += (conf.ki * error + conf.kaw * (output - unlimited)) * dt;
+
+        self.output = output;
+        output
+    }
+}
+
+
+/// Controller of an asserv axis: integer PID or floating-point PID
+pub(crate) struct AxisController {
+    pid: PidFilter,
+    float_pid: FloatPidFilter,
+    use_float_pid: bool,
+    /// Scale from axis units to integer PID internal units
+    scale: f32,
+}
+
+impl AxisController {
+    pub fn new(scale: f32) -> Self {
+        Self {
+            pid: PidFilter::default(),
+            float_pid: FloatPidFilter::default(),
+            use_float_pid: false,
+            scale,
+        }
+    }
+
+    /// Set controller configuration, select the controller type
+    pub fn set_conf(&mut self, conf: ControllerConf) {
+        match conf {
+            ControllerConf::Pid(conf) => {
+                self.pid.conf = conf;
+                self.use_float_pid = false;
+            }
+            ControllerConf::FloatPid(conf) => {
+                self.float_pid.conf = conf;
+                self.use_float_pid = true;
+            }
+        }
+        self.reset();
+    }
+
+    /// Integer PID configuration, used even if the floating-point PID is selected
+    pub fn pid_conf(&self) -> &PidConf {
+        &self.pid.conf
+    }
+
+    pub fn pid_conf_mut(&mut self) -> &mut PidConf {
+        &mut self.pid.conf
+    }
+
+    pub fn reset(&mut self) {
+        self.pid.reset();
+        self.float_pid.reset();
+    }
+
+    /// Filter using position (current) and target (consign)
+    ///
+    /// Return controller input and output, in controller units, and output in axis units.
+    pub fn filter(&mut self, current: f32, target: f32, dt: f32) -> (f32, f32, f32) {
+        if self.use_float_pid {
+            let error = target - current;
+            let output = self.float_pid.filter(target, error, dt);
+            (error, output, output)
+        } else {
+            let error = ((target - current) * self.scale) as i32;
+            let output = self.pid.filter(error);
+            (error as f32, output as f32, output as f32 / self.scale)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn float_pid_proportional() {
+        // Same output as an integer PID with only a proportional gain
+        let mut pid = AxisController::new(1000.0);
+        pid.set_conf(PidConf { gain_p: 10, ..Default::default() }.into());
+        let (_, _, expected) = pid.filter(1.0, 3.5, 0.01);
+        pid.set_conf(FloatPidConf { kp: 10.0, ..Default::default() }.into());
+        let (error, _, output) = pid.filter(1.0, 3.5, 0.01);
+        assert_eq!(error, 2.5);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn float_pid_limits() {
+        let mut pid = FloatPidFilter {
+            conf: FloatPidConf { max_out: 5.0, max_out_rate: 100.0, ..Default::default() },
+            ..Default::default()
+        };
+        // Output change is limited to 1 per update
+        assert_eq!(pid.filter(0.0, 10.0, 0.01), 1.0);
+        assert_eq!(pid.filter(0.0, 10.0, 0.01), 2.0);
+        for _ in 0..10 {
+            pid.filter(0.0, 10.0, 0.01);
+        }
+        assert_eq!(pid.filter(0.0, 10.0, 0.01), 5.0);
+    }
+
+    #[test]
+    fn float_pid_invalid_limits() {
+        // Negative limits are used as absolute values
+        let mut pid = AxisController::new(1000.0);
+        pid.set_conf(FloatPidConf { max_out: -5.0, max_out_rate: -100.0, ..Default::default() }.into());
+        assert_eq!(pid.filter(0.0, 10.0, 0.01).2, 1.0);
+        // NaN limits are ignored
+        pid.set_conf(FloatPidConf { max_out: f32::NAN, max_out_rate: f32::NAN, ..Default::default() }.into());
+        assert_eq!(pid.filter(0.0, 10.0, 0.01).2, 10.0);
+    }
+
+    #[test]
+    fn float_pid_anti_windup() {
+        let conf = FloatPidConf { kp: 0.0, ki: 1.0, max_out: 1.0, ..Default::default() };
+        let mut windup = FloatPidFilter { conf: conf.clone(), ..Default::default() };
+        let mut anti_windup = FloatPidFilter { conf: FloatPidConf { kaw: 10.0, ..conf }, ..Default::default() };
+        // Output is saturated for a long time
+        for _ in 0..1000 {
+            windup.filter(0.0, 1.0, 0.01);
+            anti_windup.filter(0.0, 1.0, 0.01);
+        }
+        // Integral is bounded, output leaves saturation quickly
+        let ticks = |pid: &mut FloatPidFilter| (0..2000).take_while(|_| pid.filter(0.0, -1.0, 0.01) >= 1.0).count();
+        assert!(ticks(&mut anti_windup) < 20);
+        assert!(ticks(&mut windup) > 500);
+    }
+
+    #[test]
+    fn float_pid_feed_forward() {
+        let mut pid = FloatPidFilter {
+            conf: FloatPidConf { kp: 0.0, kff_v: 1.0, ..Default::default() },
+            ..Default::default()
+        };
+        // No feed-forward on first update, then target velocity
+        assert_eq!(pid.filter(10.0, 0.0, 0.5), 0.0);
+        assert_eq!(pid.filter(20.0, 0.0, 0.5), 20.0);
+        assert_eq!(pid.filter(20.0, 0.0, 0.5), 0.0);
+    }
+}
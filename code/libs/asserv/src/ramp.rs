@@ -67,8 +67,18 @@ impl RampFilter {
 
     #[allow(dead_code)]
     pub fn reset_finished_to(&mut self, consign: f32) {
-        self.position = consign;
-        self.speed = 0.0;
+        self.reset_to(consign, 0.0);
+    }
+
+    /// Reset to given position and speed
+    pub fn reset_to(&mut self, position: f32, speed: f32) {
+        self.position = position;
+        self.speed = speed;
+    }
+
+    /// Return current `(position, speed)`
+    pub fn state(&self) -> (f32, f32) {
+        (self.position, self.speed)
     }
 }
 
@@ -1,12 +1,199 @@
-use rome::Message;
+use rome::{Handled, Message, RomeHandler, params};
+use rome::registry::{ParamRegistry, ParamType};
+use crate::conf::PidConf;
+use crate::TableSide;
+use crate::maths::XY;
+use crate::trace::{TraceRecorder, TraceSample};
 
 /// Support for common asserv ROME messages
-pub trait AsservRome {
-    /// ROME message event handler, return true if message has been processed
-    fn on_rome_message(&mut self, message: &Message) -> bool;
+///
+/// Received messages are handled through [RomeHandler].
+pub trait AsservRome: RomeHandler {
     /// Create an `AsservTmStatus` message from current asserv state
     fn asserv_tm_status(&self) -> Message;
     /// Create an `AsservTmVelocity` message from current asserv state (optional)
     fn asserv_tm_velocity(&self) -> Option<Message>;
 }
 
+
+/// Register parameters of a PID configuration: `{prefix}.gain_p`, `{prefix}.gain_i`, ...
+///
+/// The configuration is read with `get` and updated with `set`.
+pub fn register_pid_params<C: 'static>(registry: &mut ParamRegistry<C>, prefix: &str, get: fn(&C) -> &PidConf, set: fn(&mut C, PidConf)) {
+    macro_rules! register_field {
+        ($field:ident, $range:expr) => {
+            registry.add(
+                &format!("{prefix}.{}", stringify!($field)),
+                $range,
+                move |context: &C| get(context).$field,
+                move |context, value| {
+                    let mut conf = get(context).clone();
+                    conf.$field = value;
+                    set(context, conf);
+                },
+            );
+        }
+    }
+
+    register_field!(gain_p, i16::FULL_RANGE);
+    register_field!(gain_i, i16::FULL_RANGE);
+    register_field!(gain_d, i16::FULL_RANGE);
+    register_field!(max_in, 0..=i32::MAX);
+    register_field!(max_i, 0..=i32::MAX);
+    register_field!(max_out, 0..=i32::MAX);
+    register_field!(out_shift, 0..=31);
+}
+
+
+/// Freeze or unfreeze a trace, on `AsservTraceFreeze` orders
+pub(crate) fn freeze_trace<S: TraceSample>(trace: Option<&mut TraceRecorder<S>>, freeze: bool) -> Handled {
+    match trace {
+        Some(trace) if freeze => trace.freeze(),
+        Some(trace) => trace.unfreeze(),
+        None => log::warn!("ROME: trace is not enabled"),
+    }
+    Handled::Yes
+}
+
+
+impl From<params::TableSide> for TableSide {
+    fn from(value: params::TableSide) -> Self {
+        match value {
+            params::TableSide::Left => Self::Left,
+            params::TableSide::Right => Self::Right,
+            params::TableSide::Up => Self::Up,
+            params::TableSide::Down => Self::Down,
+        }
+    }
+}
+
+
+/// Convert path points of an `AsservRunPath` order
+pub(crate) fn path_points(points: &[params::XY]) -> Vec<XY> {
+    points.iter().map(|point| XY::new(point.x, point.y)).collect()
+}
+
+
+/// Maximum number of values in an `AsservTraceTm` chunk
+const TRACE_CHUNK_MAX_VALUES: usize = 96;
+
+/// Stream a trace as `AsservTraceColumns` and `AsservTraceTm` messages
+///
+/// A dump is started on `AsservTraceDump` orders, handled through [RomeHandler].
+/// Then [poll()](Self::poll()) is called regularly, and returns one message at a time,
+/// to not flood the link.
+#[derive(Default)]
+pub struct TraceStream {
+    state: TraceStreamState,
+}
+
+#[derive(Default)]
+enum TraceStreamState {
+    #[default]
+    Idle,
+    Columns,
+    Chunk(usize),
+}
+
+impl TraceStream {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a new dump, abort the current one
+    pub fn start(&mut self) {
+        self.state = TraceStreamState::Columns;
+    }
+
+    /// Return true if a dump is in progress
+    pub fn in_progress(&self) -> bool {
+        !matches!(self.state, TraceStreamState::Idle)
+    }
+
+    /// Return the next message of the current dump, if any
+    ///
+    /// The trace is frozen when the dump starts, to send consistent data.
+    pub fn poll<S: TraceSample>(&mut self, trace: Option<&mut TraceRecorder<S>>) -> Option<Message> {
+        let Some(trace) = trace else {
+            if self.in_progress() {
+                log::warn!("ROME: cannot dump trace, trace is not enabled");
+                self.state = TraceStreamState::Idle;
+            }
+            return None;
+        };
+        match self.state {
+            TraceStreamState::Idle => None,
+            TraceStreamState::Columns => {
+                trace.freeze();
+                self.state = TraceStreamState::Chunk(0);
+                let columns = S::COLUMNS.join(",");
+                Some(Message::AsservTraceColumns {
+                    columns: columns.as_str().try_into().expect("trace column names are too long"),
+                })
+            }
+            TraceStreamState::Chunk(index) => {
+                let num_chunks = trace.num_chunks(TRACE_CHUNK_MAX_VALUES);
+                match trace.chunk(index, TRACE_CHUNK_MAX_VALUES) {
+                    Some((tick, values)) => {
+                        self.state = TraceStreamState::Chunk(index + 1);
+                        Some(Message::AsservTraceTm {
+                            chunk_index: index as u16,
+                            num_chunks: num_chunks as u16,
+                            tick,
+                            values: values.collect(),
+                        })
+                    }
+                    None => {
+                        self.state = TraceStreamState::Idle;
+                        None
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl RomeHandler for TraceStream {
+    fn on_asserv_trace_dump(&mut self) -> Handled {
+        log::info!("ROME: trace dump");
+        self.start();
+        Handled::Yes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::differential::AsservSample;
+
+    #[test]
+    fn trace_stream() {
+        let mut trace = TraceRecorder::new(20);
+        for i in 0..15 {
+            trace.record(AsservSample { x: i as f32, ..Default::default() });
+        }
+        let mut stream = TraceStream::new();
+        assert!(stream.poll(Some(&mut trace)).is_none());
+        assert_eq!(stream.on_asserv_trace_dump(), Handled::Yes);
+
+        let Some(Message::AsservTraceColumns { columns }) = stream.poll(Some(&mut trace)) else {
+            panic!("columns expected");
+        };
+        assert!(columns.starts_with("x,y,a,dist,"));
+        assert!(trace.is_frozen());
+
+        // 14 columns, 6 samples per chunk
+        let mut samples = Vec::new();
+        while let Some(message) = stream.poll(Some(&mut trace)) {
+            let Message::AsservTraceTm { chunk_index, num_chunks, tick, values } = message else {
+                panic!("chunk expected, got {message:?}");
+            };
+            assert_eq!(num_chunks, 3);
+            assert_eq!(tick, chunk_index as u32 * 6);
+            samples.extend(values.chunks(14).map(|values| values[0]));
+        }
+        assert_eq!(samples, (0..15).map(|i| i as f32).collect::<Vec<_>>());
+        assert!(!stream.in_progress());
+    }
+}
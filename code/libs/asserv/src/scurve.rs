@@ -0,0 +1,315 @@
+//! Jerk-limited (S-curve) profiles
+//!
+//! Acceleration changes progressively, at most by the maximum jerk on each
+//! update, instead of instantly as with trapezoidal profiles.
+//!
+//! All values are raw (unit-less): speed, acceleration and jerk are per update.
+
+use crate::ramp::RampFilter;
+
+
+/// Jerk-limited position profile
+///
+/// Same as [RampFilter], with a limited jerk.
+/// On each update, the highest jerk is used, as long as the profile can still
+/// stop on the target and does not exceed the maximum speed.
+#[derive(Default)]
+pub struct SCurveFilter {
+    max_speed: f32,
+    max_acc: f32,
+    max_jerk: f32,
+    position: f32,
+    speed: f32,
+    acc: f32,
+}
+
+impl SCurveFilter {
+    /// Number of iterations of the jerk search
+    const JERK_SEARCH_ITERATIONS: usize = 16;
+
+    /// Configure using raw values (unit-less)
+    pub fn configure_raw(&mut self, max_speed: f32, max_acc: f32, max_jerk: f32) {
+        self.max_speed = max_speed;
+        self.max_acc = max_acc;
+        self.max_jerk = max_jerk;
+    }
+
+    /// Return filtered target position
+    pub fn filter(&mut self, target: f32) -> f32 {
+        let diff = target - self.position;
+        if diff == 0.0 && self.speed == 0.0 && self.acc == 0.0 {
+            return target;
+        }
+
+        // Work in the target direction
+        let direction = if diff >= 0.0 { 1.0 } else { -1.0 };
+        let distance = diff.abs();
+        let speed = self.speed * direction;
+        let acc = self.acc * direction;
+
+        // Jerk range, keep acceleration within limits
+        let min_jerk = (-self.max_jerk).max(-self.max_acc - acc);
+        let max_jerk = self.max_jerk.min(self.max_acc - acc).max(min_jerk);
+        // Constraints are monotonic with the jerk: search the highest safe value
+        let jerk = if self.is_safe(speed, acc, distance, max_jerk) {
+            max_jerk
+        } else if !self.is_safe(speed, acc, distance, min_jerk) {
+            min_jerk
+        } else {
+            let (mut safe, mut unsafe_) = (min_jerk, max_jerk);
+            for _ in 0..Self::JERK_SEARCH_ITERATIONS {
+                let jerk = (safe + unsafe_) / 2.0;
+                if self.is_safe(speed, acc, distance, jerk) {
+                    safe = jerk;
+                } else {
+                    unsafe_ = jerk;
+                }
+            }
+            safe
+        };
+
+        let (delta, speed, acc) = integrate(speed, acc, jerk, 1.0);
+        if delta >= distance || (distance - delta < 0.001 && speed.abs() < 0.001) {
+            // Target reached, prevent overshoot because of discrete steps
+            self.reset_finished_to(target);
+        } else {
+            self.position += delta * direction;
+            self.speed = speed * direction;
+            self.acc = acc * direction;
+        }
+        self.position
+    }
+
+    /// Reset to a still position
+    pub fn reset_finished_to(&mut self, position: f32) {
+        self.reset_to(position, 0.0);
+    }
+
+    /// Reset to given position and speed, with a null acceleration
+    pub fn reset_to(&mut self, position: f32, speed: f32) {
+        self.position = position;
+        self.speed = speed;
+        self.acc = 0.0;
+    }
+
+    /// Return true if applying `jerk` for one update respects speed limit and allows to stop in time
+    fn is_safe(&self, speed: f32, acc: f32, distance: f32, jerk: f32) -> bool {
+        let (delta, speed, acc) = integrate(speed, acc, jerk, 1.0);
+        // Speed reached when acceleration is brought back to zero
+        let final_speed = speed + acc.max(0.0) * acc.max(0.0) / (2.0 * self.max_jerk);
+        final_speed <= self.max_speed && self.stop_distance(speed, acc) <= distance - delta
+    }
+
+    /// Return the distance needed to stop, using maximum jerk and deceleration
+    fn stop_distance(&self, speed: f32, acc: f32) -> f32 {
+        if speed <= 0.0 && acc <= 0.0 {
+            return 0.0;
+        }
+        let jerk = self.max_jerk;
+        // Deceleration profile: decrease acceleration to `-peak`, keep it, then increase it back to zero
+        let peak = (jerk * speed + acc * acc / 2.0).max(0.0).sqrt().min(self.max_acc).max(-acc);
+        if peak <= 0.0 {
+            return 0.0;
+        }
+        let t1 = ((acc + peak) / jerk).max(0.0);
+        let (d1, v1, _) = integrate(speed, acc, -jerk, t1);
+        let t2 = ((v1 - peak * peak / (2.0 * jerk)) / peak).max(0.0);
+        let (d2, v2, _) = integrate(v1, -peak, 0.0, t2);
+        let (d3, _, _) = integrate(v2, -peak, jerk, peak / jerk);
+        d1 + d2 + d3
+    }
+}
+
+
+/// Position profile, trapezoidal or jerk-limited
+///
+/// The S-curve profile is used if a jerk is set.
+#[derive(Default)]
+pub struct ProfileFilter {
+    ramp: RampFilter,
+    scurve: SCurveFilter,
+    use_scurve: bool,
+}
+
+impl ProfileFilter {
+    /// Configure speed and acceleration, using in s⁻¹, s⁻² and step duration values
+    pub fn configure_scaled(&mut self, max_speed: f32, max_acc: f32, step_secs: f32) {
+        self.ramp.configure_scaled(max_speed, max_acc, step_secs);
+        let max_jerk = self.scurve.max_jerk;
+        self.scurve.configure_raw(max_speed * step_secs, max_acc * step_secs * step_secs, max_jerk);
+    }
+
+    /// Configure jerk, using in s⁻³ and step duration value, 0 to use a trapezoidal profile
+    ///
+    /// Current position and speed are kept when the profile type changes.
+    pub fn set_jerk_scaled(&mut self, max_jerk: f32, step_secs: f32) {
+        self.scurve.max_jerk = max_jerk * step_secs * step_secs * step_secs;
+        let use_scurve = max_jerk > 0.0;
+        if use_scurve && !self.use_scurve {
+            let (position, speed) = self.ramp.state();
+            self.scurve.reset_to(position, speed);
+        } else if !use_scurve && self.use_scurve {
+            self.ramp.reset_to(self.scurve.position, self.scurve.speed);
+        }
+        self.use_scurve = use_scurve;
+    }
+
+    /// Return filtered target position
+    pub fn filter(&mut self, target: f32) -> f32 {
+        if self.use_scurve {
+            self.scurve.filter(target)
+        } else {
+            self.ramp.filter(target)
+        }
+    }
+
+    pub fn reset_finished_to(&mut self, position: f32) {
+        self.ramp.reset_finished_to(position);
+        self.scurve.reset_finished_to(position);
+    }
+}
+
+
+/// Return the next acceleration to reach a target speed, with a limited jerk
+///
+/// Acceleration is decreased early enough to reach the target speed without overshoot.
+pub fn speed_acceleration(speed: f32, acc: f32, target: f32, max_acc: f32, max_jerk: f32) -> f32 {
+    let dv = target - speed;
+    // Bringing acceleration from `acc` to zero at maximum jerk, one step at a time,
+    // changes speed by `acc² / (2 × jerk) + acc / 2`: solve it for `dv`
+    let desired = (max_jerk * max_jerk + 8.0 * max_jerk * dv.abs()).sqrt() - max_jerk;
+    let desired = dv.signum() * (desired / 2.0).min(max_acc);
+    acc + (desired - acc).clamp(-max_jerk, max_jerk)
+}
+
+/// Return the distance needed to change speed, with a limited jerk
+///
+/// Acceleration is assumed to be null at the start and at the end.
+pub fn speed_change_distance(from: f32, to: f32, max_acc: f32, max_jerk: f32) -> f32 {
+    let dv = (to - from).abs();
+    let duration = if dv * max_jerk >= max_acc * max_acc {
+        // Maximum acceleration is reached
+        dv / max_acc + max_acc / max_jerk
+    } else {
+        2.0 * (dv / max_jerk).sqrt()
+    };
+    // Profile is symmetric: average speed is the mean of both speeds
+    0.5 * (from + to) * duration
+}
+
+
+/// Integrate a constant jerk during `t`, return `(distance, speed, acceleration)`
+fn integrate(speed: f32, acc: f32, jerk: f32, t: f32) -> (f32, f32, f32) {
+    (
+        speed * t + acc * t * t / 2.0 + jerk * t * t * t / 6.0,
+        speed + acc * t + jerk * t * t / 2.0,
+        acc + jerk * t,
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scurve(max_speed: f32, max_acc: f32, max_jerk: f32) -> SCurveFilter {
+        let mut filter = SCurveFilter::default();
+        filter.configure_raw(max_speed, max_acc, max_jerk);
+        filter
+    }
+
+    /// Run the filter to a target, return positions
+    fn run(filter: &mut SCurveFilter, target: f32, max_steps: usize) -> Vec<f32> {
+        let mut positions = vec![filter.position];
+        while positions.len() <= max_steps {
+            positions.push(filter.filter(target));
+            if filter.position == target && filter.speed == 0.0 {
+                return positions;
+            }
+        }
+        panic!("target not reached, position: {}", filter.position);
+    }
+
+    /// Return successive differences
+    fn diff(values: &[f32]) -> Vec<f32> {
+        values.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    #[test]
+    fn limits() {
+        let mut filter = scurve(10.0, 1.0, 0.1);
+        let positions = run(&mut filter, 1000.0, 1000);
+        assert!(positions.windows(2).all(|w| w[1] >= w[0]), "no overshoot");
+        let speeds = diff(&positions);
+        assert!(speeds.iter().all(|v| *v <= 10.0 + 1e-3));
+        let accs = diff(&speeds);
+        assert!(accs.iter().all(|a| a.abs() <= 1.0 + 1e-3));
+        // Last step may be truncated
+        let jerks = diff(&accs);
+        assert!(jerks[..jerks.len() - 2].iter().all(|j| j.abs() <= 0.1 + 1e-2), "jerks: {jerks:?}");
+        // 100 steps at max speed, plus 10 to reach max acceleration and 10 more to reach max speed
+        assert!((110..140).contains(&positions.len()), "steps: {}", positions.len());
+    }
+
+    #[test]
+    fn short_move() {
+        // Maximum acceleration and speed are not reached
+        let mut filter = scurve(10.0, 1.0, 0.1);
+        let positions = run(&mut filter, -5.0, 100);
+        assert!(positions.windows(2).all(|w| w[1] <= w[0]), "no overshoot");
+        let accs = diff(&diff(&positions));
+        assert!(accs.iter().all(|a| a.abs() < 0.5));
+    }
+
+    #[test]
+    fn reverse_target() {
+        let mut filter = scurve(10.0, 1.0, 0.1);
+        for _ in 0..30 {
+            filter.filter(1000.0);
+        }
+        assert!(filter.speed > 5.0);
+        let positions = run(&mut filter, 0.0, 1000);
+        let accs = diff(&diff(&positions));
+        assert!(accs.iter().all(|a| a.abs() <= 1.0 + 1e-3));
+        assert!(positions.iter().all(|p| *p >= 0.0), "no overshoot");
+    }
+
+    #[test]
+    fn profile_switch() {
+        let mut filter = ProfileFilter::default();
+        filter.configure_scaled(10.0, 1.0, 1.0);
+        for _ in 0..5 {
+            filter.filter(1000.0);
+        }
+        // Speed is kept
+        filter.set_jerk_scaled(0.1, 1.0);
+        assert_eq!(filter.scurve.speed, 5.0);
+        let p0 = filter.filter(1000.0);
+        let p1 = filter.filter(1000.0);
+        assert!(p1 - p0 >= 5.0);
+        filter.set_jerk_scaled(0.0, 1.0);
+        assert_eq!(filter.ramp.state(), (filter.scurve.position, filter.scurve.speed));
+    }
+
+    #[test]
+    fn speed_profile() {
+        let (mut speed, mut acc) = (0.0, 0.0);
+        let mut distance = 0.0;
+        let mut steps = 0;
+        while speed < 10.0 - 1e-3 {
+            let next_acc = speed_acceleration(speed, acc, 10.0, 1.0, 0.1);
+            assert!((next_acc - acc).abs() <= 0.1 + 1e-6);
+            assert!(next_acc <= 1.0);
+            acc = next_acc;
+            speed = (speed + acc).min(10.0);
+            distance += speed;
+            steps += 1;
+            assert!(steps < 100);
+        }
+        // Same duration and distance as the continuous profile
+        assert_eq!(steps, 19);
+        let expected = speed_change_distance(0.0, 10.0, 1.0, 0.1);
+        assert_eq!(expected, 100.0);
+        assert!((distance - expected).abs() < 1.0, "distance: {distance}");
+    }
+}
@@ -0,0 +1,90 @@
+//! Common elements for simulated asserv hardware
+//!
+//! Simulated hardware is intended for host builds and tests. Each asserv kind
+//! provides its own `sim` module, implementing its `AsservHardware`.
+
+use crate::maths::XY;
+
+
+/// Pseudo-random noise generator, deterministic for a given seed
+#[derive(Clone)]
+pub struct Noise {
+    state: u32,
+}
+
+impl Noise {
+    pub fn new(seed: u32) -> Self {
+        // Xorshift state must not be null
+        Self { state: seed.max(1) }
+    }
+
+    /// Return a uniform value in `[0, 1)`
+    pub fn uniform(&mut self) -> f32 {
+        // Xorshift32
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Return a value from a normal distribution centered on 0
+    pub fn gaussian(&mut self, std_dev: f32) -> f32 {
+        if std_dev == 0.0 {
+            return 0.0;
+        }
+        // Box-Muller transform
+        let u1 = 1.0 - self.uniform();
+        let u2 = self.uniform();
+        std_dev * (-2.0 * u1.ln()).sqrt() * (core::f32::consts::TAU * u2).cos()
+    }
+}
+
+
+/// Table walls, the table spans from `(0, 0)` to `(width, height)`
+#[derive(Clone, Copy, Debug)]
+pub struct Table {
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Table {
+    /// Eurobot table size, in mm
+    pub const EUROBOT: Self = Self { width: 3000.0, height: 2000.0 };
+
+    /// Keep a disc of given radius inside the table
+    pub fn constrain(&self, center: XY, radius: f32) -> XY {
+        XY::new(
+            center.x.clamp(radius, self.width - radius),
+            center.y.clamp(radius, self.height - radius),
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise() {
+        let mut noise = Noise::new(42);
+        let values: Vec<f32> = (0..10000).map(|_| noise.gaussian(2.0)).collect();
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        let variance = values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / values.len() as f32;
+        assert!(mean.abs() < 0.1, "mean: {mean}");
+        assert!((variance.sqrt() - 2.0).abs() < 0.1, "std dev: {}", variance.sqrt());
+
+        // Deterministic
+        let mut other = Noise::new(42);
+        assert_eq!(other.gaussian(2.0), values[0]);
+    }
+
+    #[test]
+    fn table_constrain() {
+        let table = Table::EUROBOT;
+        let xy = table.constrain(XY::new(-10.0, 1000.0), 100.0);
+        assert_eq!((xy.x, xy.y), (100.0, 1000.0));
+        let xy = table.constrain(XY::new(2950.0, 1950.0), 100.0);
+        assert_eq!((xy.x, xy.y), (2900.0, 1900.0));
+    }
+}
@@ -0,0 +1,247 @@
+//! Per-update trace recorder, for asserv tuning
+//!
+//! A [TraceRecorder] stores the last samples in a ring buffer, one sample per
+//! asserv update. Each asserv defines its own sample type, implementing
+//! [TraceSample]; samples are a list of named `f32` values.
+//!
+//! Recording can be frozen, for instance after a move, to keep the samples
+//! until they are dumped. Samples can be written as CSV, or read by chunks to
+//! be sent as telemetry.
+
+use std::io;
+
+
+/// Sample of a trace, a list of named values
+pub trait TraceSample: Copy + Default {
+    /// Names of sample values, in order
+    const COLUMNS: &'static [&'static str];
+    /// Return the value of the column at given index
+    fn value(&self, column: usize) -> f32;
+}
+
+/// Declare a sample structure with `f32` fields, and implement [TraceSample] for it
+macro_rules! trace_sample {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident { $($(#[$field_attr:meta])* $field:ident,)* }
+    ) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Default, Debug)]
+        $vis struct $name {
+            $($(#[$field_attr])* pub $field: f32,)*
+        }
+
+        impl $crate::trace::TraceSample for $name {
+            const COLUMNS: &'static [&'static str] = &[$(stringify!($field)),*];
+
+            fn value(&self, column: usize) -> f32 {
+                [$(self.$field),*][column]
+            }
+        }
+    }
+}
+pub(crate) use trace_sample;
+
+
+/// Record asserv samples in a ring buffer
+pub struct TraceRecorder<S: TraceSample> {
+    samples: Vec<S>,
+    capacity: usize,
+    /// Index of the next sample to write, when the buffer is full
+    next: usize,
+    /// Update index, incremented on each recorded sample, even when frozen
+    tick: u32,
+    /// Update index following the newest recorded sample
+    end_tick: u32,
+    frozen: bool,
+}
+
+impl<S: TraceSample> TraceRecorder<S> {
+    /// Create a recorder, keeping at most `capacity` samples
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            samples: Vec::with_capacity(capacity),
+            capacity,
+            next: 0,
+            tick: 0,
+            end_tick: 0,
+            frozen: false,
+        }
+    }
+
+    /// Record a new sample, drop the oldest one if needed
+    ///
+    /// Samples are ignored when the recorder is frozen, but still counted.
+    pub fn record(&mut self, sample: S) {
+        self.tick = self.tick.wrapping_add(1);
+        if self.frozen {
+            return;
+        }
+        self.end_tick = self.tick;
+        if self.samples.len() < self.capacity {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.next] = sample;
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+
+    /// Stop recording new samples, recorded ones are kept
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    /// Resume recording, drop previously recorded samples
+    ///
+    /// Recorded samples are always consecutive.
+    pub fn unfreeze(&mut self) {
+        self.frozen = false;
+        self.clear();
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Drop all recorded samples
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.next = 0;
+        self.end_tick = self.tick;
+    }
+
+    /// Return the number of recorded samples
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Return the update index of the oldest recorded sample
+    ///
+    /// Index is incremented on each update, even when frozen, and wraps around.
+    pub fn first_tick(&self) -> u32 {
+        self.end_tick.wrapping_sub(self.samples.len() as u32)
+    }
+
+    /// Iterate on recorded samples, from the oldest one
+    pub fn iter(&self) -> impl Iterator<Item = &S> {
+        let (newest, oldest) = self.samples.split_at(self.next);
+        oldest.iter().chain(newest)
+    }
+
+    /// Write recorded samples as CSV, with a header line
+    ///
+    /// The first column is the update index.
+    pub fn write_csv<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        write!(writer, "tick")?;
+        for column in S::COLUMNS {
+            write!(writer, ",{column}")?;
+        }
+        writeln!(writer)?;
+        let mut tick = self.first_tick();
+        for sample in self.iter() {
+            write!(writer, "{tick}")?;
+            for column in 0..S::COLUMNS.len() {
+                write!(writer, ",{}", sample.value(column))?;
+            }
+            writeln!(writer)?;
+            tick = tick.wrapping_add(1);
+        }
+        Ok(())
+    }
+
+    /// Return the number of chunks needed to transfer recorded samples
+    ///
+    /// Each chunk has at most `max_values` values, and only complete samples.
+    pub fn num_chunks(&self, max_values: usize) -> usize {
+        self.samples.len().div_ceil(Self::samples_per_chunk(max_values))
+    }
+
+    /// Return the values of a chunk, and the update index of its first sample
+    ///
+    /// Values of each sample are in column order. See [num_chunks()](Self::num_chunks()).
+    /// Return `None` if the chunk index is out of range.
+    pub fn chunk(&self, index: usize, max_values: usize) -> Option<(u32, impl Iterator<Item = f32> + '_)> {
+        let samples_per_chunk = Self::samples_per_chunk(max_values);
+        let first = index * samples_per_chunk;
+        if first >= self.samples.len() {
+            return None;
+        }
+        let tick = self.first_tick().wrapping_add(first as u32);
+        let values = self.iter()
+            .skip(first)
+            .take(samples_per_chunk)
+            .flat_map(|sample| (0..S::COLUMNS.len()).map(|column| sample.value(column)));
+        Some((tick, values))
+    }
+
+    fn samples_per_chunk(max_values: usize) -> usize {
+        let samples = max_values / S::COLUMNS.len();
+        assert!(samples > 0, "chunk too small for a single sample");
+        samples
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    trace_sample! {
+        struct Sample { a, b, }
+    }
+
+    fn recorder(capacity: usize, count: usize) -> TraceRecorder<Sample> {
+        let mut recorder = TraceRecorder::new(capacity);
+        for i in 0..count {
+            recorder.record(Sample { a: i as f32, b: -(i as f32) });
+        }
+        recorder
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let recorder = recorder(4, 3);
+        assert_eq!(recorder.iter().map(|s| s.a).collect::<Vec<_>>(), [0.0, 1.0, 2.0]);
+        assert_eq!(recorder.first_tick(), 0);
+
+        let mut recorder = self::recorder(4, 6);
+        assert_eq!(recorder.len(), 4);
+        assert_eq!(recorder.iter().map(|s| s.a).collect::<Vec<_>>(), [2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(recorder.first_tick(), 2);
+
+        recorder.freeze();
+        recorder.record(Sample::default());
+        assert_eq!(recorder.iter().map(|s| s.a).collect::<Vec<_>>(), [2.0, 3.0, 4.0, 5.0]);
+        assert_eq!(recorder.first_tick(), 2);
+        recorder.unfreeze();
+        assert!(recorder.is_empty());
+        assert_eq!(recorder.first_tick(), 7);
+    }
+
+    #[test]
+    fn csv() {
+        let recorder = recorder(2, 3);
+        let mut output = Vec::new();
+        recorder.write_csv(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "tick,a,b\n1,1,-1\n2,2,-2\n");
+    }
+
+    #[test]
+    fn chunks() {
+        let recorder = recorder(10, 5);
+        // 2 samples per chunk
+        assert_eq!(recorder.num_chunks(5), 3);
+        let (tick, values) = recorder.chunk(1, 5).unwrap();
+        assert_eq!(tick, 2);
+        assert_eq!(values.collect::<Vec<_>>(), [2.0, -2.0, 3.0, -3.0]);
+        let (tick, values) = recorder.chunk(2, 5).unwrap();
+        assert_eq!(tick, 4);
+        assert_eq!(values.count(), 2);
+        assert!(recorder.chunk(3, 5).is_none());
+    }
+}
@@ -0,0 +1,437 @@
+//! Closed-loop tests of the differential asserv, using simulated hardware
+
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+use asserv::maths::{XY, XYA, normalize_radians_pi_pi};
+use asserv::differential::{Asserv, MoveDirection, RobotSide, TableSide};
+use asserv::differential::conf::{AsservConf, FloatPidConf, PidConf, TrajectoryConf};
+use asserv::differential::sim::{SimConf, SimHardware};
+use asserv::sim::Table;
+
+
+const PERIOD: Duration = Duration::from_millis(10);
+
+fn asserv_conf(sim_conf: &SimConf) -> AsservConf {
+    AsservConf {
+        pid_dist: PidConf { gain_p: 10, ..Default::default() }.into(),
+        pid_angle: PidConf { gain_p: 200, ..Default::default() }.into(),
+        trajectory: TrajectoryConf {
+            a_speed: 6.0,
+            a_acc: 20.0,
+            a_jerk: 0.0,
+            xy_speed: 500.0,
+            xy_acc: 1000.0,
+            xy_jerk: 0.0,
+            xy_stop_window: 5.0,
+            xy_aim_angle_window: 0.05,
+            xy_cruise_angle_window: 1.0,
+            xy_approach_window: 50.0,
+            a_stop_window: 0.03,
+            xy_idle_speed: 0.01,
+            a_idle_speed: 0.01,
+            xy_path_lookahead: 100.0,
+            xy_path_lateral_acc: 0.0,
+            autoset_speed: 100.0,
+            autoset_backoff: 50.0,
+            autoset_timeout: 5.0,
+        },
+        motors: sim_conf.motors_conf(),
+    }
+}
+
+/// Create an asserv, with an initial position known by both the asserv and the simulation
+fn new_asserv(position: XYA) -> Asserv<SimHardware> {
+    new_asserv_with_conf(SimConf::default(), position)
+}
+
+/// Same as [new_asserv()], with a custom simulation configuration
+fn new_asserv_with_conf(sim_conf: SimConf, position: XYA) -> Asserv<SimHardware> {
+    let conf = asserv_conf(&sim_conf);
+    let mut asserv = Asserv::new(SimHardware::new(sim_conf, position), PERIOD);
+    asserv.set_conf(conf);
+    asserv.reset_position(position);
+    asserv
+}
+
+fn step(asserv: &mut Asserv<SimHardware>) {
+    asserv.hardware_mut().step(&PERIOD);
+    asserv.update(&PERIOD);
+}
+
+/// Run the asserv until `done` returns true, return the number of updates
+///
+/// Panic if `done` is still false after `max_ticks` updates.
+fn run_until(asserv: &mut Asserv<SimHardware>, max_ticks: u32, mut done: impl FnMut(&Asserv<SimHardware>) -> bool) -> u32 {
+    for tick in 0..max_ticks {
+        if done(asserv) {
+            return tick;
+        }
+        step(asserv);
+    }
+    panic!("not done after {max_ticks} updates, position: {:?}", asserv.hardware().position());
+}
+
+/// Run the asserv for given number of updates
+fn run(asserv: &mut Asserv<SimHardware>, ticks: u32) {
+    for _ in 0..ticks {
+        step(asserv);
+    }
+}
+
+#[track_caller]
+fn assert_near(position: &XYA, x: f32, y: f32, tolerance: f32) {
+    let d = (position.xy() - XY::new(x, y)).length();
+    assert!(d < tolerance, "position {position:?} too far from ({x}, {y}): {d}");
+}
+
+
+#[test]
+fn goto_xy() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.goto_xy(600.0, 100.0);
+    assert!(!asserv.idle());
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.idle());
+    // 1 s at 500 mm/s, plus acceleration and deceleration
+    assert!((100..250).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.cs.position(), 600.0, 100.0, 5.0);
+    assert_near(asserv.hardware().position(), 600.0, 100.0, 5.0);
+    assert!(asserv.hardware().position().a.abs() < 0.05);
+}
+
+#[test]
+fn goto_xy_float_pid() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.set_dist_controller_conf(FloatPidConf {
+        kp: 10.0,
+        ki: 1.0,
+        kd: 0.2,
+        d_filter: 0.02,
+        kaw: 10.0,
+        kff_v: 1.0,
+        // Compensate motor time constant
+        kff_a: 0.05,
+        max_out: 1000.0,
+        ..Default::default()
+    }.into());
+    asserv.set_angle_controller_conf(FloatPidConf { kp: 200.0, d_filter: 0.02, ..Default::default() }.into());
+    asserv.goto_xy(600.0, 100.0);
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.idle());
+    assert!((100..250).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.hardware().position(), 600.0, 100.0, 5.0);
+
+    // Feed-forward prevents overshoot, robot stays still
+    run(&mut asserv, 100);
+    assert_near(asserv.hardware().position(), 600.0, 100.0, 2.0);
+    assert!(asserv.hardware().position().a.abs() < 0.05);
+}
+
+#[test]
+fn goto_xy_scurve() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.set_xy_jerk(5000.0);
+    asserv.cs.enable_trace(1000);
+    asserv.goto_xy(600.0, 100.0);
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.idle());
+    // Acceleration and deceleration take 0.2 s more than with a trapezoidal profile
+    assert!((120..270).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.hardware().position(), 600.0, 100.0, 5.0);
+
+    // Ramped distance has a limited jerk: 5000 mm/s³ is 0.005 mm per update³
+    let ramp: Vec<f32> = asserv.cs.trace().unwrap().iter().map(|sample| sample.ramp_dist).collect();
+    let diff = |values: &[f32]| values.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    let accs = diff(&diff(&ramp));
+    assert!(accs.iter().all(|a| a.abs() <= 0.1 + 1e-3), "acceleration above maximum");
+    let jerks = diff(&accs);
+    // f32 precision is limited on distances
+    assert!(jerks.iter().all(|j| j.abs() < 0.01), "jerk above maximum");
+}
+
+/// Run a path until idle, return the minimum speed (mm/s) near each intermediate point
+fn run_path(asserv: &mut Asserv<SimHardware>, path: &[XY]) -> Vec<f32> {
+    asserv.run_path(path);
+    let mut min_speeds = vec![f32::INFINITY; path.len() - 1];
+    let mut previous = asserv.hardware().position().xy();
+    run_until(asserv, 1000, |asserv| {
+        let position = asserv.hardware().position().xy();
+        let speed = (position - previous).length() / PERIOD.as_secs_f32();
+        previous = position;
+        for (point, min_speed) in path.iter().zip(min_speeds.iter_mut()) {
+            if (position - *point).length() < 100.0 {
+                *min_speed = min_speed.min(speed);
+            }
+        }
+        asserv.idle()
+    });
+    min_speeds
+}
+
+#[test]
+fn run_path_through_points() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    let path = [XY::new(500.0, 100.0), XY::new(700.0, 300.0), XY::new(700.0, 700.0)];
+    let min_speeds = run_path(&mut asserv, &path);
+    // Robot does not stop on intermediate points
+    assert!(min_speeds.iter().all(|v| *v > 50.0), "speeds near points: {min_speeds:?}");
+    assert_near(asserv.hardware().position(), 700.0, 700.0, 5.0);
+    assert!(asserv.idle());
+}
+
+#[test]
+fn run_path_lateral_acc() {
+    let path = [XY::new(600.0, 100.0), XY::new(600.0, 600.0)];
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    let fast = run_path(&mut asserv, &path)[0];
+
+    // Robot slows down in the curve
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.set_path_conf(100.0, 200.0);
+    let slow = run_path(&mut asserv, &path)[0];
+    assert!(slow < fast * 0.8, "speed in curve: {slow}, without limit: {fast}");
+    assert!(slow > 30.0, "speed in curve: {slow}");
+    assert_near(asserv.hardware().position(), 600.0, 600.0, 5.0);
+}
+
+#[test]
+fn run_path_empty() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.run_path(&[]);
+    run_until(&mut asserv, 100, |asserv| asserv.idle());
+    assert_near(asserv.hardware().position(), 100.0, 100.0, 1.0);
+}
+
+#[test]
+fn trace() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.cs.enable_trace(1000);
+    asserv.goto_xy(300.0, 100.0);
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.idle());
+
+    let trace = asserv.cs.trace().unwrap();
+    assert_eq!(trace.len(), ticks as usize);
+    let last = trace.iter().last().unwrap();
+    assert_eq!((last.x, last.consign_left), (asserv.cs.position().x, asserv.hardware().consigns()[0]));
+    // Ramped target follows the target
+    assert!(trace.iter().all(|sample| sample.ramp_dist <= sample.target_dist + 0.001));
+
+    let mut csv = Vec::new();
+    trace.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    assert_eq!(csv.lines().count(), ticks as usize + 1);
+    assert!(csv.starts_with("tick,x,y,a,dist,"));
+}
+
+#[test]
+fn goto_a() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.goto_a(FRAC_PI_2);
+    let ticks = run_until(&mut asserv, 300, |asserv| asserv.idle());
+    // 0.3 s to reach max speed, then about 0.25 s at max speed
+    assert!((50..150).contains(&ticks), "ticks: {ticks}");
+    let position = asserv.hardware().position();
+    assert!((position.a - FRAC_PI_2).abs() < 0.03, "angle: {}", position.a);
+    // Robot rotates in place
+    assert_near(position, 100.0, 100.0, 2.0);
+}
+
+#[test]
+fn still_after_reset() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 1.0));
+    run(&mut asserv, 100);
+    let position = asserv.hardware().position();
+    assert_near(position, 100.0, 100.0, 1.0);
+    assert!((position.a - 1.0).abs() < 0.01, "angle: {}", position.a);
+}
+
+#[test]
+fn aim_before_cruise() {
+    let mut asserv = new_asserv(XYA::new(500.0, 500.0, 0.0));
+    // Target is behind the robot
+    asserv.goto_xy(200.0, 600.0);
+    let target_a = (XY::new(200.0, 600.0) - XY::new(500.0, 500.0)).angle();
+
+    // Robot does not move forward while aiming
+    run_until(&mut asserv, 300, |asserv| {
+        let position = asserv.hardware().position();
+        assert_near(position, 500.0, 500.0, 5.0);
+        normalize_radians_pi_pi(position.a - target_a).abs() < 0.05
+    });
+
+    run_until(&mut asserv, 500, |asserv| asserv.idle());
+    assert_near(asserv.hardware().position(), 200.0, 600.0, 5.0);
+}
+
+#[test]
+fn goto_xy_backward() {
+    let mut asserv = new_asserv(XYA::new(500.0, 500.0, 0.0));
+    asserv.goto_xy_dir(200.0, 550.0, MoveDirection::Backward);
+    run_until(&mut asserv, 500, |asserv| {
+        // Robot aims with its rear, without a half turn
+        assert!(asserv.hardware().position().a.abs() < 0.5);
+        asserv.idle()
+    });
+    assert_near(asserv.hardware().position(), 200.0, 550.0, 5.0);
+    let target_a = (XY::new(500.0, 500.0) - XY::new(200.0, 550.0)).angle();
+    assert!((asserv.hardware().position().a - target_a).abs() < 0.05);
+}
+
+#[test]
+fn goto_xy_shortest() {
+    // Target behind the robot: drive backward
+    let mut asserv = new_asserv(XYA::new(500.0, 500.0, 0.0));
+    asserv.goto_xy_dir(200.0, 400.0, MoveDirection::Shortest);
+    run_until(&mut asserv, 500, |asserv| asserv.idle());
+    assert_near(asserv.hardware().position(), 200.0, 400.0, 5.0);
+    assert!(asserv.hardware().position().a.abs() < FRAC_PI_2);
+
+    // Target in front of the robot: drive forward
+    let dist = asserv.cs.dist();
+    asserv.goto_xy_dir(500.0, 450.0, MoveDirection::Shortest);
+    run_until(&mut asserv, 500, |asserv| {
+        assert!(asserv.hardware().position().a.abs() < FRAC_PI_2);
+        asserv.idle()
+    });
+    assert_near(asserv.hardware().position(), 500.0, 450.0, 5.0);
+    assert!(asserv.cs.dist() > dist + 250.0);
+}
+
+#[test]
+fn aim_again_when_pushed() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.goto_xy(1000.0, 100.0);
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().x > 400.0);
+
+    // Turn the robot by more than the cruise angle window
+    let wheel_distance = SimConf::default().wheel_distance;
+    asserv.hardware_mut().push([wheel_distance * 0.6, -wheel_distance * 0.6]);
+    assert!(asserv.hardware().position().a < -1.1);
+    let pushed = asserv.hardware().position().xy();
+
+    // Robot decelerates (125 mm at full speed), then aims again
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().a.abs() < 0.05);
+    let d = (asserv.hardware().position().xy() - pushed).length();
+    assert!(d < 250.0, "distance while aiming again: {d}");
+
+    run_until(&mut asserv, 500, |asserv| asserv.idle());
+    assert_near(asserv.hardware().position(), 1000.0, 100.0, 10.0);
+}
+
+#[test]
+fn stop_until_idle() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.goto_xy(400.0, 100.0);
+
+    // Order is not idle until the robot is stopped
+    let mut ticks_in_window = 0;
+    run_until(&mut asserv, 500, |asserv| {
+        if asserv.idle() {
+            return true;
+        }
+        if (asserv.cs.position().xy() - XY::new(400.0, 100.0)).length() < 5.0 {
+            ticks_in_window += 1;
+        }
+        false
+    });
+    assert!(ticks_in_window > 0);
+    // Below one encoder tick per update
+    let speeds = asserv.hardware().wheel_speeds();
+    assert!(speeds.iter().all(|v| v.abs() < 40.0), "wheel speeds: {speeds:?}");
+
+    // Robot stays still
+    let position = *asserv.hardware().position();
+    run(&mut asserv, 100);
+    assert!(asserv.idle());
+    assert_near(asserv.hardware().position(), position.x, position.y, 1.0);
+}
+
+#[test]
+fn autoset() {
+    let sim_conf = SimConf { table: Some(Table::EUROBOT), robot_radius: 50.0, ..Default::default() };
+    let mut asserv = new_asserv_with_conf(sim_conf, XYA::new(800.0, 300.0, 1.0));
+    // Position known by the asserv is wrong
+    asserv.reset_position(XYA::new(820.0, 330.0, 1.0));
+
+    // Push the back of the robot against the bottom wall
+    asserv.autoset(RobotSide::Back, TableSide::Down, XY::new(0.0, 50.0));
+    assert!(asserv.autoset_in_progress());
+    let mut blocked = false;
+    let ticks = run_until(&mut asserv, 1000, |asserv| {
+        blocked |= asserv.hardware().is_blocked();
+        !asserv.autoset_in_progress()
+    });
+    assert!(blocked);
+    // 250 mm at 100 mm/s
+    assert!((250..500).contains(&ticks), "ticks: {ticks}");
+    run_until(&mut asserv, 100, |asserv| asserv.idle());
+
+    // Y coordinate and angle are reset, then robot moves away from the wall
+    let actual = *asserv.hardware().position();
+    let position = *asserv.cs.position();
+    assert!((actual.y - 100.0).abs() < 5.0, "actual: {actual:?}");
+    assert!((position.y - actual.y).abs() < 5.0, "position: {position:?}, actual: {actual:?}");
+    assert!((position.x - actual.x - 20.0).abs() < 5.0, "position: {position:?}, actual: {actual:?}");
+    assert!((position.a - FRAC_PI_2).abs() < 0.03, "angle: {}", position.a);
+}
+
+#[test]
+fn autoset_timeout() {
+    // No wall: robot never gets blocked
+    let mut asserv = new_asserv(XYA::new(800.0, 300.0, 1.0));
+    asserv.set_autoset_timeout(1.0);
+    asserv.autoset(RobotSide::Back, TableSide::Down, XY::new(0.0, 50.0));
+    let ticks = run_until(&mut asserv, 1000, |asserv| !asserv.autoset_in_progress());
+    // Aiming, then 1 s at 100 mm/s
+    assert!((100..300).contains(&ticks), "ticks: {ticks}");
+    run_until(&mut asserv, 100, |asserv| asserv.idle());
+
+    // Robot is stopped, position is not reset
+    let actual = *asserv.hardware().position();
+    let position = *asserv.cs.position();
+    assert!((actual.y - 200.0).abs() < 20.0, "actual: {actual:?}");
+    assert_near(&position, actual.x, actual.y, 5.0);
+    run(&mut asserv, 50);
+    assert_near(asserv.hardware().position(), actual.x, actual.y, 2.0);
+}
+
+#[test]
+fn autoset_low_acceleration() {
+    // No wall, robot lags behind the target while accelerating slowly
+    let mut asserv = new_asserv(XYA::new(800.0, 1100.0, 1.0));
+    asserv.set_xy_speed(500.0, 100.0);
+    asserv.set_autoset_timeout(3.0);
+    asserv.autoset(RobotSide::Back, TableSide::Down, XY::new(0.0, 50.0));
+    let ticks = run_until(&mut asserv, 1000, |asserv| !asserv.autoset_in_progress());
+    // Aborted on timeout, not blocked
+    assert!(ticks >= 300, "ticks: {ticks}");
+    run_until(&mut asserv, 100, |asserv| asserv.idle());
+
+    // Position is not reset
+    let actual = *asserv.hardware().position();
+    let position = *asserv.cs.position();
+    assert!(actual.y < 900.0, "actual: {actual:?}");
+    assert_near(&position, actual.x, actual.y, 5.0);
+}
+
+#[test]
+fn emergency_stop() {
+    let mut asserv = new_asserv(XYA::new(100.0, 100.0, 0.0));
+    asserv.goto_xy(1000.0, 100.0);
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().x > 400.0);
+
+    // Order is aborted, motors are stopped
+    asserv.hardware_mut().set_emergency_stop(true);
+    step(&mut asserv);
+    assert!(asserv.idle());
+    assert_eq!(asserv.hardware().consigns(), [0.0, 0.0]);
+    run(&mut asserv, 50);
+    assert!(asserv.hardware().wheel_speeds().iter().all(|v| v.abs() < 1.0));
+    let position = *asserv.hardware().position();
+    assert!(position.x < 1000.0 - 100.0);
+
+    // Robot does not resume its move
+    asserv.hardware_mut().set_emergency_stop(false);
+    run(&mut asserv, 100);
+    assert!(asserv.idle());
+    assert_near(asserv.hardware().position(), position.x, position.y, 2.0);
+    assert!((asserv.hardware().position().a - position.a).abs() < 0.01);
+}
+
@@ -0,0 +1,275 @@
+//! Closed-loop tests of the holonomic asserv, using simulated hardware
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+use std::time::Duration;
+use asserv::maths::{XY, XYA};
+use asserv::holonomic::{Asserv, RobotSide, TableSide, Waypoint};
+use asserv::holonomic::conf::{AsservConf, MotorsConf, PidConf, TrajectoryConf};
+use asserv::holonomic::sim::{SimConf, SimHardware};
+use asserv::sim::Table;
+
+const PERIOD: Duration = Duration::from_millis(10);
+
+
+/// Motors configuration of Galipeur
+#[allow(clippy::excessive_precision)]
+fn motors_conf() -> MotorsConf {
+    MotorsConf {
+        velocities_to_consigns: [
+            0.137193775559,     -0.227742535811,    32.7587578324,
+            -0.267514745628,    0.000225842067981,  32.2910980339,
+            0.138273262887,     0.235015679279,     32.2670974911,
+        ],
+        encoders_to_position: [
+            -1.24627114282,     2.4735001584,       -1.21007913871,
+            2.15287736186,      -0.0169008404017,   -2.16876778164,
+            -0.0103397573436,   -0.010476522571,    -0.0100097003094,
+        ],
+    }
+}
+
+fn asserv_conf() -> AsservConf {
+    // Output velocity is a quarter of the error, per update
+    let pid = PidConf { gain_p: 1, out_shift: 2, ..Default::default() };
+    AsservConf {
+        pid_x: pid.clone().into(),
+        pid_y: pid.clone().into(),
+        pid_a: pid.into(),
+        trajectory: TrajectoryConf {
+            a_speed: 5.0,
+            a_acc: 20.0,
+            xy_cruise_speed: 1000.0,
+            xy_cruise_acc: 5000.0,
+            xy_jerk: 0.0,
+            xy_steering_speed: 500.0,
+            xy_steering_acc: 5000.0,
+            xy_stop_speed: 100.0,
+            xy_stop_acc: 5000.0,
+            xy_steering_window: 30.0,
+            xy_stop_window: 3.0,
+            a_stop_window: 0.02,
+            autoset_speed: 500.0,
+            autoset_wait: Duration::from_millis(200),
+            autoset_duration: Duration::from_millis(1000),
+        },
+        motors: motors_conf(),
+    }
+}
+
+fn sim_conf() -> SimConf {
+    SimConf {
+        motors: motors_conf(),
+        encoder_noise: 1.0,
+        gyro_noise: 1e-4,
+        robot_radius: 150.0,
+        ..Default::default()
+    }
+}
+
+/// Create an asserv, with an initial position known by both the asserv and the simulation
+fn new_asserv(conf: SimConf, position: XYA) -> Asserv<SimHardware> {
+    new_asserv_with_period(conf, position, PERIOD)
+}
+
+/// Same as [new_asserv()], with a given update period
+fn new_asserv_with_period(conf: SimConf, position: XYA, period: Duration) -> Asserv<SimHardware> {
+    let mut asserv = Asserv::new(SimHardware::new(conf, position), period);
+    asserv.set_conf(asserv_conf());
+    asserv.reset_position(position);
+    asserv
+}
+
+/// Run the asserv until `done` returns true, return the number of updates
+///
+/// Panic if `done` is still false after `max_ticks` updates.
+fn run_until(asserv: &mut Asserv<SimHardware>, max_ticks: u32, done: impl FnMut(&Asserv<SimHardware>) -> bool) -> u32 {
+    run_until_with_period(asserv, PERIOD, max_ticks, done)
+}
+
+/// Same as [run_until()], with a given update period
+fn run_until_with_period(asserv: &mut Asserv<SimHardware>, period: Duration, max_ticks: u32, mut done: impl FnMut(&Asserv<SimHardware>) -> bool) -> u32 {
+    for tick in 0..max_ticks {
+        if done(asserv) {
+            return tick;
+        }
+        asserv.hardware_mut().step();
+        asserv.update(&period);
+    }
+    panic!("not done after {max_ticks} updates, position: {:?}", asserv.hardware().position());
+}
+
+/// Run the asserv for given number of updates
+fn run(asserv: &mut Asserv<SimHardware>, ticks: u32) {
+    for _ in 0..ticks {
+        asserv.hardware_mut().step();
+        asserv.update(&PERIOD);
+    }
+}
+
+#[track_caller]
+fn assert_near(position: &XYA, x: f32, y: f32, tolerance: f32) {
+    let d = (position.xy() - XY::new(x, y)).length();
+    assert!(d < tolerance, "position {position:?} too far from ({x}, {y}): {d}");
+}
+
+
+#[test]
+fn goto_xy() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    asserv.goto_xy(1500.0, 1000.0);
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.done_xy());
+    // Distance is about 1118 mm, cruise speed is 1000 mm/s (10 mm per update)
+    assert!((112..250).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.cs.position(), 1500.0, 1000.0, 3.0);
+    assert_near(asserv.hardware().position(), 1500.0, 1000.0, 5.0);
+
+    // Robot stabilizes on target, angle is kept
+    run(&mut asserv, 100);
+    assert_near(asserv.hardware().position(), 1500.0, 1000.0, 2.0);
+    assert!(asserv.hardware().position().a.abs() < 0.02);
+}
+
+#[test]
+fn goto_xy_scurve() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    asserv.set_xy_jerk(50000.0);
+    asserv.cs.enable_trace(1000);
+    asserv.goto_xy(1500.0, 500.0);
+    let ticks = run_until(&mut asserv, 500, |asserv| asserv.done_xy());
+    assert!((100..250).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.hardware().position(), 1500.0, 500.0, 5.0);
+
+    // Carrot acceleration changes progressively
+    // Ignore the last step to the target point, which is truncated
+    let carrot: Vec<f32> = asserv.cs.trace().unwrap().iter()
+        .map(|sample| sample.carrot_x)
+        .take_while(|x| *x < 1500.0)
+        .collect();
+    let diff = |values: &[f32]| values.windows(2).map(|w| w[1] - w[0]).collect::<Vec<_>>();
+    let accs = diff(&diff(&carrot));
+    assert!(accs.iter().all(|a| a.abs() <= 0.5 + 1e-3), "acceleration above maximum");
+    let jerks = diff(&accs);
+    assert!(jerks.iter().all(|j| j.abs() <= 0.05 + 1e-3), "jerk above maximum: {jerks:?}");
+}
+
+#[test]
+fn goto_xy_update_period() {
+    // Movement duration does not depend on the update period
+    let mut asserv = new_asserv_with_period(sim_conf(), XYA::new(500.0, 500.0, 0.0), PERIOD / 2);
+    asserv.goto_xy(1500.0, 1000.0);
+    let ticks = run_until_with_period(&mut asserv, PERIOD / 2, 1000, |asserv| asserv.done_xy());
+    assert!((224..500).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.hardware().position(), 1500.0, 1000.0, 5.0);
+}
+
+#[test]
+fn goto_a() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    asserv.goto_a(FRAC_PI_2);
+    let ticks = run_until(&mut asserv, 300, |asserv| asserv.done_a());
+    // Angular speed is 5 rad/s (0.05 rad per update), acceleration is 20 rad/s² (0.002 rad per update²)
+    assert!((31..150).contains(&ticks), "ticks: {ticks}");
+    assert!((asserv.hardware().position().a - FRAC_PI_2).abs() < 0.03);
+    // Robot does not move while rotating
+    assert_near(asserv.hardware().position(), 500.0, 500.0, 2.0);
+}
+
+#[test]
+fn run_path() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    let path = [XY::new(1500.0, 500.0), XY::new(1500.0, 1500.0), XY::new(500.0, 1500.0)];
+    asserv.run_path(&path);
+
+    // Record the minimal distance to each intermediate point
+    let mut min_distances = [f32::MAX; 2];
+    let ticks = run_until(&mut asserv, 1000, |asserv| {
+        let xy = asserv.hardware().position().xy();
+        for (d, point) in min_distances.iter_mut().zip(&path) {
+            *d = d.min((xy - *point).length());
+        }
+        asserv.done_xy()
+    });
+    for d in min_distances {
+        // Steering window, plus some margin for the carrot lag
+        assert!(d < 60.0, "intermediate point missed by {d}");
+    }
+    // Path length is 3000 mm
+    assert!((300..600).contains(&ticks), "ticks: {ticks}");
+    assert_near(asserv.hardware().position(), 500.0, 1500.0, 5.0);
+}
+
+#[test]
+fn run_path_headings() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    asserv.cs.enable_trace(1000);
+    let path = [
+        Waypoint::new(1500.0, 500.0, None),
+        XYA::new(1500.0, 1500.0, FRAC_PI_2).into(),
+        XYA::new(500.0, 1500.0, PI).into(),
+    ];
+    asserv.run_path_xya(&path);
+
+    // Angle follows path length, between points with a heading
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().x >= 1000.0);
+    let a = asserv.hardware().position().a;
+    assert!((a - PI / 8.0).abs() < 0.15, "angle at a quarter of the first heading: {a}");
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().y >= 1000.0);
+    let a = asserv.hardware().position().a;
+    assert!((a - 3.0 * PI / 8.0).abs() < 0.15, "angle at 3/4 of the first heading: {a}");
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().x <= 1000.0);
+    let a = asserv.hardware().position().a;
+    assert!((a - 3.0 * FRAC_PI_4).abs() < 0.15, "angle at half of the second heading: {a}");
+
+    run_until(&mut asserv, 300, |asserv| asserv.done_xy() && asserv.done_a());
+    let position = asserv.hardware().position();
+    assert_near(position, 500.0, 1500.0, 5.0);
+    assert!((position.a - PI).abs() < 0.03, "final angle: {}", position.a);
+
+    // Angle target does not jump when the robot passes a point
+    let targets: Vec<f32> = asserv.cs.trace().unwrap().iter().map(|sample| sample.target_a).collect();
+    let max_step = targets.windows(2).map(|w| (w[1] - w[0]).abs()).fold(0.0, f32::max);
+    assert!(max_step < 0.025, "angle target step: {max_step}");
+}
+
+#[test]
+fn goto_xy_synced() {
+    let mut asserv = new_asserv(sim_conf(), XYA::new(500.0, 500.0, 0.0));
+    asserv.goto_xy(1500.0, 500.0);
+    asserv.goto_xy_synced(1500.0, 500.0, FRAC_PI_2);
+
+    // Angle follows linear progress
+    run_until(&mut asserv, 300, |asserv| asserv.hardware().position().x >= 1000.0);
+    let a = asserv.hardware().position().a;
+    assert!((a - FRAC_PI_4).abs() < 0.15, "angle at half the path: {a}");
+
+    run_until(&mut asserv, 300, |asserv| asserv.done_xy() && asserv.done_a());
+    let position = asserv.hardware().position();
+    assert_near(position, 1500.0, 500.0, 5.0);
+    assert!((position.a - FRAC_PI_2).abs() < 0.03, "final angle: {}", position.a);
+}
+
+#[test]
+fn autoset() {
+    let conf = SimConf { table: Some(Table::EUROBOT), ..sim_conf() };
+    let mut asserv = new_asserv(conf, XYA::new(800.0, 400.0, 0.0));
+    // Position known by the asserv is wrong
+    asserv.reset_position(XYA::new(820.0, 430.0, 0.0));
+
+    // Push the back of the robot against the bottom wall
+    asserv.autoset(RobotSide::Back, TableSide::Down, XY::new(0.0, 150.0));
+    let ticks = run_until(&mut asserv, 500, |asserv| !asserv.autoset_in_progress());
+    assert!((140..300).contains(&ticks), "ticks: {ticks}");
+    assert!(asserv.hardware().is_blocked());
+
+    // Y coordinate is reset, X coordinate is unchanged
+    let actual = *asserv.hardware().position();
+    let position = *asserv.cs.position();
+    assert_eq!(position.y, 150.0);
+    assert!((position.x - actual.x - 20.0).abs() < 2.0, "position: {position:?}, actual: {actual:?}");
+    assert!(position.a.abs() < 0.02);
+
+    // Asserv is enabled again, robot can move away
+    asserv.goto_xy(position.x, 500.0);
+    run_until(&mut asserv, 300, |asserv| asserv.done_xy());
+    assert!((asserv.hardware().position().y - 500.0).abs() < 5.0);
+}
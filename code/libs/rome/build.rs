@@ -1,127 +1,23 @@
 use std::fs::File;
-use std::io::{BufWriter, Read, Write};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
-use std::collections::{HashMap, HashSet};
-use yaml_rust::{YamlLoader, Yaml};
 
-
-enum ParamType<'a> {
-    Name(&'a str),
-    Choice(Vec<&'a str>),
-}
-
-enum Parameters<'a> {
-    None,
-    Positional(Vec<ParamType<'a>>),
-    Named(Vec<(&'a str, ParamType<'a>)>),
-}
-
-struct Message<'a> {
-    id: u8,
-    name: &'a str,
-    parameters: Parameters<'a>,
-}
+mod schema;
+use schema::*;
 
 
 fn main() {
     let yaml_file = "rome_messages.yaml";
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=schema.rs");
     println!("cargo:rerun-if-changed={}", yaml_file);
 
     let out_dir = PathBuf::from(std::env::var("OUT_DIR").unwrap());
     let out_path = out_dir.join("rome_messages.rs");
 
     let doc = load_yaml_doc(yaml_file);
-    let messages = parse_message_doc(&doc);
-    generate_bindings(&messages, out_path);
-}
-
-
-fn parse_message_doc(doc: &Yaml) -> Vec<Message<'_>> {
-    let hash = if let Yaml::Hash(hash) = doc {
-        hash
-    } else {
-        panic!("Invalid document: top level element must be an object");
-    };
-
-
-    let mut messages = Vec::new();
-    let mut ids_in_use = HashMap::new();
-    let mut names_in_use = HashSet::new();
-    for (group_id, items) in hash {
-        let mut current_id = if let Yaml::Integer(id) = group_id {
-            if *id <= 0 || *id > u8::MAX as i64 {
-                panic!("Invalid message group ID: value must be a valid non-zero 8-bit value, got {id:?}");
-            }
-            *id as u8
-        } else {
-            panic!("Invalid message group ID: key must be an integer, got {group_id:?}");
-        };
-        let items = if let Yaml::Hash(hash) = items {
-            hash
-        } else {
-            panic!("Invalid message group: value must an object");
-        };
-
-        for (message_name, parameters_decl) in items {
-            let message_name = if let Yaml::String(name) = message_name {
-                name.as_str()
-            } else {
-                panic!("Invalid message name: must be a string, got {message_name:?}");
-            };
-            if let Some(old_name) = ids_in_use.insert(current_id, message_name) {
-                panic!("Duplicate message ID {current_id}, used by {old_name} and {message_name}");
-            }
-            if !names_in_use.insert(message_name) {
-                panic!("Duplicate message name: {message_name}");
-            }
-            let parameters = match parameters_decl {
-                Yaml::Null => {
-                    Parameters::None
-                }
-                Yaml::Array(items) => {
-                    let items = items.iter().map(parse_type_name).collect();
-                    Parameters::Positional(items)
-                }
-                Yaml::Hash(items) => {
-                    // Note: assume YAML is correct and there is no duplicate parameter name
-                    let items = items.iter().map(|(name, value)| {
-                        let param_name = if let Yaml::String(name) = name {
-                            name.as_str()
-                        } else {
-                            panic!("Invalid parameter name: must be a string, got {name:?}");
-                        };
-                        let param_type = parse_type_name(value);
-                        (param_name, param_type)
-                    }).collect();
-                    Parameters::Named(items)
-                }
-                _ => panic!("Invalid messsage declaration: value must be an array or object"),
-            };
-            messages.push(Message {
-                id: current_id,
-                name: message_name,
-                parameters,
-            });
-            current_id += 1;
-        }
-    }
-
-    messages
-}
-
-fn parse_type_name(yaml: &Yaml) -> ParamType<'_> {
-    match yaml {
-        Yaml::String(name) => ParamType::Name(name.as_str()),
-        Yaml::Array(items) => ParamType::Choice(items.iter().map(|v| {
-            if let Yaml::String(s) = v {
-                s.as_str()
-            } else {
-                panic!("Invalid choice value: must be a string, got {v:?}")
-            }
-        }).collect()),
-        _ => panic!("Invalid parameter type: {yaml:?}"),
-    }
+    let (types, messages) = parse_message_doc(&doc);
+    generate_bindings(&types, &messages, out_path);
 }
 
 
@@ -142,14 +38,15 @@ fn capitalize(s: &str) -> String {
 }
 
 
-fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
+fn generate_bindings<P: AsRef<Path>>(types: &[TypeDecl], messages: &[Message], path: P) {
     let f = File::create(path).unwrap();
     let mut writer = BufWriter::new(f);
 
     writeln!(writer, "// This file is generated").unwrap();
-    writeln!(writer, "use crate::DecodeError;").unwrap();
+    writeln!(writer, "use crate::{{DecodeError, EncodeError, Handled}};").unwrap();
     writeln!(writer, "use crate::deserialize::{{Deserialize, Reader}};").unwrap();
     writeln!(writer, "use crate::serialize::{{Serialize, Writer}};").unwrap();
+    writeln!(writer, "use crate::text::{{ParseError, TextParser, TextValue}};").unwrap();
     writeln!(writer, "\n").unwrap();
 
     // #[derive(Debug)]
@@ -171,14 +68,14 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
             Parameters::Positional(params) => {
                 let values = params.iter().enumerate().map(|(i, typ)| {
                     let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
-                    format_rust_type(typ, message.name, suffix)
+                    format_rust_type(typ, message.name, suffix, types)
                 }).collect::<Vec<_>>().join(", ");
                 writeln!(writer, "    {}({}),", message.name, values).unwrap();
             }
             Parameters::Named(params) => {
                 writeln!(writer, "    {} {{", message.name).unwrap();
                 for (name, typ) in params {
-                    let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name));
+                    let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name), types);
                     writeln!(writer, "        {name}: {type_name},").unwrap();
                 }
                 writeln!(writer, "    }},").unwrap();
@@ -187,12 +84,27 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     }
     writeln!(writer, "}}\n").unwrap();
 
+    // #[derive(Clone, Copy, Debug, PartialEq, Eq)]
     // #[repr(u8)]
     // pub enum MessageId {
     //     Empty = 20
     //     SomeValues = 21,
     //     Coordinates = 22,
     // }
+    //
+    // impl TryFrom<u8> for MessageId {
+    //     type Error = DecodeError;
+    //
+    //     fn try_from(value: u8) -> Result<Self, DecodeError> {
+    //         match value {
+    //             20 => Ok(Self::Empty),
+    //             21 => Ok(Self::SomeValues),
+    //             22 => Ok(Self::Coordinates),
+    //             id => Err(DecodeError::UnknownMessage(id)),
+    //         }
+    //     }
+    // }
+    writeln!(writer, "#[derive(Clone, Copy, Debug, PartialEq, Eq)]").unwrap();
     writeln!(writer, "#[repr(u8)]").unwrap();
     writeln!(writer, "pub enum MessageId {{").unwrap();
     for message in messages {
@@ -200,6 +112,18 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     }
     writeln!(writer, "}}\n").unwrap();
 
+    writeln!(writer, "impl TryFrom<u8> for MessageId {{").unwrap();
+    writeln!(writer, "    type Error = DecodeError;\n").unwrap();
+    writeln!(writer, "    fn try_from(value: u8) -> Result<Self, DecodeError> {{").unwrap();
+    writeln!(writer, "        match value {{").unwrap();
+    for message in messages {
+        writeln!(writer, "            {} => Ok(Self::{}),", message.id, message.name).unwrap();
+    }
+    writeln!(writer, "            id => Err(DecodeError::UnknownMessage(id)),").unwrap();
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
     // impl Message {
     //     pub(crate) fn deserialize_with_id<R: Reader>(id: u8, reader: &mut R) -> Result<Self, DecodeError> {
     //         match id {
@@ -224,6 +148,9 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     //         }
     //     }
     // }
+    //
+    // Optional parameters use their default value when there is no data left:
+    //   `z: if reader.is_at_end() { 0.0 } else { f32::deserialize(reader)? }`
     writeln!(writer, "impl Message {{").unwrap();
     writeln!(writer, "    pub(crate) fn deserialize_with_id<R: Reader>(id: u8, reader: &mut R) -> Result<Self, DecodeError> {{").unwrap();
     writeln!(writer, "        match id {{").unwrap();
@@ -236,16 +163,16 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
                 writeln!(writer, "            {} => Ok(Self::{}(", message.id, message.name).unwrap();
                 for (i, typ) in params.iter().enumerate() {
                     let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
-                    let type_name = format_rust_type(typ, message.name, suffix);
-                    writeln!(writer, "                {}::deserialize(reader)?,", enclosed_rust_type(&type_name)).unwrap();
+                    let type_name = format_rust_type(typ, message.name, suffix, types);
+                    writeln!(writer, "                {},", deserialize_param_expr(message, i, &type_name)).unwrap();
                 }
                 writeln!(writer, "            )),").unwrap();
             }
             Parameters::Named(params) => {
                 writeln!(writer, "            {} => Ok(Self::{} {{", message.id, message.name).unwrap();
-                for (name, typ) in params {
-                    let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name));
-                    writeln!(writer, "                {}: {}::deserialize(reader)?,", name, enclosed_rust_type(&type_name)).unwrap();
+                for (i, (name, typ)) in params.iter().enumerate() {
+                    let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name), types);
+                    writeln!(writer, "                {}: {},", name, deserialize_param_expr(message, i, &type_name)).unwrap();
                 }
                 writeln!(writer, "            }}),").unwrap();
             }
@@ -278,22 +205,23 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     //         }) + 1
     //     }
     //
-    //     fn serialize<W: Writer>(&self, writer: &mut W) {
+    //     fn serialize<W: Writer>(&self, writer: &mut W) -> Result<(), EncodeError> {
     //         match self {
     //             Self::Empty => {
-    //                 20u8.serialize(writer);
+    //                 20u8.serialize(writer)?;
     //             },
     //             Self::SomeValues(v0, v1) => {
-    //                 21u8.serialize(writer);
-    //                 v0.serialize(writer);
-    //                 v1.serialize(writer);
+    //                 21u8.serialize(writer)?;
+    //                 v0.serialize(writer)?;
+    //                 v1.serialize(writer)?;
     //             }
     //             Self::Coordinates { x, y } => {
-    //                 22u8.serialize(writer);
-    //                 x.serialize(writer);
-    //                 y.serialize(writer);
+    //                 22u8.serialize(writer)?;
+    //                 x.serialize(writer)?;
+    //                 y.serialize(writer)?;
     //             }
     //         }
+    //         Ok(())
     //     }
     // }
     writeln!(writer, "impl Serialize for Message {{").unwrap();
@@ -323,29 +251,333 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     writeln!(writer, "        }}) + 1").unwrap();
     writeln!(writer, "    }}\n").unwrap();
 
-    writeln!(writer, r#"    fn serialize<W: Writer>(&self, writer: &mut W) {{"#).unwrap();
+    writeln!(writer, r#"    fn serialize<W: Writer>(&self, writer: &mut W) -> Result<(), EncodeError> {{"#).unwrap();
     writeln!(writer, r#"        match self {{"#).unwrap();
     for message in messages {
         writeln!(writer, "            {} => {{", destructured_parameters(message)).unwrap();
-        writeln!(writer, "                {}u8.serialize(writer);", message.id).unwrap();
+        writeln!(writer, "                {}u8.serialize(writer)?;", message.id).unwrap();
         match &message.parameters {
             Parameters::None => {}
             Parameters::Positional(params) => {
                 for i in 0..params.len() {
-                    writeln!(writer, "                v{i}.serialize(writer);").unwrap();
+                    writeln!(writer, "                v{i}.serialize(writer)?;").unwrap();
                 }
             }
             Parameters::Named(params) => {
                 for (name, _) in params {
-                    writeln!(writer, "                {name}.serialize(writer);").unwrap();
+                    writeln!(writer, "                {name}.serialize(writer)?;").unwrap();
                 }
             }
         }
         writeln!(writer, "            }}").unwrap();
     }
     writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "        Ok(())").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    // /// Handler of ROME messages, with one method per message
+    // ///
+    // /// All methods do nothing and return [Handled::No] by default.
+    // #[allow(unused_variables, clippy::too_many_arguments)]
+    // pub trait RomeHandler {
+    //     fn on_empty(&mut self) -> Handled { Handled::No }
+    //     fn on_some_values(&mut self, v0: [u16; 3], v1: params::SomeValuesParam1) -> Handled { Handled::No }
+    //     fn on_coordinates(&mut self, x: f32, y: f32) -> Handled { Handled::No }
+    // }
+    //
+    // impl Message {
+    //     /// Call the handler method associated to the message
+    //     pub fn dispatch_to<H: RomeHandler + ?Sized>(&self, handler: &mut H) -> Handled {
+    //         match self {
+    //             Self::Empty => handler.on_empty(),
+    //             Self::SomeValues(v0, v1) => handler.on_some_values(*v0, *v1),
+    //             Self::Coordinates { x, y } => handler.on_coordinates(*x, *y),
+    //         }
+    //     }
+    // }
+    writeln!(writer, "/// Handler of ROME messages, with one method per message").unwrap();
+    writeln!(writer, "///").unwrap();
+    writeln!(writer, "/// All methods do nothing and return [Handled::No] by default.").unwrap();
+    writeln!(writer, "/// Parameters are passed by value, except for strings and bounded arrays.").unwrap();
+    writeln!(writer, "#[allow(unused_variables, clippy::too_many_arguments)]").unwrap();
+    writeln!(writer, "pub trait RomeHandler {{").unwrap();
+    for message in messages {
+        let args = handler_arguments(message, types).iter()
+            .map(|(name, typ, is_copy)| format!(", {name}: {}{typ}", if *is_copy { "" } else { "&" }))
+            .collect::<String>();
+        writeln!(writer, "    fn {}(&mut self{args}) -> Handled {{ Handled::No }}", handler_method_name(message.name)).unwrap();
+    }
+    writeln!(writer, "}}\n").unwrap();
+
+    writeln!(writer, "impl Message {{").unwrap();
+    writeln!(writer, "    /// Call the handler method associated to the message").unwrap();
+    writeln!(writer, "    pub fn dispatch_to<H: RomeHandler + ?Sized>(&self, handler: &mut H) -> Handled {{").unwrap();
+    writeln!(writer, "        match self {{").unwrap();
+    for message in messages {
+        let args = handler_arguments(message, types).iter()
+            .map(|(name, _, is_copy)| format!("{}{name}", if *is_copy { "*" } else { "" }))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(writer, "            {} => handler.{}({args}),", destructured_parameters(message), handler_method_name(message.name)).unwrap();
+    }
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    // impl Message {
+    //     /// Return the name of the message
+    //     pub fn name(&self) -> &'static str {
+    //         match self {
+    //             Self::Empty => "Empty",
+    //             Self::SomeValues(_, _) => "SomeValues",
+    //             Self::Coordinates { .. } => "Coordinates",
+    //         }
+    //     }
+    // }
+    //
+    // impl core::fmt::Display for Message {
+    //     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    //         f.write_str(self.name())?;
+    //         match self {
+    //             Self::Empty => {}
+    //             Self::SomeValues(v0, v1) => {
+    //                 f.write_str(" ")?;
+    //                 v0.fmt_text(f)?;
+    //                 f.write_str(" ")?;
+    //                 v1.fmt_text(f)?;
+    //             }
+    //             Self::Coordinates { x, y } => {
+    //                 f.write_str(" x=")?;
+    //                 x.fmt_text(f)?;
+    //                 f.write_str(" y=")?;
+    //                 y.fmt_text(f)?;
+    //             }
+    //         }
+    //         Ok(())
+    //     }
+    // }
+    //
+    // impl core::str::FromStr for Message {
+    //     type Err = ParseError;
+    //
+    //     fn from_str(s: &str) -> Result<Self, ParseError> {
+    //         let mut parser = TextParser::new(s);
+    //         let message = match parser.word()?.0 {
+    //             "Empty" => Self::Empty,
+    //             "SomeValues" => Self::SomeValues(parser.positional()?, parser.positional()?),
+    //             "Coordinates" => Self::Coordinates { x: parser.named("x")?, y: parser.named("y")? },
+    //             _ => return Err(ParseError::UnknownMessage),
+    //         };
+    //         parser.end()?;
+    //         Ok(message)
+    //     }
+    // }
+    writeln!(writer, "impl Message {{").unwrap();
+    writeln!(writer, "    /// Return the name of the message").unwrap();
+    writeln!(writer, "    pub fn name(&self) -> &'static str {{").unwrap();
+    writeln!(writer, "        match self {{").unwrap();
+    for message in messages {
+        writeln!(writer, "            {} => \"{}\",", destructured_parameters_ignored(message), message.name).unwrap();
+    }
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    writeln!(writer, "impl core::fmt::Display for Message {{").unwrap();
+    writeln!(writer, "    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {{").unwrap();
+    writeln!(writer, "        f.write_str(self.name())?;").unwrap();
+    writeln!(writer, "        match self {{").unwrap();
+    for message in messages {
+        match &message.parameters {
+            Parameters::None => {
+                writeln!(writer, "            {} => {{}}", destructured_parameters(message)).unwrap();
+            }
+            Parameters::Positional(params) => {
+                writeln!(writer, "            {} => {{", destructured_parameters(message)).unwrap();
+                for i in 0..params.len() {
+                    writeln!(writer, "                f.write_str(\" \")?;").unwrap();
+                    writeln!(writer, "                v{i}.fmt_text(f)?;").unwrap();
+                }
+                writeln!(writer, "            }}").unwrap();
+            }
+            Parameters::Named(params) => {
+                writeln!(writer, "            {} => {{", destructured_parameters(message)).unwrap();
+                for (name, _) in params {
+                    writeln!(writer, "                f.write_str(\" {name}=\")?;").unwrap();
+                    writeln!(writer, "                {name}.fmt_text(f)?;").unwrap();
+                }
+                writeln!(writer, "            }}").unwrap();
+            }
+        }
+    }
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "        Ok(())").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    // Optional parameters can be omitted: `parser.named_or("z", 0.0)?`
+    writeln!(writer, "impl core::str::FromStr for Message {{").unwrap();
+    writeln!(writer, "    type Err = ParseError;\n").unwrap();
+    writeln!(writer, "    fn from_str(s: &str) -> Result<Self, ParseError> {{").unwrap();
+    writeln!(writer, "        let mut parser = TextParser::new(s);").unwrap();
+    writeln!(writer, "        let message = match parser.word()?.0 {{").unwrap();
+    for message in messages {
+        let value = match &message.parameters {
+            Parameters::None => format!("Self::{}", message.name),
+            Parameters::Positional(params) => {
+                let values = params.iter().enumerate().map(|(i, typ)| {
+                    let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
+                    match message.default_value(i) {
+                        Some(value) => format!("parser.positional_or({})?", rust_default_value(value, &format_rust_type(typ, message.name, suffix, types))),
+                        None => "parser.positional()?".to_string(),
+                    }
+                }).collect::<Vec<_>>().join(", ");
+                format!("Self::{}({values})", message.name)
+            }
+            Parameters::Named(params) => {
+                let values = params.iter().enumerate()
+                    .map(|(i, (name, typ))| match message.default_value(i) {
+                        Some(value) => {
+                            let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name), types);
+                            format!("{name}: parser.named_or(\"{name}\", {})?", rust_default_value(value, &type_name))
+                        }
+                        None => format!("{name}: parser.named(\"{name}\")?"),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("Self::{} {{ {values} }}", message.name)
+            }
+        };
+        writeln!(writer, "            \"{}\" => {value},", message.name).unwrap();
+    }
+    writeln!(writer, "            _ => return Err(ParseError::UnknownMessage),").unwrap();
+    writeln!(writer, "        }};").unwrap();
+    writeln!(writer, "        parser.end()?;").unwrap();
+    writeln!(writer, "        Ok(message)").unwrap();
     writeln!(writer, "    }}").unwrap();
-    writeln!(writer, "}}\n\n").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    // /// Borrowed view of a message, see [Message]
+    // ///
+    // /// Arrays of scalars and strings borrow the decoded data.
+    // #[derive(Clone, Debug)]
+    // pub enum MessageRef<'a> {
+    //     Empty,
+    //     SomeValues(crate::message_ref::ArrayRef<'a, u16>, params::SomeValuesParam1),
+    //     Coordinates {
+    //         x: f32,
+    //         y: f32,
+    //     },
+    // }
+    //
+    // impl<'a> MessageRef<'a> {
+    //     pub(crate) fn deserialize_with_id(id: u8, reader: &mut &'a [u8]) -> Result<Self, DecodeError> {
+    //         match id {
+    //             20 => Ok(Self::Empty),
+    //             21 => Ok(Self::SomeValues(
+    //                 crate::message_ref::ArrayRef::deserialize_array(reader, 3)?,
+    //                 params::SomeValuesParam1::deserialize(reader)?,
+    //             )),
+    //             22 => Ok(Self::Coordinates {
+    //                 x: f32::deserialize(reader)?,
+    //                 y: f32::deserialize(reader)?,
+    //             }),
+    //             id => Err(DecodeError::UnknownMessage(id)),
+    //         }
+    //     }
+    //
+    //     pub fn message_id(&self) -> u8 { ... }
+    //
+    //     pub fn name(&self) -> &'static str { ... }
+    // }
+    writeln!(writer, "/// Borrowed view of a message, see [Message]").unwrap();
+    writeln!(writer, "///").unwrap();
+    writeln!(writer, "/// Arrays of scalars and strings borrow the decoded data.").unwrap();
+    writeln!(writer, "#[derive(Clone, Debug)]").unwrap();
+    writeln!(writer, "pub enum MessageRef<'a> {{").unwrap();
+    for message in messages {
+        match &message.parameters {
+            Parameters::None => {
+                writeln!(writer, "    {},", message.name).unwrap();
+            }
+            Parameters::Positional(params) => {
+                let values = params.iter().enumerate().map(|(i, typ)| {
+                    let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
+                    format_rust_ref_type(typ, message.name, suffix, types)
+                }).collect::<Vec<_>>().join(", ");
+                writeln!(writer, "    {}({}),", message.name, values).unwrap();
+            }
+            Parameters::Named(params) => {
+                writeln!(writer, "    {} {{", message.name).unwrap();
+                for (name, typ) in params {
+                    let type_name = format_rust_ref_type(typ, message.name, ParamChoiceSuffix::Name(name), types);
+                    writeln!(writer, "        {name}: {type_name},").unwrap();
+                }
+                writeln!(writer, "    }},").unwrap();
+            }
+        }
+    }
+    writeln!(writer, "}}\n").unwrap();
+
+    writeln!(writer, "impl<'a> MessageRef<'a> {{").unwrap();
+    writeln!(writer, "    pub(crate) fn deserialize_with_id(id: u8, reader: &mut &'a [u8]) -> Result<Self, DecodeError> {{").unwrap();
+    writeln!(writer, "        match id {{").unwrap();
+    for message in messages {
+        match &message.parameters {
+            Parameters::None => {
+                writeln!(writer, "            {} => Ok(Self::{}),", message.id, message.name).unwrap();
+            }
+            Parameters::Positional(params) => {
+                writeln!(writer, "            {} => Ok(Self::{}(", message.id, message.name).unwrap();
+                for (i, typ) in params.iter().enumerate() {
+                    let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
+                    writeln!(writer, "                {},", deserialize_ref_param_expr(message, i, typ, suffix, types)).unwrap();
+                }
+                writeln!(writer, "            )),").unwrap();
+            }
+            Parameters::Named(params) => {
+                writeln!(writer, "            {} => Ok(Self::{} {{", message.id, message.name).unwrap();
+                for (i, (name, typ)) in params.iter().enumerate() {
+                    let expr = deserialize_ref_param_expr(message, i, typ, ParamChoiceSuffix::Name(name), types);
+                    writeln!(writer, "                {name}: {expr},").unwrap();
+                }
+                writeln!(writer, "            }}),").unwrap();
+            }
+        }
+    }
+    writeln!(writer, "            id => Err(DecodeError::UnknownMessage(id)),").unwrap();
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}\n").unwrap();
+    writeln!(writer, "    pub fn message_id(&self) -> u8 {{").unwrap();
+    writeln!(writer, "        match self {{").unwrap();
+    for message in messages {
+        writeln!(writer, "            {} => {},", destructured_parameters_ignored(message), message.id).unwrap();
+    }
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}\n").unwrap();
+    writeln!(writer, "    /// Return the name of the message").unwrap();
+    writeln!(writer, "    pub fn name(&self) -> &'static str {{").unwrap();
+    writeln!(writer, "        match self {{").unwrap();
+    for message in messages {
+        writeln!(writer, "            {} => \"{}\",", destructured_parameters_ignored(message), message.name).unwrap();
+    }
+    writeln!(writer, "        }}").unwrap();
+    writeln!(writer, "    }}").unwrap();
+    writeln!(writer, "}}\n").unwrap();
+
+    // /// Maximum size of an encoded message, in bytes
+    // pub const MAX_ENCODED_SIZE: usize = 42;
+    let max_encoded_size = messages.iter().map(|message| message_max_encoded_size(message, types)).max().unwrap_or(0);
+    writeln!(writer, "/// Maximum size of an encoded message, in bytes").unwrap();
+    writeln!(writer, "pub const MAX_ENCODED_SIZE: usize = {max_encoded_size};").unwrap();
+    writeln!(writer).unwrap();
+
+    // /// Hash of the message schema, used to check compatibility between peers
+    // pub const SCHEMA_HASH: u32 = 0x12345678;
+    writeln!(writer, "/// Hash of the message schema, used to check compatibility between peers").unwrap();
+    writeln!(writer, "pub const SCHEMA_HASH: u32 = 0x{:08x};", schema_hash(messages, types)).unwrap();
+    writeln!(writer, "\n").unwrap();
 
     // Parameter types are gathered in `params` module
     writeln!(writer, "pub mod params {{").unwrap();
@@ -370,9 +602,31 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
     //
     // impl Serialize for MessageParam {
     //     fn serialized_size(&self) -> usize { core::mem::size_of::<u8>() }
-    //     fn serialize<W: Writer>(&self, encoder: &mut W) { (*self as u8).serialize(encoder) }
+    //     fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> { (*self as u8).serialize(encoder) }
+    // }
+    //
+    // impl TextValue for MessageParam {
+    //     fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    //         f.write_str(match self {
+    //             Self::Choice => "choice",
+    //             Self::Alternative => "alternative",
+    //         })
+    //     }
+    //
+    //     fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+    //         match parser.word()? {
+    //             ("choice", _) => Ok(Self::Choice),
+    //             ("alternative", _) => Ok(Self::Alternative),
+    //             (_, pos) => Err(ParseError::BadValue(pos)),
+    //         }
+    //     }
     // }
     let mut rust_choices = vec![];
+    for decl in types {
+        if let TypeDef::Enum(choices) = &decl.def {
+            rust_choices.push((decl.name.to_string(), choices));
+        }
+    }
     for message in messages {
         match &message.parameters {
             Parameters::None => {},
@@ -418,22 +672,297 @@ fn generate_bindings<P: AsRef<Path>>(messages: &[Message], path: P) {
 
         writeln!(writer, "    impl Serialize for {name} {{").unwrap();
         writeln!(writer, "        fn serialized_size(&self) -> usize {{ core::mem::size_of::<u8>() }}").unwrap();
-        writeln!(writer, "        fn serialize<W: Writer>(&self, encoder: &mut W) {{ (*self as u8).serialize(encoder) }}").unwrap();
+        writeln!(writer, "        fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {{ (*self as u8).serialize(encoder) }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl TextValue for {name} {{").unwrap();
+        writeln!(writer, "        fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {{").unwrap();
+        writeln!(writer, "            f.write_str(match self {{").unwrap();
+        for choice in choices.iter() {
+            writeln!(writer, "                Self::{} => \"{choice}\",", capitalize(choice)).unwrap();
+        }
+        writeln!(writer, "            }})").unwrap();
+        writeln!(writer, "        }}\n").unwrap();
+        writeln!(writer, "        fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {{").unwrap();
+        writeln!(writer, "            match parser.word()? {{").unwrap();
+        for choice in choices.iter() {
+            writeln!(writer, "                (\"{choice}\", _) => Ok(Self::{}),", capitalize(choice)).unwrap();
+        }
+        writeln!(writer, "                (_, pos) => Err(ParseError::BadValue(pos)),").unwrap();
+        writeln!(writer, "            }}").unwrap();
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}").unwrap();
+    }
+
+    // bitflags::bitflags! {
+    //     #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    //     pub struct Flags: u8 {
+    //         const FIRST = 1 << 0;
+    //         const SECOND = 1 << 1;
+    //     }
+    // }
+    //
+    // impl Deserialize for Flags {
+    //     fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {
+    //         let bits = u8::deserialize(reader)?;
+    //         Self::from_bits(bits).ok_or(DecodeError::BadFlagsValue(bits as u32))
+    //     }
+    // }
+    //
+    // impl Serialize for Flags {
+    //     fn serialized_size(&self) -> usize { core::mem::size_of::<u8>() }
+    //     fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> { self.bits().serialize(encoder) }
+    // }
+    //
+    // impl Flags {
+    //     const TEXT_NAMES: [(Self, &'static str); 2] = [(Self::FIRST, "first"), (Self::SECOND, "second")];
+    // }
+    //
+    // impl TextValue for Flags {
+    //     fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    //         let mut empty = true;
+    //         for (flag, name) in Self::TEXT_NAMES {
+    //             if self.contains(flag) {
+    //                 if !empty { f.write_str("|")?; }
+    //                 f.write_str(name)?;
+    //                 empty = false;
+    //             }
+    //         }
+    //         if empty { f.write_str("0")?; }
+    //         Ok(())
+    //     }
+    //
+    //     fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+    //         let mut value = Self::empty();
+    //         loop {
+    //             let (word, pos) = parser.word()?;
+    //             value |= match Self::TEXT_NAMES.iter().find(|(_, name)| *name == word) {
+    //                 Some((flag, _)) => *flag,
+    //                 None if word == "0" => Self::empty(),
+    //                 None => return Err(ParseError::BadValue(pos)),
+    //             };
+    //             if parser.peek() != Some('|') {
+    //                 return Ok(value);
+    //             }
+    //             parser.expect('|')?;
+    //         }
+    //     }
+    // }
+    for decl in types {
+        let TypeDef::Flags(flags) = &decl.def else { continue };
+        let name = decl.name;
+        let bits_type = flags_bits_type(flags);
+        writeln!(writer).unwrap();
+        writeln!(writer, "    bitflags::bitflags! {{").unwrap();
+        writeln!(writer, "        #[derive(Clone, Copy, PartialEq, Eq, Debug)]").unwrap();
+        writeln!(writer, "        pub struct {name}: {bits_type} {{").unwrap();
+        for (i, flag) in flags.iter().enumerate() {
+            writeln!(writer, "            const {} = 1 << {i};", flag.to_ascii_uppercase()).unwrap();
+        }
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl Deserialize for {name} {{").unwrap();
+        writeln!(writer, "        fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {{").unwrap();
+        writeln!(writer, "            let bits = {bits_type}::deserialize(reader)?;").unwrap();
+        writeln!(writer, "            Self::from_bits(bits).ok_or(DecodeError::BadFlagsValue(bits as u32))").unwrap();
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl Serialize for {name} {{").unwrap();
+        writeln!(writer, "        fn serialized_size(&self) -> usize {{ core::mem::size_of::<{bits_type}>() }}").unwrap();
+        writeln!(writer, "        fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {{ self.bits().serialize(encoder) }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        let text_names = flags.iter()
+            .map(|flag| format!("(Self::{}, \"{flag}\")", flag.to_ascii_uppercase()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(writer, "    impl {name} {{").unwrap();
+        writeln!(writer, "        const TEXT_NAMES: [(Self, &'static str); {}] = [{text_names}];", flags.len()).unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl TextValue for {name} {{").unwrap();
+        writeln!(writer, "        fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {{").unwrap();
+        writeln!(writer, "            let mut empty = true;").unwrap();
+        writeln!(writer, "            for (flag, name) in Self::TEXT_NAMES {{").unwrap();
+        writeln!(writer, "                if self.contains(flag) {{").unwrap();
+        writeln!(writer, "                    if !empty {{ f.write_str(\"|\")?; }}").unwrap();
+        writeln!(writer, "                    f.write_str(name)?;").unwrap();
+        writeln!(writer, "                    empty = false;").unwrap();
+        writeln!(writer, "                }}").unwrap();
+        writeln!(writer, "            }}").unwrap();
+        writeln!(writer, "            if empty {{ f.write_str(\"0\")?; }}").unwrap();
+        writeln!(writer, "            Ok(())").unwrap();
+        writeln!(writer, "        }}\n").unwrap();
+        writeln!(writer, "        fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {{").unwrap();
+        writeln!(writer, "            let mut value = Self::empty();").unwrap();
+        writeln!(writer, "            loop {{").unwrap();
+        writeln!(writer, "                let (word, pos) = parser.word()?;").unwrap();
+        writeln!(writer, "                value |= match Self::TEXT_NAMES.iter().find(|(_, name)| *name == word) {{").unwrap();
+        writeln!(writer, "                    Some((flag, _)) => *flag,").unwrap();
+        writeln!(writer, "                    None if word == \"0\" => Self::empty(),").unwrap();
+        writeln!(writer, "                    None => return Err(ParseError::BadValue(pos)),").unwrap();
+        writeln!(writer, "                }};").unwrap();
+        writeln!(writer, "                if parser.peek() != Some('|') {{").unwrap();
+        writeln!(writer, "                    return Ok(value);").unwrap();
+        writeln!(writer, "                }}").unwrap();
+        writeln!(writer, "                parser.expect('|')?;").unwrap();
+        writeln!(writer, "            }}").unwrap();
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}").unwrap();
+    }
+
+    // #[derive(Clone, Copy, PartialEq, Debug)]
+    // pub struct Coordinates {
+    //     pub x: f32,
+    //     pub y: f32,
+    // }
+    //
+    // impl Deserialize for Coordinates {
+    //     fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {
+    //         Ok(Self {
+    //             x: f32::deserialize(reader)?,
+    //             y: f32::deserialize(reader)?,
+    //         })
+    //     }
+    // }
+    //
+    // impl Serialize for Coordinates {
+    //     fn serialized_size(&self) -> usize {
+    //         self.x.serialized_size() +
+    //         self.y.serialized_size()
+    //     }
+    //
+    //     fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
+    //         self.x.serialize(encoder)?;
+    //         self.y.serialize(encoder)?;
+    //         Ok(())
+    //     }
+    // }
+    //
+    // impl TextValue for Coordinates {
+    //     fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+    //         f.write_str("{x=")?;
+    //         self.x.fmt_text(f)?;
+    //         f.write_str(",y=")?;
+    //         self.y.fmt_text(f)?;
+    //         f.write_str("}")
+    //     }
+    //
+    //     fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+    //         parser.expect('{')?;
+    //         let x = parser.named("x")?;
+    //         parser.expect(',')?;
+    //         let y = parser.named("y")?;
+    //         parser.expect('}')?;
+    //         Ok(Self { x, y })
+    //     }
+    // }
+    for decl in types {
+        let TypeDef::Struct(fields) = &decl.def else { continue };
+        let name = decl.name;
+        let is_copy = fields.iter().all(|(_, typ)| is_copy_type(typ, types));
+        writeln!(writer).unwrap();
+        writeln!(writer, "    #[derive(Clone, {}PartialEq, Debug)]", if is_copy { "Copy, " } else { "" }).unwrap();
+        writeln!(writer, "    pub struct {name} {{").unwrap();
+        for (field, typ) in fields {
+            writeln!(writer, "        pub {field}: {},", rust_type_name(typ, types)).unwrap();
+        }
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl Deserialize for {name} {{").unwrap();
+        writeln!(writer, "        fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {{").unwrap();
+        writeln!(writer, "            Ok(Self {{").unwrap();
+        for (field, typ) in fields {
+            writeln!(writer, "                {field}: {}::deserialize(reader)?,", enclosed_rust_type(&rust_type_name(typ, types))).unwrap();
+        }
+        writeln!(writer, "            }})").unwrap();
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl Serialize for {name} {{").unwrap();
+        writeln!(writer, "        fn serialized_size(&self) -> usize {{").unwrap();
+        for (i, (field, _)) in fields.iter().enumerate() {
+            writeln!(writer, "            self.{field}.serialized_size(){}", if i == fields.len() - 1 { "" } else { " +" }).unwrap();
+        }
+        writeln!(writer, "        }}\n").unwrap();
+        writeln!(writer, "        fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {{").unwrap();
+        for (field, _) in fields {
+            writeln!(writer, "            self.{field}.serialize(encoder)?;").unwrap();
+        }
+        writeln!(writer, "            Ok(())").unwrap();
+        writeln!(writer, "        }}").unwrap();
+        writeln!(writer, "    }}\n").unwrap();
+
+        writeln!(writer, "    impl TextValue for {name} {{").unwrap();
+        writeln!(writer, "        fn fmt_text(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {{").unwrap();
+        for (i, (field, _)) in fields.iter().enumerate() {
+            writeln!(writer, "            f.write_str(\"{}{field}=\")?;", if i == 0 { "{" } else { "," }).unwrap();
+            writeln!(writer, "            self.{field}.fmt_text(f)?;").unwrap();
+        }
+        writeln!(writer, "            f.write_str(\"}}\")").unwrap();
+        writeln!(writer, "        }}\n").unwrap();
+        writeln!(writer, "        fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {{").unwrap();
+        writeln!(writer, "            parser.expect('{{')?;").unwrap();
+        for (i, (field, _)) in fields.iter().enumerate() {
+            if i != 0 {
+                writeln!(writer, "            parser.expect(',')?;").unwrap();
+            }
+            writeln!(writer, "            let {field} = parser.named(\"{field}\")?;").unwrap();
+        }
+        writeln!(writer, "            parser.expect('}}')?;").unwrap();
+        let field_names = fields.iter().map(|(field, _)| *field).collect::<Vec<_>>().join(", ");
+        writeln!(writer, "            Ok(Self {{ {field_names} }})").unwrap();
+        writeln!(writer, "        }}").unwrap();
         writeln!(writer, "    }}").unwrap();
     }
 
     writeln!(writer, "}}").unwrap();  // End of `params` module
 }
 
+/// Return the name of the [RomeHandler] method of a message: `on_{snake_case_name}`
+fn handler_method_name(message_name: &str) -> String {
+    let mut result = String::from("on");
+    for c in message_name.chars() {
+        if c.is_ascii_uppercase() {
+            result.push('_');
+        }
+        result.push(c.to_ascii_lowercase());
+    }
+    result
+}
+
+/// Return the arguments of a [RomeHandler] method: name, Rust type and whether it is `Copy`
+///
+/// Names match the ones used by [destructured_parameters()].
+fn handler_arguments(message: &Message<'_>, types: &[TypeDecl<'_>]) -> Vec<(String, String, bool)> {
+    let is_copy = |typ: &ParamType| match typ {
+        ParamType::Name(name) => is_copy_type(name, types),
+        ParamType::Choice(_) => true,
+    };
+    match &message.parameters {
+        Parameters::None => vec![],
+        Parameters::Positional(params) => params.iter().enumerate().map(|(i, typ)| {
+            let suffix = if params.len() == 1 { ParamChoiceSuffix::None } else { ParamChoiceSuffix::Index(i) };
+            (format!("v{i}"), format_rust_type(typ, message.name, suffix, types), is_copy(typ))
+        }).collect(),
+        Parameters::Named(params) => params.iter().map(|(name, typ)| {
+            let type_name = format_rust_type(typ, message.name, ParamChoiceSuffix::Name(name), types);
+            (name.to_string(), type_name, is_copy(typ))
+        }).collect(),
+    }
+}
+
 enum ParamChoiceSuffix<'a> {
     None,
     Index(usize),
     Name(&'a str),
 }
 
-fn format_rust_type(typ: &ParamType<'_>, message_name: &str, suffix: ParamChoiceSuffix) -> String {
+fn format_rust_type(typ: &ParamType<'_>, message_name: &str, suffix: ParamChoiceSuffix, types: &[TypeDecl<'_>]) -> String {
     match typ {
-        ParamType::Name(s) => s.to_string(),
+        ParamType::Name(s) => rust_type_name(s, types),
         ParamType::Choice(_) => format!("params::{}", format_rust_choice_type(message_name, suffix)),
     }
 }
@@ -446,8 +975,101 @@ fn format_rust_choice_type(message_name: &str, suffix: ParamChoiceSuffix) -> Str
     }
 }
 
+/// Return the type and deserialization expression of a parameter borrowed by [MessageRef]
+///
+/// Arrays of scalars are borrowed as `ArrayRef`, strings as `&str`.
+/// Return `None` for other parameters, which are decoded as in [Message].
+fn borrowed_param(typ: &ParamType<'_>) -> Option<(String, String)> {
+    let ParamType::Name(name) = typ else { return None };
+    let is_array_item = |item: &str| matches!(item, "bool" | "u8" | "i8" | "u16" | "i16" | "u32" | "i32" | "f32");
+    if let Some((item, count)) = parse_array_type(name).filter(|(item, _)| is_array_item(item)) {
+        Some((
+            format!("crate::message_ref::ArrayRef<'a, {item}>"),
+            format!("crate::message_ref::ArrayRef::deserialize_array(reader, {count})?"),
+        ))
+    } else if let Some((item, capacity)) = parse_vec_type(name).filter(|(item, _)| is_array_item(item)) {
+        Some((
+            format!("crate::message_ref::ArrayRef<'a, {item}>"),
+            format!("crate::message_ref::ArrayRef::deserialize_vec(reader, {capacity})?"),
+        ))
+    } else {
+        parse_str_type(name).map(|capacity| (
+            "&'a str".to_string(),
+            format!("crate::message_ref::deserialize_str(reader, {capacity})?"),
+        ))
+    }
+}
+
+fn format_rust_ref_type(typ: &ParamType<'_>, message_name: &str, suffix: ParamChoiceSuffix, types: &[TypeDecl<'_>]) -> String {
+    match borrowed_param(typ) {
+        Some((type_name, _)) => type_name,
+        None => format_rust_type(typ, message_name, suffix, types),
+    }
+}
+
+fn deserialize_ref_param_expr(message: &Message<'_>, index: usize, typ: &ParamType<'_>, suffix: ParamChoiceSuffix, types: &[TypeDecl<'_>]) -> String {
+    match borrowed_param(typ) {
+        Some((_, expr)) => expr,
+        None => deserialize_param_expr(message, index, &format_rust_type(typ, message.name, suffix, types)),
+    }
+}
+
+/// Return the encoded size of a message, including its ID
+fn message_max_encoded_size(message: &Message<'_>, types: &[TypeDecl<'_>]) -> usize {
+    let params_size: usize = match &message.parameters {
+        Parameters::None => 0,
+        Parameters::Positional(params) => params.iter().map(|typ| param_type_size(typ, types)).sum(),
+        Parameters::Named(params) => params.iter().map(|(_, typ)| param_type_size(typ, types)).sum(),
+    };
+    params_size + 1
+}
+
+fn param_type_size(typ: &ParamType<'_>, types: &[TypeDecl<'_>]) -> usize {
+    match typ {
+        ParamType::Name(name) => type_name_size(name, types),
+        ParamType::Choice(_) => 1,
+    }
+}
+
+
+/// Convert a type name from YAML to a Rust type
+fn rust_type_name(name: &str, types: &[TypeDecl<'_>]) -> String {
+    if find_type(name, types).is_some() {
+        format!("params::{name}")
+    } else if let Some((item, count)) = parse_array_type(name) {
+        format!("[{}; {count}]", rust_type_name(item, types))
+    } else if let Some(capacity) = parse_str_type(name) {
+        format!("heapless::String<{capacity}>")
+    } else if let Some((item, capacity)) = parse_vec_type(name) {
+        format!("heapless::Vec<{}, {capacity}>", rust_type_name(item, types))
+    } else {
+        name.to_string()
+    }
+}
+
+
+/// Return the expression to deserialize a parameter, using its default value if optional
+fn deserialize_param_expr(message: &Message<'_>, index: usize, type_name: &str) -> String {
+    let expr = format!("{}::deserialize(reader)?", enclosed_rust_type(type_name));
+    match message.default_value(index) {
+        Some(value) => format!("if reader.is_at_end() {{ {} }} else {{ {expr} }}", rust_default_value(value, type_name)),
+        None => expr,
+    }
+}
+
+/// Return the Rust expression of a default value, for given Rust type
+fn rust_default_value(value: &DefaultValue<'_>, type_name: &str) -> String {
+    match value {
+        DefaultValue::Bool(value) => value.to_string(),
+        DefaultValue::Integer(value) if type_name == "f32" => format!("{:?}", *value as f32),
+        DefaultValue::Integer(value) => value.to_string(),
+        DefaultValue::Real(value) => format!("{:?}", *value as f32),
+        DefaultValue::Choice(choice) => format!("{type_name}::{}", capitalize(choice)),
+    }
+}
+
 fn enclosed_rust_type(name: &str) -> String {
-    if name.chars().next().map(|c| c.is_ascii_alphabetic()).unwrap_or(false) {
+    if name.chars().next().map(|c| c.is_ascii_alphabetic()).unwrap_or(false) && !name.contains('<') {
         name.into()
     } else {
         format!("<{name}>")
@@ -491,14 +1113,3 @@ fn destructured_parameters_ignored(message: &Message<'_>) -> String {
     }
 }
 
-fn load_yaml_doc<P: AsRef<Path>>(path: P) -> Yaml {
-    let mut contents = String::new();
-    let mut file = File::open(path).unwrap();
-    file.read_to_string(&mut contents).unwrap();
-
-    let mut docs = YamlLoader::load_from_str(&contents).unwrap();
-    if docs.len() != 1 {
-        panic!("Unexpected document count in YAML files, expect 1 document, got {}", docs.len());
-    }
-    docs.pop().unwrap()
-}
@@ -25,10 +25,32 @@ class Encoder:
             case "fmt":
                 return self.write_pack(val, data)
             case "array":
+                if len(data) != val[1]:
+                    raise ValueError(f"Invalid array size: expected {val[1]}, got {len(data)}")
+                for v in data:
+                    self.write_type(val[0], v)
+            case "str":
+                encoded = data.encode()
+                if len(encoded) > val:
+                    raise ValueError(f"String too long: capacity is {val}, got {len(encoded)}")
+                self.write_pack("<B", len(encoded))
+                self.buffer += encoded
+            case "vec":
+                if len(data) > val[1]:
+                    raise ValueError(f"Array too long: capacity is {val[1]}, got {len(data)}")
+                self.write_pack("<B", len(data))
                 for v in data:
                     self.write_type(val[0], v)
             case "choice":
                 return self.write_pack("<B", val.index(data))
+            case "flags":
+                names, fmt = val
+                return self.write_pack(fmt, sum(1 << names.index(name) for name in data))
+            case "struct":
+                if set(data) != set(val):
+                    raise ValueError(f"Invalid struct fields: expected {list(val)}, got {list(data)}")
+                for name, field_type in val.items():
+                    self.write_type(field_type, data[name])
             case _:
                 raise ValueError(f"Unexpected type: {typ!r}")
 
@@ -52,14 +74,37 @@ class Decoder:
                 return self.read_unpack(val)
             case "array":
                 return [self.read_type(val[0]) for _ in range(val[1])]
+            case "str":
+                size = self.read_unpack("<B")
+                if size > val:
+                    raise ValueError(f"String too long: capacity is {val}, got {size}")
+                return self.read_bytes(size).decode()
+            case "vec":
+                size = self.read_unpack("<B")
+                if size > val[1]:
+                    raise ValueError(f"Array too long: capacity is {val[1]}, got {size}")
+                return [self.read_type(val[0]) for _ in range(size)]
             case "choice":
                 return val[self.read_unpack("<B")]
+            case "flags":
+                names, fmt = val
+                bits = self.read_unpack(fmt)
+                if bits >> len(names):
+                    raise ValueError(f"Invalid flags value: {bits:#x}")
+                return {name for i, name in enumerate(names) if bits & (1 << i)}
+            case "struct":
+                return {name: self.read_type(field_type) for name, field_type in val.items()}
             case _:
                 raise ValueError(f"Unexpected type: {typ!r}")
 
     def read_unpack(self, fmt: str) -> Any:
-        data = self.io.read(struct.calcsize(fmt))
-        return struct.unpack(fmt, data)[0]
+        return struct.unpack(fmt, self.read_bytes(struct.calcsize(fmt)))[0]
+
+    def read_bytes(self, size: int) -> bytes:
+        data = self.io.read(size)
+        if len(data) != size:
+            raise ValueError("Not enough data")
+        return data
 
 
 @dataclass
@@ -68,6 +113,10 @@ class Message:
     id: int
     name: str
     params: Parameters
+    # Signature used to compute the schema hash
+    signature: str = ""
+    # Default values of optional parameters, which are always the last ones
+    defaults: tuple = ()
 
     def __class_getitem__(cls, key: str | int) -> Self:
         """Get a message declaration, by name or ID"""
@@ -89,10 +138,16 @@ class Message:
         message = messages.get(message_id)
         if not message:
             raise KeyError(f"Unknown message ID: {message_id}")
-        frame = message.decode_payload(decoder)
-        if not decoder.eof():
-            raise ValueError(f"Undecoded data after message with ID {message_id}")
-        return frame
+        # Note: trailing data is ignored, it may be optional parameters unknown to the local schema
+        return message.decode_payload(decoder)
+
+    def read_params(self, decoder: Decoder, types: list[ParamType]) -> list[Any]:
+        """Read parameter values, use default values of missing optional parameters"""
+        required = len(types) - len(self.defaults)
+        return [
+            self.defaults[i - required] if i >= required and decoder.eof() else decoder.read_type(typ)
+            for i, typ in enumerate(types)
+        ]
 
 
 class MessageEmpty(Message):
@@ -112,7 +167,7 @@ class MessagePositional(Message):
         return Frame(self, args)
 
     def decode_payload(self, decoder: Decoder) -> "Frame":
-        return Frame(self, tuple(decoder.read_type(typ) for typ in self.params))
+        return Frame(self, tuple(self.read_params(decoder, list(self.params))))
 
 
 class MessageNamed(Message):
@@ -121,7 +176,7 @@ class MessageNamed(Message):
         return Frame(self, kwargs)
 
     def decode_payload(self, decoder: Decoder) -> "Frame":
-        return Frame(self, {k: decoder.read_type(typ) for k, typ in self.params.items()})
+        return Frame(self, dict(zip(self.params, self.read_params(decoder, list(self.params.values())))))
 
 
 @dataclass
@@ -134,13 +189,18 @@ class Frame:
     def __init__(self, message: Message, args: Arguments):
         if type(args) is not type(message.params):
             raise ValueError("Provided arguments type does not match message declaration")
+        # Optional parameters can be omitted
+        required = len(message.params or ()) - len(message.defaults)
         match args:
             case tuple(args):
-                if len(args) != len(message.params):
+                if not required <= len(args) <= len(message.params):
                     raise ValueError("Provided arguments count does not match message declaration")
+                args += message.defaults[len(args) - required:]
             case dict(args):
-                if set(args) != set(message.params):
+                names = list(message.params)
+                if not set(names[:required]) <= set(args) <= set(names):
                     raise ValueError("Provided argument names does not match message declaration")
+                args = {name: args[name] if name in args else message.defaults[i - required] for i, name in enumerate(names)}
         # Note: values are not checked at this stage
         self.message = message
         self.args = args
@@ -169,6 +229,111 @@ class Frame:
                     encoder.write_type(self.message.params[name], args[name])
         return bytes(encoder.buffer)
 
+    def encode_sequenced(self, seq: int) -> bytes:
+        """Encode the frame in an envelope with a sequence number (see `rome::reliable`)"""
+        return struct.pack("<BH", ENVELOPE_ID, seq) + self.encode()
+
+    def encode_stream(self, seq: int | None = None) -> bytes:
+        """Encode the frame for a byte stream (see `rome::framing`)
+
+        If `seq` is set, the frame is sent in an envelope with a sequence number.
+        """
+        data = self.encode() if seq is None else self.encode_sequenced(seq)
+        return _cobs_encode(data + struct.pack("<H", crc16(data))) + STREAM_DELIMITER
+
+
+STREAM_DELIMITER = b"\x00"
+
+# Message ID reserved for sequenced envelopes
+ENVELOPE_ID = 0xff
+
+
+def split_envelope(data: bytes) -> tuple[int | None, bytes]:
+    """Split data into an optional sequence number and the encoded message"""
+    if len(data) >= 3 and data[0] == ENVELOPE_ID:
+        return struct.unpack("<H", data[1:3])[0], data[3:]
+    return None, data
+
+
+def crc16(data: bytes) -> int:
+    """Compute a CRC-16/CCITT-FALSE"""
+    crc = 0xffff
+    for byte in data:
+        crc ^= byte << 8
+        for _ in range(8):
+            crc = ((crc << 1) ^ 0x1021) if crc & 0x8000 else (crc << 1)
+            crc &= 0xffff
+    return crc
+
+
+class StreamDecoder:
+    """Incremental decoder for framed messages received from a byte stream
+
+    Data is fed as it is received. Decoded frames are returned with their
+    sequence number, `None` if they are not sent in an envelope.
+    Decoding errors are returned as exceptions instead of being raised, so that
+    the stream can be decoded further.
+    """
+
+    def __init__(self):
+        self.buffer = bytearray()
+
+    def feed(self, data: bytes) -> list[tuple[int | None, Frame] | Exception]:
+        results = []
+        for byte in data:
+            if byte == STREAM_DELIMITER[0]:
+                if self.buffer:
+                    results.append(self._decode_buffer())
+                self.buffer.clear()
+            else:
+                self.buffer.append(byte)
+        return results
+
+    def _decode_buffer(self) -> tuple[int | None, Frame] | Exception:
+        try:
+            payload = _cobs_decode(bytes(self.buffer))
+            if len(payload) < 2:
+                raise ValueError("Stream frame is too short")
+            data, crc = payload[:-2], struct.unpack("<H", payload[-2:])[0]
+            if crc16(data) != crc:
+                raise ValueError("Stream frame checksum mismatch")
+            seq, data = split_envelope(data)
+            return seq, Message.decode(data)
+        except Exception as e:
+            return e
+
+
+def _cobs_encode(data: bytes) -> bytes:
+    output = bytearray(b"\x00")
+    code_pos = 0
+    for byte in data:
+        if byte == 0:
+            output[code_pos] = len(output) - code_pos
+            code_pos = len(output)
+            output.append(0)
+        else:
+            output.append(byte)
+            if len(output) - code_pos == 0xff:
+                output[code_pos] = 0xff
+                code_pos = len(output)
+                output.append(0)
+    output[code_pos] = len(output) - code_pos
+    return bytes(output)
+
+
+def _cobs_decode(data: bytes) -> bytes:
+    output = bytearray()
+    pos = 0
+    while pos < len(data):
+        code = data[pos]
+        if code == 0 or pos + code > len(data):
+            raise ValueError("Invalid COBS data")
+        output += data[pos + 1:pos + code]
+        pos += code
+        if code < 0xff and pos < len(data):
+            output.append(0)
+    return bytes(output)
+
 
 # Registered messages, indexed by ID
 messages: dict[int, Message] = {}
@@ -188,9 +353,13 @@ def load_messages(source: Path | str | TextIO) -> list[Message]:
     if not isinstance(doc, dict):
         raise ValueError("Invalid document: top level element must be an object")
 
+    types = _parse_types(doc.get("types", {}))
+
     declarations: dict[int, Message] = {}
     names_in_use: set[str] = set()
     for group_id, group_items in doc.items():
+        if group_id == "types":
+            continue
         if not isinstance(group_id, int):
             raise ValueError(f"Invalid message group ID: key must be an integer, got {group_id!r}")
         if group_id <= 0 or group_id > 0xff:
@@ -202,24 +371,38 @@ def load_messages(source: Path | str | TextIO) -> list[Message]:
         for message_name, parameters_decl in group_items.items():
             if not isinstance(message_name, str):
                 raise ValueError(f"Invalid message name: must be a string, got {message_name!r}")
+            if current_id == ENVELOPE_ID:
+                raise ValueError(f"Invalid message ID for {message_name}: {ENVELOPE_ID} is reserved for sequenced envelopes")
             if current_id in declarations:
                 raise ValueError(f"Duplicate message ID {current_id}, used by {declarations[current_id].name} and {message_name}")
             if message_name in names_in_use:
                 raise ValueError(f"Duplicate message name: {message_name}")
 
+            signature = _message_signature(current_id, message_name, parameters_decl, types)
+            defaults = []
+
+            def parse_param(value):
+                typ, default = _parse_param(value, types)
+                if default is not _REQUIRED:
+                    defaults.append(default)
+                elif defaults:
+                    raise ValueError(f"Invalid parameters for {message_name}: optional parameters must be the last ones")
+                return typ
+
             match parameters_decl:
                 case None:
-                    message = MessageEmpty(current_id, message_name, None)
+                    message = MessageEmpty(current_id, message_name, None, signature)
                 case list(items):
-                    message = MessagePositional(current_id, message_name, tuple(_parse_type_name(v) for v in items))
+                    params = tuple(parse_param(v) for v in items)
+                    message = MessagePositional(current_id, message_name, params, signature, tuple(defaults))
                 case dict(items):
                     # Note: assume YAML is correct and there is no duplicate parameter name
                     params = {}
                     for k, v in items.items():
                         if not isinstance(k, str):
                             raise ValueError(f"Invalid parameter name: must be a string, got {k!r}")
-                        params[k] = _parse_type_name(v)
-                    message = MessageNamed(current_id, message_name, params)
+                        params[k] = parse_param(v)
+                    message = MessageNamed(current_id, message_name, params, signature, tuple(defaults))
                 case _:
                     raise ValueError("Invalid messsage declaration: value must be an array or object")
             declarations[current_id] = message
@@ -254,8 +437,113 @@ def register_default_messages() -> None:
     return register_messages(DEFAULT_MESSAGES_PATH)
 
 
-def _parse_type_name(value) -> ParamType:
+def schema_hash(declarations: list[Message] | None = None) -> int:
+    """Compute the schema hash of message declarations (registered ones by default)
+
+    The value must match `rome::SCHEMA_HASH` of the peer.
+    """
+    if declarations is None:
+        declarations = list(messages.values())
+    h = 0x811c9dc5
+    for decl in sorted(declarations, key=lambda m: m.id):
+        for byte in (decl.signature + "\n").encode():
+            h = ((h ^ byte) * 0x01000193) & 0xffffffff
+    return h
+
+
+def hello(device: str = "ground_station") -> Frame:
+    """Create a `RomeHello` frame for the registered messages"""
+    return Message["RomeHello"](schema_hash=schema_hash(), device=device)
+
+
+@dataclass
+class TypeDecl:
+    """Type declared in the `types` section"""
+    name: str
+    typ: ParamType
+    # Expanded signature, used in message signatures
+    signature: str
+
+
+def _parse_types(doc) -> dict[str, TypeDecl]:
+    """Parse the `types` section, return declarations indexed by name"""
+
+    if not isinstance(doc, dict):
+        raise ValueError("Invalid types section: value must be an object")
+    types: dict[str, TypeDecl] = {}
+    for name, decl in doc.items():
+        if not isinstance(name, str) or not name[:1].isupper():
+            raise ValueError(f"Invalid type name: must be a capitalized string, got {name!r}")
+        if name in types:
+            raise ValueError(f"Duplicate type name: {name}")
+        match decl:
+            case {"enum": list(values)}:
+                typ = _parse_type_name(values, {})
+                signature = _type_signature(values, {})
+            case {"flags": list(values)}:
+                if len(values) > 32:
+                    raise ValueError(f"Invalid flags type {name}: at most 32 flags are supported")
+                fmt = "<B" if len(values) <= 8 else "<H" if len(values) <= 16 else "<L"
+                typ = ("flags", (values, fmt))
+                signature = f"flags[{','.join(values)}]"
+            case {"struct": dict(fields)} if fields:
+                # Only previously declared types can be used, this prevents recursive types
+                typ = ("struct", {k: _parse_type_name(v, types) for k, v in fields.items()})
+                signature = f"{{{','.join(f'{k}:{_type_signature(v, types)}' for k, v in fields.items())}}}"
+            case _:
+                raise ValueError(f"Invalid declaration of type {name}")
+        types[name] = TypeDecl(name, typ, signature)
+    return types
+
+
+def _message_signature(message_id: int, name: str, parameters_decl, types: dict[str, TypeDecl]) -> str:
+    """Return message signature, must match `message_signature()` from `build.rs`
+
+    Optional parameters are not included.
+    """
+    match parameters_decl:
+        case None:
+            return f"{message_id}:{name}"
+        case list(items):
+            items = [v for v in items if not isinstance(v, dict)]
+            return f"{message_id}:{name}({','.join(_type_signature(v, types) for v in items)})"
+        case dict(items):
+            items = {k: v for k, v in items.items() if not isinstance(v, dict)}
+            return f"{message_id}:{name}{{{','.join(f'{k}:{_type_signature(v, types)}' for k, v in items.items())}}}"
+        case _:
+            raise ValueError("Invalid messsage declaration: value must be an array or object")
+
+
+def _type_signature(value, types: dict[str, TypeDecl]) -> str:
+    """Return type signature, with declared types expanded"""
+    if isinstance(value, list):
+        return f"[{','.join(value)}]"
+    if value in types:
+        return types[value].signature
+    if m := re.match(r"^\[(.*); (\d+)\]$", value):
+        return f"[{_type_signature(m.group(1), types)}; {m.group(2)}]"
+    if m := re.match(r"^vec<(.*), *(\d+)>$", value):
+        return f"vec<{_type_signature(m.group(1), types)}, {m.group(2)}>"
+    return str(value)
+
+
+# Marker for parameters without default value
+_REQUIRED = object()
+
+
+def _parse_param(value, types: dict[str, TypeDecl]) -> tuple[ParamType, Any]:
+    """Parse a parameter declaration, return its type and default value (`_REQUIRED` if not optional)"""
+    if isinstance(value, dict):
+        if set(value) != {"type", "default"}:
+            raise ValueError(f"Invalid optional parameter: expected an object with `type` and `default` keys, got {value!r}")
+        return _parse_type_name(value["type"], types), value["default"]
+    return _parse_type_name(value, types), _REQUIRED
+
+
+def _parse_type_name(value, types: dict[str, TypeDecl]) -> ParamType:
     if isinstance(value, str):
+        if value in types:
+            return types[value].typ
         match value:
             case "bool":
                 return ("fmt", "<?")
@@ -274,10 +562,21 @@ def _parse_type_name(value) -> ParamType:
             case "f32":
                 return ("fmt", "<f")
         if m := re.match(r"^\[(.*); (\d+)\]$", value):
-            return ("array", (_parse_type_name(m.group(1)), int(m.group(2))))
+            return ("array", (_parse_type_name(m.group(1), types), int(m.group(2))))
+        if m := re.match(r"^str<(\d+)>$", value):
+            return ("str", _parse_bounded_capacity(value, m.group(1)))
+        if m := re.match(r"^vec<(.*), *(\d+)>$", value):
+            return ("vec", (_parse_type_name(m.group(1), types), _parse_bounded_capacity(value, m.group(2))))
     elif isinstance(value, list):
         # Note: nested choices are not supported, but accepted here
         if all(isinstance(v, str) for v in value):
             return ("choice", value)
     raise ValueError(f"Unsupported type: {value!r}")
 
+
+def _parse_bounded_capacity(value: str, capacity: str) -> int:
+    """Parse capacity of variable-length types, length is encoded on 8 bits"""
+    n = int(capacity)
+    if n > 0xff:
+        raise ValueError(f"Invalid capacity in parameter type: {value!r}, maximum is 255")
+    return n
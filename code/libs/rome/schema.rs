@@ -0,0 +1,492 @@
+//! Parser of `rome_messages.yaml`
+//!
+//! This file is shared by `build.rs` and the `rome-schema-check` binary.
+//! Invalid declarations are reported by panicking, which fails the build.
+
+// Not all items are used by both users of this file
+#![allow(dead_code)]
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use yaml_rust::{YamlLoader, Yaml};
+
+
+pub enum ParamType<'a> {
+    Name(&'a str),
+    Choice(Vec<&'a str>),
+}
+
+pub enum Parameters<'a> {
+    None,
+    Positional(Vec<ParamType<'a>>),
+    Named(Vec<(&'a str, ParamType<'a>)>),
+}
+
+/// Message ID reserved for sequenced envelopes, see `rome::reliable`
+pub const ENVELOPE_ID: u8 = 0xFF;
+
+pub struct Message<'a> {
+    pub id: u8,
+    /// ID of the group the message is declared in
+    pub group: u8,
+    pub name: &'a str,
+    pub parameters: Parameters<'a>,
+    /// Default values of optional parameters, which are always the last ones
+    pub defaults: Vec<DefaultValue<'a>>,
+}
+
+impl<'a> Message<'a> {
+    /// Return parameter types, in order
+    pub fn param_types(&self) -> Vec<&ParamType<'a>> {
+        match &self.parameters {
+            Parameters::None => vec![],
+            Parameters::Positional(params) => params.iter().collect(),
+            Parameters::Named(params) => params.iter().map(|(_, typ)| typ).collect(),
+        }
+    }
+
+    /// Return the number of required (non-optional) parameters
+    pub fn required_count(&self) -> usize {
+        self.param_types().len() - self.defaults.len()
+    }
+
+    /// Return the default value of a parameter, `None` if the parameter is required
+    pub fn default_value(&self, index: usize) -> Option<&DefaultValue<'a>> {
+        self.defaults.get(index.checked_sub(self.required_count())?)
+    }
+}
+
+/// Default value of an optional parameter
+pub enum DefaultValue<'a> {
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    /// Choice or enum value
+    Choice(&'a str),
+}
+
+/// Type declared in the `types` section, shared by messages
+pub enum TypeDef<'a> {
+    Enum(Vec<&'a str>),
+    Flags(Vec<&'a str>),
+    Struct(Vec<(&'a str, &'a str)>),
+}
+
+pub struct TypeDecl<'a> {
+    pub name: &'a str,
+    pub def: TypeDef<'a>,
+}
+
+
+pub fn parse_message_doc(doc: &Yaml) -> (Vec<TypeDecl<'_>>, Vec<Message<'_>>) {
+    let hash = if let Yaml::Hash(hash) = doc {
+        hash
+    } else {
+        panic!("Invalid document: top level element must be an object");
+    };
+
+    let types = match hash.get(&Yaml::String("types".into())) {
+        Some(items) => parse_types(items),
+        None => Vec::new(),
+    };
+
+    // Groups must not overlap, messages are not allowed to use IDs of the next group
+    let mut group_ids = hash.keys().filter_map(|key| key.as_i64()).collect::<Vec<_>>();
+    group_ids.sort();
+
+    let mut messages = Vec::new();
+    let mut ids_in_use = HashMap::new();
+    let mut names_in_use = HashSet::new();
+    for (group_id, items) in hash {
+        let current_id = if let Yaml::Integer(id) = group_id {
+            if *id <= 0 || *id > u8::MAX as i64 {
+                panic!("Invalid message group ID: value must be a valid non-zero 8-bit value, got {id:?}");
+            }
+            *id as u8
+        } else if group_id.as_str() == Some("types") {
+            continue;
+        } else {
+            panic!("Invalid message group ID: key must be an integer, got {group_id:?}");
+        };
+        let items = if let Yaml::Hash(hash) = items {
+            hash
+        } else {
+            panic!("Invalid message group: value must an object");
+        };
+
+        let group_id = current_id;
+        let next_group_id = group_ids.iter().find(|id| **id > group_id as i64).copied().unwrap_or(ENVELOPE_ID as i64);
+        if group_id as usize + items.len() > next_group_id as usize {
+            panic!("Message group {group_id} overflows into group {next_group_id}: it has {} messages", items.len());
+        }
+
+        for (offset, (message_name, parameters_decl)) in items.iter().enumerate() {
+            let current_id = current_id + offset as u8;
+            let message_name = if let Yaml::String(name) = message_name {
+                name.as_str()
+            } else {
+                panic!("Invalid message name: must be a string, got {message_name:?}");
+            };
+            if current_id == ENVELOPE_ID {
+                panic!("Invalid message ID for {message_name}: {ENVELOPE_ID} is reserved for sequenced envelopes");
+            }
+            if let Some(old_name) = ids_in_use.insert(current_id, message_name) {
+                panic!("Duplicate message ID {current_id}, used by {old_name} and {message_name}");
+            }
+            if !names_in_use.insert(message_name) {
+                panic!("Duplicate message name: {message_name}");
+            }
+            let mut defaults = Vec::new();
+            let mut parse_item = |yaml| {
+                let (param_type, default) = parse_param(yaml);
+                match default {
+                    Some(value) => defaults.push(value),
+                    None if !defaults.is_empty() => panic!("Invalid parameters for {message_name}: optional parameters must be the last ones"),
+                    None => {}
+                }
+                param_type
+            };
+            let parameters = match parameters_decl {
+                Yaml::Null => {
+                    Parameters::None
+                }
+                Yaml::Array(items) => {
+                    let items = items.iter().map(&mut parse_item).collect();
+                    Parameters::Positional(items)
+                }
+                Yaml::Hash(items) => {
+                    // Note: assume YAML is correct and there is no duplicate parameter name
+                    let items = items.iter().map(|(name, value)| {
+                        let param_name = if let Yaml::String(name) = name {
+                            name.as_str()
+                        } else {
+                            panic!("Invalid parameter name: must be a string, got {name:?}");
+                        };
+                        let param_type = parse_item(value);
+                        (param_name, param_type)
+                    }).collect();
+                    Parameters::Named(items)
+                }
+                _ => panic!("Invalid messsage declaration: value must be an array or object"),
+            };
+            messages.push(Message {
+                id: current_id,
+                group: group_id,
+                name: message_name,
+                parameters,
+                defaults,
+            });
+        }
+    }
+
+    for message in &messages {
+        for (i, typ) in message.param_types().into_iter().enumerate() {
+            if let ParamType::Name(name) = typ {
+                check_type_name(name, &types);
+            }
+            if let Some(value) = message.default_value(i) {
+                check_default_value(message.name, typ, value, &types);
+            }
+        }
+    }
+
+    (types, messages)
+}
+
+pub fn parse_types(doc: &Yaml) -> Vec<TypeDecl<'_>> {
+    let items = if let Yaml::Hash(hash) = doc {
+        hash
+    } else {
+        panic!("Invalid types section: value must be an object");
+    };
+
+    let mut types: Vec<TypeDecl> = Vec::new();
+    for (type_name, decl) in items {
+        let type_name = match type_name {
+            Yaml::String(name) if name.starts_with(|c: char| c.is_ascii_uppercase()) => name.as_str(),
+            _ => panic!("Invalid type name: must be a capitalized string, got {type_name:?}"),
+        };
+        if types.iter().any(|t| t.name == type_name) {
+            panic!("Duplicate type name: {type_name}");
+        }
+        let def = match decl {
+            Yaml::Hash(hash) if hash.len() == 1 => {
+                let (kind, value) = hash.front().unwrap();
+                match (kind.as_str(), value) {
+                    (Some("enum"), Yaml::Array(values)) => TypeDef::Enum(parse_string_list(values)),
+                    (Some("flags"), Yaml::Array(values)) => {
+                        if values.len() > 32 {
+                            panic!("Invalid flags type {type_name}: at most 32 flags are supported");
+                        }
+                        TypeDef::Flags(parse_string_list(values))
+                    }
+                    (Some("struct"), Yaml::Hash(fields)) if !fields.is_empty() => {
+                        // Note: assume YAML is correct and there is no duplicate field name
+                        let fields = fields.iter().map(|(name, value)| {
+                            match (name, value) {
+                                (Yaml::String(name), Yaml::String(typ)) => {
+                                    // Only previously declared types can be used, this prevents recursive types
+                                    check_type_name(typ, &types);
+                                    (name.as_str(), typ.as_str())
+                                }
+                                _ => panic!("Invalid field in struct {type_name}: expected a type name, got {value:?}"),
+                            }
+                        }).collect();
+                        TypeDef::Struct(fields)
+                    }
+                    _ => panic!("Invalid declaration of type {type_name}"),
+                }
+            }
+            _ => panic!("Invalid declaration of type {type_name}: expected an object with a single `enum`, `flags` or `struct` key"),
+        };
+        types.push(TypeDecl { name: type_name, def });
+    }
+
+    types
+}
+
+pub fn parse_string_list(values: &[Yaml]) -> Vec<&str> {
+    values.iter().map(|v| {
+        if let Yaml::String(s) = v {
+            s.as_str()
+        } else {
+            panic!("Invalid choice value: must be a string, got {v:?}")
+        }
+    }).collect()
+}
+
+/// Check that a type name is valid, panic otherwise
+pub fn check_type_name(name: &str, types: &[TypeDecl<'_>]) {
+    type_name_size(name, types);
+}
+
+pub fn parse_type_name(yaml: &Yaml) -> ParamType<'_> {
+    match yaml {
+        Yaml::String(name) => ParamType::Name(name.as_str()),
+        Yaml::Array(items) => ParamType::Choice(parse_string_list(items)),
+        _ => panic!("Invalid parameter type: {yaml:?}"),
+    }
+}
+
+/// Parse a parameter declaration: a type, or `{type: T, default: value}` for optional parameters
+pub fn parse_param(yaml: &Yaml) -> (ParamType<'_>, Option<DefaultValue<'_>>) {
+    if let Yaml::Hash(hash) = yaml {
+        let get = |key: &str| hash.get(&Yaml::String(key.into()));
+        match (get("type"), get("default")) {
+            (Some(typ), Some(default)) if hash.len() == 2 => (parse_type_name(typ), Some(parse_default_value(default))),
+            _ => panic!("Invalid optional parameter: expected an object with `type` and `default` keys, got {yaml:?}"),
+        }
+    } else {
+        (parse_type_name(yaml), None)
+    }
+}
+
+pub fn parse_default_value(yaml: &Yaml) -> DefaultValue<'_> {
+    match yaml {
+        Yaml::Boolean(value) => DefaultValue::Bool(*value),
+        Yaml::Integer(value) => DefaultValue::Integer(*value),
+        Yaml::Real(value) => DefaultValue::Real(value.parse().unwrap_or_else(|_| panic!("Invalid default value: {value}"))),
+        Yaml::String(value) => DefaultValue::Choice(value.as_str()),
+        _ => panic!("Invalid default value: {yaml:?}"),
+    }
+}
+
+/// Check that a default value is valid for its parameter type, panic otherwise
+///
+/// Only scalars, choices and enums can have a default value.
+pub fn check_default_value(message_name: &str, typ: &ParamType<'_>, value: &DefaultValue<'_>, types: &[TypeDecl<'_>]) {
+    let choices = match typ {
+        ParamType::Choice(choices) => Some(choices),
+        ParamType::Name(name) => match find_type(name, types) {
+            Some(TypeDecl { def: TypeDef::Enum(choices), .. }) => Some(choices),
+            _ => None,
+        },
+    };
+    let valid = match (typ, value) {
+        (_, DefaultValue::Choice(choice)) => choices.is_some_and(|choices| choices.contains(choice)),
+        (ParamType::Name("bool"), DefaultValue::Bool(_)) => true,
+        (ParamType::Name("f32"), DefaultValue::Integer(_) | DefaultValue::Real(_)) => true,
+        (ParamType::Name(name), DefaultValue::Integer(value)) => match *name {
+            "u8" => u8::try_from(*value).is_ok(),
+            "i8" => i8::try_from(*value).is_ok(),
+            "u16" => u16::try_from(*value).is_ok(),
+            "i16" => i16::try_from(*value).is_ok(),
+            "u32" => u32::try_from(*value).is_ok(),
+            "i32" => i32::try_from(*value).is_ok(),
+            _ => false,
+        },
+        _ => false,
+    };
+    if !valid {
+        panic!("Invalid default value for optional parameter of {message_name}: only scalars, choices and enums are supported, and value must match the type");
+    }
+}
+
+/// Compute a stable hash of the message schema
+///
+/// The hash is a FNV-1a of the schema signature: one line per message, ordered by ID.
+/// Declared types are expanded in message signatures, so that only the wire format matters.
+/// Optional parameters are not part of signatures: adding one does not break compatibility.
+/// It must be kept in sync with `schema_hash()` in `rome.py`.
+pub fn schema_hash(messages: &[Message<'_>], types: &[TypeDecl<'_>]) -> u32 {
+    let mut sorted_messages = messages.iter().collect::<Vec<_>>();
+    sorted_messages.sort_by_key(|message| message.id);
+    let mut hash: u32 = 0x811c9dc5;
+    for message in sorted_messages {
+        for byte in message_signature(message, types).bytes().chain(std::iter::once(b'\n')) {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+    }
+    hash
+}
+
+/// Return the signature of a message, as used by [schema_hash()]
+///
+/// Examples: `20:Empty`, `21:SomeValues([u16; 3],[a,b])`, `22:Coordinates{x:f32,y:f32}`
+pub fn message_signature(message: &Message<'_>, types: &[TypeDecl<'_>]) -> String {
+    let required_count = message.required_count();
+    match &message.parameters {
+        Parameters::None => format!("{}:{}", message.id, message.name),
+        Parameters::Positional(params) => {
+            let params = params[..required_count].iter().map(|typ| param_type_signature(typ, types)).collect::<Vec<_>>().join(",");
+            format!("{}:{}({})", message.id, message.name, params)
+        }
+        Parameters::Named(params) => {
+            let params = params[..required_count].iter()
+                .map(|(name, typ)| format!("{name}:{}", param_type_signature(typ, types)))
+                .collect::<Vec<_>>()
+                .join(",");
+            format!("{}:{}{{{}}}", message.id, message.name, params)
+        }
+    }
+}
+
+pub fn param_type_signature(typ: &ParamType<'_>, types: &[TypeDecl<'_>]) -> String {
+    match typ {
+        ParamType::Name(name) => type_name_signature(name, types),
+        ParamType::Choice(choices) => format!("[{}]", choices.join(",")),
+    }
+}
+
+/// Return the signature of a type name, with declared types expanded
+///
+/// Enums have the same signature as inline choices.
+/// Examples: `[a,b]`, `flags[a,b]`, `{x:f32,y:f32}`, `vec<{x:f32,y:f32}, 10>`
+pub fn type_name_signature(name: &str, types: &[TypeDecl<'_>]) -> String {
+    if let Some(decl) = find_type(name, types) {
+        match &decl.def {
+            TypeDef::Enum(choices) => format!("[{}]", choices.join(",")),
+            TypeDef::Flags(flags) => format!("flags[{}]", flags.join(",")),
+            TypeDef::Struct(fields) => {
+                let fields = fields.iter()
+                    .map(|(field, typ)| format!("{field}:{}", type_name_signature(typ, types)))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{{{fields}}}")
+            }
+        }
+    } else if let Some((item, count)) = parse_array_type(name) {
+        format!("[{}; {count}]", type_name_signature(item, types))
+    } else if let Some((item, capacity)) = parse_vec_type(name) {
+        format!("vec<{}, {capacity}>", type_name_signature(item, types))
+    } else {
+        name.to_string()
+    }
+}
+
+pub fn type_name_size(name: &str, types: &[TypeDecl<'_>]) -> usize {
+    match name {
+        "bool" | "u8" | "i8" => 1,
+        "u16" | "i16" => 2,
+        "u32" | "i32" | "f32" => 4,
+        _ => {
+            if let Some(decl) = find_type(name, types) {
+                match &decl.def {
+                    TypeDef::Enum(_) => 1,
+                    TypeDef::Flags(flags) => type_name_size(flags_bits_type(flags), types),
+                    TypeDef::Struct(fields) => fields.iter().map(|(_, typ)| type_name_size(typ, types)).sum(),
+                }
+            } else if let Some((item, count)) = parse_array_type(name) {
+                type_name_size(item, types) * count
+            } else if let Some(capacity) = parse_str_type(name) {
+                1 + capacity
+            } else if let Some((item, capacity)) = parse_vec_type(name) {
+                1 + type_name_size(item, types) * capacity
+            } else {
+                panic!("Unsupported parameter type: {name}");
+            }
+        }
+    }
+}
+
+/// Return true if the Rust type of a type name implements `Copy`
+pub fn is_copy_type(name: &str, types: &[TypeDecl<'_>]) -> bool {
+    if let Some(decl) = find_type(name, types) {
+        match &decl.def {
+            TypeDef::Enum(_) | TypeDef::Flags(_) => true,
+            TypeDef::Struct(fields) => fields.iter().all(|(_, typ)| is_copy_type(typ, types)),
+        }
+    } else if let Some((item, _)) = parse_array_type(name) {
+        is_copy_type(item, types)
+    } else {
+        parse_str_type(name).is_none() && parse_vec_type(name).is_none()
+    }
+}
+
+pub fn find_type<'a, 'b>(name: &str, types: &'b [TypeDecl<'a>]) -> Option<&'b TypeDecl<'a>> {
+    types.iter().find(|decl| decl.name == name)
+}
+
+/// Return the type name used to store bits of a flags type
+pub fn flags_bits_type(flags: &[&str]) -> &'static str {
+    match flags.len() {
+        0..=8 => "u8",
+        9..=16 => "u16",
+        _ => "u32",
+    }
+}
+
+/// Parse a fixed-size array type: `[T; N]`
+pub fn parse_array_type(name: &str) -> Option<(&str, usize)> {
+    let (item, count) = name.strip_prefix('[')?.strip_suffix(']')?.rsplit_once(';')?;
+    let count = count.trim().parse()
+        .unwrap_or_else(|_| panic!("Invalid array size in parameter type: {name}"));
+    Some((item.trim(), count))
+}
+
+/// Parse a bounded string type: `str<N>`
+pub fn parse_str_type(name: &str) -> Option<usize> {
+    let capacity = name.strip_prefix("str<")?.strip_suffix('>')?;
+    Some(parse_bounded_capacity(name, capacity))
+}
+
+/// Parse a bounded array type: `vec<T, N>`
+pub fn parse_vec_type(name: &str) -> Option<(&str, usize)> {
+    let (item, capacity) = name.strip_prefix("vec<")?.strip_suffix('>')?.rsplit_once(',')?;
+    Some((item.trim(), parse_bounded_capacity(name, capacity)))
+}
+
+/// Parse capacity of variable-length types, length is encoded on 8 bits
+pub fn parse_bounded_capacity(name: &str, capacity: &str) -> usize {
+    let capacity = capacity.trim().parse()
+        .unwrap_or_else(|_| panic!("Invalid capacity in parameter type: {name}"));
+    if capacity > u8::MAX as usize {
+        panic!("Invalid capacity in parameter type: {name}, maximum is {}", u8::MAX);
+    }
+    capacity
+}
+
+pub fn load_yaml_doc<P: AsRef<Path>>(path: P) -> Yaml {
+    let mut contents = String::new();
+    let mut file = File::open(path).unwrap();
+    file.read_to_string(&mut contents).unwrap();
+
+    let mut docs = YamlLoader::load_from_str(&contents).unwrap();
+    if docs.len() != 1 {
+        panic!("Unexpected document count in YAML files, expect 1 document, got {}", docs.len());
+    }
+    docs.pop().unwrap()
+}
@@ -0,0 +1,379 @@
+//! Check compatibility between two revisions of `rome_messages.yaml`
+//!
+//! Usage: `rome-schema-check OLD.yaml NEW.yaml`
+//!
+//! Breaking changes are changes that prevent peers using the old revision to
+//! communicate with peers using the new one. The exit code is 1 if any is found,
+//! 2 if a schema is invalid.
+//!
+//! To compare with a committed revision:
+//! ```text
+//! git show HEAD:code/libs/rome/rome_messages.yaml > /tmp/old.yaml
+//! cargo run -p rome --features schema-check -- /tmp/old.yaml code/libs/rome/rome_messages.yaml
+//! ```
+
+use std::process::ExitCode;
+
+#[path = "../../schema.rs"]
+mod schema;
+use schema::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Severity {
+    /// Old and new peers cannot communicate
+    Breaking,
+    /// Wire format is compatible, but the change may be worth a look
+    Compatible,
+}
+
+#[derive(Debug)]
+struct Change {
+    severity: Severity,
+    description: String,
+}
+
+impl Change {
+    fn breaking(description: String) -> Self {
+        Self { severity: Severity::Breaking, description }
+    }
+
+    fn compatible(description: String) -> Self {
+        Self { severity: Severity::Compatible, description }
+    }
+}
+
+/// Parsed schema, with its declared types
+struct Schema<'a> {
+    types: Vec<TypeDecl<'a>>,
+    messages: Vec<Message<'a>>,
+}
+
+impl<'a> Schema<'a> {
+    fn parse(doc: &'a yaml_rust::Yaml) -> Self {
+        let (types, messages) = parse_message_doc(doc);
+        Self { types, messages }
+    }
+
+    fn find_by_name(&self, name: &str) -> Option<&Message<'a>> {
+        self.messages.iter().find(|message| message.name == name)
+    }
+
+    fn find_by_id(&self, id: u8) -> Option<&Message<'a>> {
+        self.messages.iter().find(|message| message.id == id)
+    }
+
+    /// Return message parameters as a list of (optional name, parameter type)
+    fn parameters(message: &'a Message<'a>) -> Vec<(Option<&'a str>, &'a ParamType<'a>)> {
+        match &message.parameters {
+            Parameters::None => vec![],
+            Parameters::Positional(params) => params.iter().map(|typ| (None, typ)).collect(),
+            Parameters::Named(params) => params.iter().map(|(name, typ)| (Some(*name), typ)).collect(),
+        }
+    }
+
+    fn type_signature(&self, typ: &ParamType<'_>) -> String {
+        param_type_signature(typ, &self.types)
+    }
+
+    /// Return values of a choice or an enum type, `None` for other types
+    fn choices(&self, typ: &'a ParamType<'a>) -> Option<&[&'a str]> {
+        match typ {
+            ParamType::Choice(choices) => Some(choices),
+            ParamType::Name(name) => match find_type(name, &self.types) {
+                Some(TypeDecl { def: TypeDef::Enum(choices), .. }) => Some(choices),
+                _ => None,
+            },
+        }
+    }
+}
+
+
+/// Compare two schemas, return the list of changes
+fn check_compatibility(old: &Schema<'_>, new: &Schema<'_>) -> Vec<Change> {
+    let mut changes = Vec::new();
+
+    for old_message in &old.messages {
+        let name = old_message.name;
+        let Some(new_message) = new.find_by_name(name) else {
+            match renamed_message(old, old_message, new) {
+                Some(new_message) => changes.push(Change::compatible(format!("message {name} renamed to {} (ID {})", new_message.name, old_message.id))),
+                None => changes.push(Change::breaking(format!("message {name} removed (ID {})", old_message.id))),
+            }
+            continue;
+        };
+        if new_message.id != old_message.id {
+            changes.push(Change::breaking(format!("message {name} renumbered from ID {} to {}", old_message.id, new_message.id)));
+        }
+        check_message_parameters(old, old_message, new, new_message, &mut changes);
+    }
+
+    for new_message in &new.messages {
+        let is_renamed = old.messages.iter()
+            .filter(|old_message| new.find_by_name(old_message.name).is_none())
+            .any(|old_message| renamed_message(old, old_message, new).is_some_and(|m| m.name == new_message.name));
+        if old.find_by_name(new_message.name).is_none() && !is_renamed {
+            changes.push(Change::compatible(format!("message {} added (ID {})", new_message.name, new_message.id)));
+        }
+    }
+
+    changes
+}
+
+fn check_message_parameters(old: &Schema<'_>, old_message: &Message<'_>, new: &Schema<'_>, new_message: &Message<'_>, changes: &mut Vec<Change>) {
+    let name = old_message.name;
+    let old_params = Schema::parameters(old_message);
+    let new_params = Schema::parameters(new_message);
+
+    let is_named = |message: &Message| matches!(message.parameters, Parameters::Named(_));
+    if is_named(old_message) != is_named(new_message) {
+        changes.push(Change::compatible(format!("message {name}: parameters changed between named and positional")));
+    }
+
+    for (i, ((old_name, old_type), (new_name, new_type))) in old_params.iter().zip(new_params.iter()).enumerate() {
+        let field = old_name.map(str::to_string).unwrap_or_else(|| format!("#{i}"));
+        if let (Some(old_name), Some(new_name)) = (old_name, new_name) && old_name != new_name {
+            if new_params.iter().any(|(param_name, _)| param_name == &Some(*old_name)) {
+                changes.push(Change::breaking(format!("message {name}: field {old_name} moved")));
+                continue;
+            } else {
+                changes.push(Change::compatible(format!("message {name}: field {old_name} renamed to {new_name}")));
+            }
+        }
+
+        if old_message.default_value(i).is_some() && new_message.default_value(i).is_none() {
+            changes.push(Change::breaking(format!("message {name}: field {field} is no longer optional")));
+        }
+
+        let old_signature = old.type_signature(old_type);
+        let new_signature = new.type_signature(new_type);
+        if old_signature == new_signature {
+            continue;
+        }
+        match (old.choices(old_type), new.choices(new_type)) {
+            (Some(old_choices), Some(new_choices)) if new_choices.starts_with(old_choices) => {
+                let added = new_choices[old_choices.len()..].join(", ");
+                changes.push(Change::compatible(format!("message {name}: field {field}: choices added: {added} (must not be sent to old peers)")));
+            }
+            (Some(old_choices), Some(new_choices)) => {
+                let removed = old_choices.iter()
+                    .enumerate()
+                    .filter(|(i, choice)| new_choices.get(*i) != Some(choice))
+                    .map(|(_, choice)| *choice)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                changes.push(Change::breaking(format!("message {name}: field {field}: choices removed or reordered: {removed}")));
+            }
+            _ => {
+                changes.push(Change::breaking(format!("message {name}: field {field}: type changed from {old_signature} to {new_signature}")));
+            }
+        }
+    }
+
+    // Trailing fields can be added or removed if they are optional
+    if new_params.len() < old_params.len() {
+        let (removed, optional) = trailing_fields(old_message, &old_params, new_params.len());
+        if optional {
+            changes.push(Change::compatible(format!("message {name}: optional field(s) removed: {removed}")));
+        } else {
+            changes.push(Change::breaking(format!("message {name}: field(s) removed: {removed}")));
+        }
+    } else if new_params.len() > old_params.len() {
+        let (added, optional) = trailing_fields(new_message, &new_params, old_params.len());
+        if optional {
+            changes.push(Change::compatible(format!("message {name}: optional field(s) added: {added}")));
+        } else {
+            changes.push(Change::breaking(format!("message {name}: required field(s) added: {added}")));
+        }
+    }
+}
+
+/// Return names of fields starting at given index, and whether they are all optional
+fn trailing_fields(message: &Message<'_>, params: &[(Option<&str>, &ParamType<'_>)], start: usize) -> (String, bool) {
+    let names = (start..params.len())
+        .map(|i| params[i].0.map(str::to_string).unwrap_or_else(|| format!("#{i}")))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let optional = (start..params.len()).all(|i| message.default_value(i).is_some());
+    (names, optional)
+}
+
+/// Return the new message an old message has been renamed to, if any
+///
+/// A message is considered renamed if its ID and parameters are unchanged.
+fn renamed_message<'a, 'b>(old: &Schema<'_>, old_message: &Message<'_>, new: &'b Schema<'a>) -> Option<&'b Message<'a>> {
+    new.find_by_id(old_message.id)
+        .filter(|new_message| old.find_by_name(new_message.name).is_none())
+        .filter(|new_message| message_signature_without_name(old, old_message) == message_signature_without_name(new, new_message))
+}
+
+/// Return the signature of a message, without its name (nor its ID)
+fn message_signature_without_name(schema: &Schema<'_>, message: &Message<'_>) -> String {
+    let signature = message_signature(message, &schema.types);
+    let prefix_len = format!("{}:{}", message.id, message.name).len();
+    signature[prefix_len..].to_string()
+}
+
+
+/// Load and parse a schema file, return `None` if it is invalid
+///
+/// The parser panics on invalid declarations, with an explicit message.
+fn load_schema(path: &str) -> Option<yaml_rust::Yaml> {
+    let doc = std::panic::catch_unwind(|| load_yaml_doc(path)).ok()?;
+    std::panic::catch_unwind(|| { Schema::parse(&doc); }).ok()?;
+    Some(doc)
+}
+
+fn main() -> ExitCode {
+    let args = std::env::args().collect::<Vec<_>>();
+    let [_, old_path, new_path] = &args[..] else {
+        eprintln!("usage: rome-schema-check OLD.yaml NEW.yaml");
+        return ExitCode::from(2);
+    };
+
+    let Some(old_doc) = load_schema(old_path) else {
+        eprintln!("error: invalid schema: {old_path}");
+        return ExitCode::from(2);
+    };
+    let Some(new_doc) = load_schema(new_path) else {
+        eprintln!("error: invalid schema: {new_path}");
+        return ExitCode::from(2);
+    };
+    let old = Schema::parse(&old_doc);
+    let new = Schema::parse(&new_doc);
+
+    let changes = check_compatibility(&old, &new);
+    for change in &changes {
+        let prefix = match change.severity {
+            Severity::Breaking => "BREAKING",
+            Severity::Compatible => "compatible",
+        };
+        println!("{prefix}: {}", change.description);
+    }
+
+    let old_hash = schema_hash(&old.messages, &old.types);
+    let new_hash = schema_hash(&new.messages, &new.types);
+    println!("schema hash: 0x{old_hash:08x} -> 0x{new_hash:08x}");
+
+    if changes.iter().any(|change| change.severity == Severity::Breaking) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "
+types:
+  Team:
+    enum: [none, left, right]
+10:
+  BatteryLevel:
+    mv: u16
+    percent: u8
+  MatchTm:
+    team: Team
+    time_ms: u32
+20:
+  AsservGotoXy:
+    x: f32
+    y: f32
+";
+
+    fn check(old: &str, new: &str) -> Vec<(Severity, String)> {
+        let old_doc = yaml_rust::YamlLoader::load_from_str(old).unwrap().pop().unwrap();
+        let new_doc = yaml_rust::YamlLoader::load_from_str(new).unwrap().pop().unwrap();
+        check_compatibility(&Schema::parse(&old_doc), &Schema::parse(&new_doc))
+            .into_iter()
+            .map(|change| (change.severity, change.description))
+            .collect()
+    }
+
+    fn breaking(changes: &[(Severity, String)]) -> Vec<&str> {
+        changes.iter().filter(|(severity, _)| *severity == Severity::Breaking).map(|(_, s)| s.as_str()).collect()
+    }
+
+    #[test]
+    fn no_change() {
+        assert!(check(BASE, BASE).is_empty());
+    }
+
+    #[test]
+    fn message_appended() {
+        let new = format!("{BASE}  AsservGotoA:\n    a: f32\n");
+        assert_eq!(check(BASE, &new), [(Severity::Compatible, "message AsservGotoA added (ID 21)".to_string())]);
+    }
+
+    #[test]
+    fn message_inserted() {
+        let new = BASE.replace("  AsservGotoXy:", "  AsservGotoA:\n    a: f32\n  AsservGotoXy:");
+        // Inserted before AsservGotoXy, which is renumbered
+        let changes = check(BASE, &new);
+        assert_eq!(breaking(&changes), ["message AsservGotoXy renumbered from ID 20 to 21"]);
+        assert!(changes.contains(&(Severity::Compatible, "message AsservGotoA added (ID 20)".to_string())));
+    }
+
+    #[test]
+    fn message_removed_or_renamed() {
+        let new = BASE.replace("  BatteryLevel:\n    mv: u16\n    percent: u8\n", "");
+        assert!(breaking(&check(BASE, &new)).contains(&"message BatteryLevel removed (ID 10)"));
+
+        let new = BASE.replace("BatteryLevel:", "Battery:");
+        assert_eq!(check(BASE, &new), [(Severity::Compatible, "message BatteryLevel renamed to Battery (ID 10)".to_string())]);
+    }
+
+    #[test]
+    fn field_changes() {
+        let new = BASE.replace("mv: u16", "mv: u32");
+        assert_eq!(breaking(&check(BASE, &new)), ["message BatteryLevel: field mv: type changed from u16 to u32"]);
+
+        let new = BASE.replace("    mv: u16\n    percent: u8\n", "    percent: u8\n    mv: u16\n");
+        assert_eq!(breaking(&check(BASE, &new)), ["message BatteryLevel: field mv moved", "message BatteryLevel: field percent moved"]);
+
+        let new = BASE.replace("    time_ms: u32\n", "");
+        assert_eq!(breaking(&check(BASE, &new)), ["message MatchTm: field(s) removed: time_ms"]);
+
+        let new = BASE.replace("mv: u16", "millivolts: u16");
+        assert_eq!(check(BASE, &new), [(Severity::Compatible, "message BatteryLevel: field mv renamed to millivolts".to_string())]);
+    }
+
+    #[test]
+    fn optional_fields() {
+        let new = BASE.replace("    percent: u8\n", "    percent: u8\n    temperature: {type: i8, default: 0}\n");
+        assert_eq!(check(BASE, &new), [(Severity::Compatible, "message BatteryLevel: optional field(s) added: temperature".to_string())]);
+        assert_eq!(check(&new, BASE), [(Severity::Compatible, "message BatteryLevel: optional field(s) removed: temperature".to_string())]);
+
+        let new = BASE.replace("    percent: u8\n", "    percent: u8\n    temperature: i8\n");
+        assert_eq!(breaking(&check(BASE, &new)), ["message BatteryLevel: required field(s) added: temperature"]);
+
+        let old = BASE.replace("percent: u8", "percent: {type: u8, default: 100}");
+        assert!(check(BASE, &old).is_empty());
+        assert_eq!(breaking(&check(&old, BASE)), ["message BatteryLevel: field percent is no longer optional"]);
+    }
+
+    #[test]
+    fn choice_changes() {
+        let new = BASE.replace("[none, left, right]", "[none, left, right, both]");
+        let changes = check(BASE, &new);
+        assert!(breaking(&changes).is_empty());
+        assert_eq!(changes.len(), 1);
+
+        let new = BASE.replace("[none, left, right]", "[none, right]");
+        assert_eq!(breaking(&check(BASE, &new)), ["message MatchTm: field team: choices removed or reordered: left, right"]);
+    }
+
+    #[test]
+    fn group_overflow() {
+        let new = BASE.replace("10:\n", "19:\n");
+        assert!(std::panic::catch_unwind(|| check(BASE, &new)).is_err());
+    }
+
+    #[test]
+    fn current_schema_is_valid() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/rome_messages.yaml");
+        assert!(load_schema(path).is_some());
+    }
+}
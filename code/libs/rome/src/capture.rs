@@ -0,0 +1,309 @@
+//! Capture files, to record exchanged ROME messages and replay them
+//!
+//! A capture starts with a header, followed by records:
+//! ```text
+//! header: "ROMECAP" | version (u8) | schema_hash (u32)
+//! record: timestamp_us (u64) | direction (u8) | size (u16) | encoded message
+//! ```
+//! Integers are little endian. Timestamps are relative to the start of the capture.
+//! Messages are recorded as sent or received, possibly in a sequenced envelope
+//! (see [crate::reliable]).
+
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+use crate::{DecodeError, Message, SCHEMA_HASH};
+use crate::reliable::split_envelope;
+
+/// Magic value at the beginning of capture files
+pub const CAPTURE_MAGIC: &[u8; 7] = b"ROMECAP";
+
+/// Version of the capture format
+pub const CAPTURE_VERSION: u8 = 1;
+
+const HEADER_SIZE: usize = CAPTURE_MAGIC.len() + 1 + 4;
+const RECORD_HEADER_SIZE: usize = 8 + 1 + 2;
+
+
+/// Direction of a recorded message, from the point of view of the recorder
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Direction {
+    Rx = 0,
+    Tx = 1,
+}
+
+impl TryFrom<u8> for Direction {
+    type Error = CaptureError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Rx),
+            1 => Ok(Self::Tx),
+            n => Err(CaptureError::BadDirection(n)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    /// Invalid magic value or unsupported version
+    BadHeader,
+    /// Invalid record direction
+    BadDirection(u8),
+    /// Capture ends in the middle of a record
+    Truncated,
+    /// Recorded message cannot be decoded
+    Decode(DecodeError),
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(value: io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<DecodeError> for CaptureError {
+    fn from(value: DecodeError) -> Self {
+        Self::Decode(value)
+    }
+}
+
+
+/// Recorded message, not decoded
+#[derive(Debug, PartialEq, Eq)]
+pub struct Record {
+    pub timestamp_us: u64,
+    pub direction: Direction,
+    pub data: Box<[u8]>,
+}
+
+impl Record {
+    /// Decode the recorded message, drop its envelope if any
+    pub fn decode(&self) -> Result<Message, DecodeError> {
+        let (_, data) = split_envelope(&self.data);
+        Message::decode(data)
+    }
+}
+
+/// Recorded message, decoded
+#[derive(Debug)]
+pub struct CapturedMessage {
+    pub timestamp_us: u64,
+    pub direction: Direction,
+    pub message: Message,
+}
+
+
+/// Write messages to a capture
+///
+/// Messages are timestamped when recorded, relatively to the writer creation.
+/// Data sent to or received from a `Sender<Box<[u8]>>` can be passed as is.
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    start: Instant,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Create a writer, write the capture header
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(CAPTURE_MAGIC)?;
+        writer.write_all(&[CAPTURE_VERSION])?;
+        writer.write_all(&SCHEMA_HASH.to_le_bytes())?;
+        Ok(Self { writer, start: Instant::now() })
+    }
+
+    /// Record encoded message data, timestamped now
+    pub fn record(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let timestamp_us = self.start.elapsed().as_micros() as u64;
+        self.record_at(timestamp_us, direction, data)
+    }
+
+    /// Record encoded message data, with an explicit timestamp
+    pub fn record_at(&mut self, timestamp_us: u64, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let size = u16::try_from(data.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "recorded data is too large"))?;
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        header[..8].copy_from_slice(&timestamp_us.to_le_bytes());
+        header[8] = direction as u8;
+        header[9..].copy_from_slice(&size.to_le_bytes());
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Return the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+
+/// Read messages from a capture
+///
+/// Iterate on decoded messages. Use [CaptureReader::records()] to get raw records.
+pub struct CaptureReader<R: Read> {
+    reader: R,
+    schema_hash: u32,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Create a reader, read the capture header
+    pub fn new(mut reader: R) -> Result<Self, CaptureError> {
+        let mut header = [0u8; HEADER_SIZE];
+        if !read_exact_or_eof(&mut reader, &mut header)? {
+            return Err(CaptureError::BadHeader);
+        }
+        let (magic, header) = header.split_at(CAPTURE_MAGIC.len());
+        if magic != CAPTURE_MAGIC || header[0] != CAPTURE_VERSION {
+            return Err(CaptureError::BadHeader);
+        }
+        let schema_hash = u32::from_le_bytes(header[1..].try_into().unwrap());
+        Ok(Self { reader, schema_hash })
+    }
+
+    /// Schema hash of the recorder, see [crate::check_schema_hash()]
+    pub fn schema_hash(&self) -> u32 {
+        self.schema_hash
+    }
+
+    /// Read the next record, return `None` at the end of the capture
+    pub fn read_record(&mut self) -> Result<Option<Record>, CaptureError> {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+        let timestamp_us = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let direction = Direction::try_from(header[8])?;
+        let size = u16::from_le_bytes(header[9..].try_into().unwrap());
+        let mut data = vec![0u8; size as usize];
+        if !read_exact_or_eof(&mut self.reader, &mut data)? && size != 0 {
+            return Err(CaptureError::Truncated);
+        }
+        Ok(Some(Record { timestamp_us, direction, data: data.into_boxed_slice() }))
+    }
+
+    /// Iterate on raw records
+    pub fn records(mut self) -> impl Iterator<Item = Result<Record, CaptureError>> {
+        std::iter::from_fn(move || self.read_record().transpose())
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = Result<CapturedMessage, CaptureError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let record = match self.read_record() {
+            Ok(record) => record?,
+            Err(err) => return Some(Err(err)),
+        };
+        Some(record.decode()
+            .map(|message| CapturedMessage { timestamp_us: record.timestamp_us, direction: record.direction, message })
+            .map_err(CaptureError::from))
+    }
+}
+
+/// Read exactly enough data to fill the buffer
+///
+/// Return `false` if the reader is at the end of its data, fail if data is truncated.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<bool, CaptureError> {
+    let mut pos = 0;
+    while pos < buffer.len() {
+        match reader.read(&mut buffer[pos..]) {
+            Ok(0) if pos == 0 => return Ok(false),
+            Ok(0) => return Err(CaptureError::Truncated),
+            Ok(n) => pos += n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(true)
+}
+
+
+/// Replay a capture with its original timing
+///
+/// Records are passed to `emit` at their recorded time, relatively to the call.
+/// Replay stops early if `emit` returns `false` (e.g. when a channel is closed):
+/// ```no_run
+/// # use rome::capture::{CaptureReader, replay};
+/// # let file = std::fs::File::open("match.romecap").unwrap();
+/// # let (tx, rx) = std::sync::mpsc::channel::<Box<[u8]>>();
+/// let reader = CaptureReader::new(std::io::BufReader::new(file)).unwrap();
+/// replay(reader, |record| tx.send(record.data).is_ok()).unwrap();
+/// ```
+pub fn replay<R: Read>(reader: CaptureReader<R>, mut emit: impl FnMut(Record) -> bool) -> Result<(), CaptureError> {
+    let start = Instant::now();
+    for record in reader.records() {
+        let record = record?;
+        let target = start + Duration::from_micros(record.timestamp_us);
+        if let Some(duration) = target.checked_duration_since(Instant::now()) {
+            std::thread::sleep(duration);
+        }
+        if !emit(record) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reliable::encode_envelope;
+
+    fn sample_capture() -> Vec<u8> {
+        let mut writer = CaptureWriter::new(Vec::new()).unwrap();
+        writer.record_at(0, Direction::Rx, &Message::AsservGotoA { a: 1.5 }.encode()).unwrap();
+        writer.record_at(1500, Direction::Tx, &Message::BatteryLevel { mv: 12000, percent: 80 }.encode()).unwrap();
+        writer.record_at(3000, Direction::Rx, &encode_envelope(7, &Message::AsservGotoXy { x: 1.0, y: 2.0 })).unwrap();
+        writer.into_inner()
+    }
+
+    #[test]
+    fn capture_roundtrip() {
+        let data = sample_capture();
+        assert_eq!(&data[..7], CAPTURE_MAGIC);
+
+        let reader = CaptureReader::new(&data[..]).unwrap();
+        assert_eq!(reader.schema_hash(), SCHEMA_HASH);
+        let messages = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(matches!(messages[..], [
+            CapturedMessage { timestamp_us: 0, direction: Direction::Rx, message: Message::AsservGotoA { a: 1.5 } },
+            CapturedMessage { timestamp_us: 1500, direction: Direction::Tx, message: Message::BatteryLevel { mv: 12000, percent: 80 } },
+            CapturedMessage { timestamp_us: 3000, direction: Direction::Rx, message: Message::AsservGotoXy { x: 1.0, y: 2.0 } },
+        ]));
+    }
+
+    #[test]
+    fn capture_bad_header() {
+        assert!(matches!(CaptureReader::new(&b"ROMECAQ\x01\0\0\0\0"[..]), Err(CaptureError::BadHeader)));
+        assert!(matches!(CaptureReader::new(&b"ROME"[..]), Err(CaptureError::Truncated)));
+        assert!(matches!(CaptureReader::new(&b""[..]), Err(CaptureError::BadHeader)));
+    }
+
+    #[test]
+    fn capture_truncated() {
+        let data = sample_capture();
+        let mut records = CaptureReader::new(&data[..data.len() - 1]).unwrap().records();
+        assert!(records.next().unwrap().is_ok());
+        assert!(records.next().unwrap().is_ok());
+        assert!(matches!(records.next(), Some(Err(CaptureError::Truncated))));
+    }
+
+    #[test]
+    fn capture_replay() {
+        let data = sample_capture();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let start = Instant::now();
+        replay(CaptureReader::new(&data[..]).unwrap(), |record| tx.send(record.data).is_ok()).unwrap();
+        assert!(start.elapsed() >= Duration::from_micros(3000));
+        let received = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[1], Message::BatteryLevel { mv: 12000, percent: 80 }.encode());
+    }
+}
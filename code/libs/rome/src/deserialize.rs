@@ -5,6 +5,8 @@ use crate::DecodeError;
 pub trait Reader {
     /// Read data, panic if there is not enough data to read
     fn read(&mut self, bytes: &mut [u8]) -> Result<(), DecodeError>;
+    /// Return true if there is no more data to read
+    fn is_at_end(&self) -> bool;
 }
 
 
@@ -15,6 +17,10 @@ impl Reader for &[u8] {
         *self = b;
         Ok(())
     }
+
+    fn is_at_end(&self) -> bool {
+        self.is_empty()
+    }
 }
 
 
@@ -93,3 +99,35 @@ impl<T: Deserialize, const N: usize> Deserialize for [T; N] {
     }
 }
 
+
+impl<const N: usize> Deserialize for heapless::String<N> {
+    fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {
+        let len = u8::deserialize(reader)?;
+        if len as usize > N {
+            return Err(DecodeError::BadLength(len));
+        }
+        let mut buffer = [0u8; N];
+        let buffer = &mut buffer[..len as usize];
+        reader.read(buffer)?;
+        let s = core::str::from_utf8(buffer).map_err(|_| DecodeError::BadString)?;
+        let mut result = Self::new();
+        // Length has been checked, cannot fail
+        let _ = result.push_str(s);
+        Ok(result)
+    }
+}
+
+impl<T: Deserialize, const N: usize> Deserialize for heapless::Vec<T, N> {
+    fn deserialize<R: Reader>(reader: &mut R) -> Result<Self, DecodeError> {
+        let len = u8::deserialize(reader)?;
+        if len as usize > N {
+            return Err(DecodeError::BadLength(len));
+        }
+        let mut result = Self::new();
+        for _ in 0..len {
+            // Length has been checked, cannot fail
+            let _ = result.push(T::deserialize(reader)?);
+        }
+        Ok(result)
+    }
+}
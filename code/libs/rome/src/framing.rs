@@ -0,0 +1,370 @@
+//! Byte-stream framing, to carry ROME messages over UART or TCP
+//!
+//! Each frame is the encoded message followed by its CRC16 (little endian),
+//! COBS-encoded and terminated by a `0x00` delimiter:
+//! ```text
+//! COBS(message | crc16) | 0x00
+//! ```
+//! CRC is CRC-16/CCITT-FALSE (polynomial `0x1021`, initial value `0xFFFF`).
+//!
+//! Since `0x00` never appears inside a COBS-encoded frame, a decoder can always
+//! resynchronize on the next delimiter after garbage or a truncated frame.
+//!
+//! Frames may also carry raw data instead of a message, for instance a message
+//! in an envelope (see [crate::reliable]).
+
+use crate::{DecodeError, EncodeError, Message};
+use crate::reliable::MAX_ENVELOPE_SIZE;
+
+/// Frame delimiter
+pub const FRAME_DELIMITER: u8 = 0x00;
+
+/// Size of the CRC trailer
+const CRC_SIZE: usize = 2;
+
+/// Maximum size of a frame payload, before COBS encoding
+const MAX_PAYLOAD_SIZE: usize = MAX_ENVELOPE_SIZE + CRC_SIZE;
+
+/// Maximum size of an encoded frame, including the delimiter
+pub const MAX_FRAME_SIZE: usize = cobs_max_encoded_size(MAX_PAYLOAD_SIZE) + 1;
+
+
+/// Compute a CRC-16/CCITT-FALSE
+pub fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0xFFFF, data)
+}
+
+fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+
+/// Encode a message as a frame into given buffer, return the frame size
+///
+/// Buffer is guaranteed to be large enough if its size is at least [MAX_FRAME_SIZE].
+pub fn encode_frame_into(message: &Message, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+    let size = message.encode_into(&mut payload)?;
+    encode_payload_into(&mut payload, size, buffer)
+}
+
+/// Encode raw data as a frame into given buffer, return the frame size
+///
+/// Buffer is guaranteed to be large enough if its size is at least [MAX_FRAME_SIZE]
+/// and data is not larger than an encoded envelope.
+pub fn encode_data_frame_into(data: &[u8], buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    if data.len() > MAX_ENVELOPE_SIZE {
+        return Err(EncodeError::BufferOverflow);
+    }
+    let mut payload = [0u8; MAX_PAYLOAD_SIZE];
+    payload[..data.len()].copy_from_slice(data);
+    encode_payload_into(&mut payload, data.len(), buffer)
+}
+
+/// Append CRC to the first `size` bytes of payload, then encode them into buffer
+fn encode_payload_into(payload: &mut [u8; MAX_PAYLOAD_SIZE], size: usize, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let crc = crc16(&payload[..size]);
+    payload[size..size + CRC_SIZE].copy_from_slice(&crc.to_le_bytes());
+
+    let encoded_size = cobs_encode(&payload[..size + CRC_SIZE], buffer)?;
+    *buffer.get_mut(encoded_size).ok_or(EncodeError::BufferOverflow)? = FRAME_DELIMITER;
+    Ok(encoded_size + 1)
+}
+
+/// Encode a message as a frame
+#[cfg(feature = "std")]
+pub fn encode_frame(message: &Message) -> Box<[u8]> {
+    let mut buffer = [0u8; MAX_FRAME_SIZE];
+    let size = encode_frame_into(message, &mut buffer).expect("buffer is large enough for any message");
+    buffer[..size].into()
+}
+
+/// Encode raw data as a frame
+#[cfg(feature = "std")]
+pub fn encode_data_frame(data: &[u8]) -> Result<Box<[u8]>, EncodeError> {
+    let mut buffer = [0u8; MAX_FRAME_SIZE];
+    let size = encode_data_frame_into(data, &mut buffer)?;
+    Ok(buffer[..size].into())
+}
+
+
+/// Incremental frame decoder
+///
+/// Bytes are pushed as they are received. A decoded message (or an error) is
+/// returned each time a frame delimiter is reached. Empty frames (consecutive
+/// delimiters) are silently ignored.
+pub struct FrameDecoder {
+    buffer: [u8; MAX_FRAME_SIZE],
+    len: usize,
+    overflow: bool,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FrameDecoder {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; MAX_FRAME_SIZE],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Drop pending data, wait for the next frame
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.overflow = false;
+    }
+
+    /// Push a single byte, return a result when a frame is complete
+    pub fn push(&mut self, byte: u8) -> Option<Result<Message, DecodeError>> {
+        self.push_with(byte, Message::decode)
+    }
+
+    /// Push received data, iterate on completed frames
+    pub fn feed<'a>(&'a mut self, data: &'a [u8]) -> impl Iterator<Item = Result<Message, DecodeError>> + 'a {
+        data.iter().filter_map(move |byte| self.push(*byte))
+    }
+
+    /// Push a single byte, return raw frame data when a frame is complete
+    #[cfg(feature = "std")]
+    pub fn push_data(&mut self, byte: u8) -> Option<Result<Box<[u8]>, DecodeError>> {
+        self.push_with(byte, |data| Ok(data.into()))
+    }
+
+    /// Push received data, iterate on raw data of completed frames
+    #[cfg(feature = "std")]
+    pub fn feed_data<'a>(&'a mut self, data: &'a [u8]) -> impl Iterator<Item = Result<Box<[u8]>, DecodeError>> + 'a {
+        data.iter().filter_map(move |byte| self.push_data(*byte))
+    }
+
+    /// Push a single byte, decode frame data with `decode` when a frame is complete
+    fn push_with<T>(&mut self, byte: u8, decode: impl FnOnce(&[u8]) -> Result<T, DecodeError>) -> Option<Result<T, DecodeError>> {
+        if byte == FRAME_DELIMITER {
+            let result = if self.overflow {
+                Some(Err(DecodeError::FrameTooLong))
+            } else if self.len == 0 {
+                None
+            } else {
+                Some(self.decode_frame(decode))
+            };
+            self.reset();
+            result
+        } else {
+            if self.len < self.buffer.len() {
+                self.buffer[self.len] = byte;
+                self.len += 1;
+            } else {
+                // Drop everything up to the next delimiter
+                self.overflow = true;
+            }
+            None
+        }
+    }
+
+    fn decode_frame<T>(&mut self, decode: impl FnOnce(&[u8]) -> Result<T, DecodeError>) -> Result<T, DecodeError> {
+        let mut payload = [0u8; MAX_FRAME_SIZE];
+        let size = cobs_decode(&self.buffer[..self.len], &mut payload)?;
+        if size < CRC_SIZE {
+            return Err(DecodeError::BadFrame);
+        }
+        let (data, crc) = payload[..size].split_at(size - CRC_SIZE);
+        if crc16(data).to_le_bytes() != crc {
+            return Err(DecodeError::BadChecksum);
+        }
+        decode(data)
+    }
+}
+
+
+/// Return the maximum size of COBS-encoded data (without delimiter)
+const fn cobs_max_encoded_size(size: usize) -> usize {
+    size + size / 254 + 1
+}
+
+/// COBS-encode data into output, return the encoded size
+fn cobs_encode(data: &[u8], output: &mut [u8]) -> Result<usize, EncodeError> {
+    if output.len() < cobs_max_encoded_size(data.len()) {
+        return Err(EncodeError::BufferOverflow);
+    }
+
+    let mut code_pos = 0;
+    let mut pos = 1;
+    let mut code = 1u8;
+    for byte in data {
+        if *byte == 0 {
+            output[code_pos] = code;
+            code_pos = pos;
+            pos += 1;
+            code = 1;
+        } else {
+            output[pos] = *byte;
+            pos += 1;
+            code += 1;
+            if code == 0xFF {
+                output[code_pos] = code;
+                code_pos = pos;
+                pos += 1;
+                code = 1;
+            }
+        }
+    }
+    output[code_pos] = code;
+    Ok(pos)
+}
+
+/// Decode COBS-encoded data (without delimiter) into output, return the decoded size
+fn cobs_decode(data: &[u8], output: &mut [u8]) -> Result<usize, DecodeError> {
+    let mut pos = 0;
+    let mut out_pos = 0;
+    while pos < data.len() {
+        let code = data[pos] as usize;
+        if code == 0 {
+            return Err(DecodeError::BadFrame);
+        }
+        let block = data.get(pos + 1..pos + code).ok_or(DecodeError::BadFrame)?;
+        output.get_mut(out_pos..out_pos + block.len()).ok_or(DecodeError::BadFrame)?.copy_from_slice(block);
+        out_pos += block.len();
+        pos += code;
+        // A zero is implied after each block, except for maximum-size blocks and the last one
+        if code < 0xFF && pos < data.len() {
+            *output.get_mut(out_pos).ok_or(DecodeError::BadFrame)? = 0;
+            out_pos += 1;
+        }
+    }
+    Ok(out_pos)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_all(decoder: &mut FrameDecoder, data: &[u8]) -> Vec<Result<Message, DecodeError>> {
+        decoder.feed(data).collect()
+    }
+
+    #[test]
+    fn crc16_check_value() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn cobs_roundtrip() {
+        let cases: [&[u8]; 5] = [
+            &[],
+            &[0],
+            &[0, 0],
+            &[1, 2, 0, 3],
+            &[0x11; 300],
+        ];
+        for data in cases {
+            let mut encoded = [0u8; 310];
+            let size = cobs_encode(data, &mut encoded).unwrap();
+            assert!(!encoded[..size].contains(&0), "zero in encoded data for {data:?}");
+            let mut decoded = [0u8; 310];
+            let decoded_size = cobs_decode(&encoded[..size], &mut decoded).unwrap();
+            assert_eq!(&decoded[..decoded_size], data);
+        }
+    }
+
+    #[test]
+    fn frame_roundtrip() {
+        let frame = encode_frame(&Message::AsservGotoXy { x: 0.0, y: 300.0 });
+        assert_eq!(frame.last(), Some(&FRAME_DELIMITER));
+        assert_eq!(frame.iter().filter(|b| **b == FRAME_DELIMITER).count(), 1);
+
+        let mut decoder = FrameDecoder::new();
+        let results = decode_all(&mut decoder, &frame);
+        assert!(matches!(results[..], [Ok(Message::AsservGotoXy { x: 0.0, y: 300.0 })]));
+    }
+
+    #[test]
+    fn frame_encoding() {
+        // Same value is produced by `rome.py`
+        let frame = encode_frame(&Message::AsservGotoXy { x: 0.0, y: 300.0 });
+        assert_eq!(&*frame, &[0x02, 0x18, 0x01, 0x01, 0x01, 0x01, 0x01, 0x05, 0x96, 0x43, 0x99, 0xa8, 0x00]);
+    }
+
+    #[test]
+    fn frame_split_and_merged() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(&encode_frame(&Message::AsservGotoA { a: 1.5 }));
+        stream.extend_from_slice(&encode_frame(&Message::BatteryLevel { mv: 12000, percent: 80 }));
+
+        let mut decoder = FrameDecoder::new();
+        let mut results = Vec::new();
+        for chunk in stream.chunks(3) {
+            results.extend(decoder.feed(chunk));
+        }
+        assert!(matches!(results[..], [
+            Ok(Message::AsservGotoA { a: 1.5 }),
+            Ok(Message::BatteryLevel { mv: 12000, percent: 80 }),
+        ]));
+    }
+
+    #[test]
+    fn data_frame_with_envelope() {
+        let envelope = crate::reliable::encode_envelope(0x1234, &Message::AsservGotoA { a: 1.5 });
+        let mut stream = encode_data_frame(&envelope).unwrap().into_vec();
+        stream.extend_from_slice(&encode_frame(&Message::AsservGotoA { a: 1.5 }));
+
+        let mut decoder = FrameDecoder::new();
+        let results: Vec<_> = decoder.feed_data(&stream).collect();
+        let message_data = Message::AsservGotoA { a: 1.5 }.encode();
+        assert!(matches!(&results[..], [Ok(data0), Ok(data1)] if *data0 == envelope && *data1 == message_data));
+
+        assert_eq!(encode_data_frame(&[0; MAX_ENVELOPE_SIZE + 1]), Err(EncodeError::BufferOverflow));
+    }
+
+    #[test]
+    fn frame_resync_after_garbage() {
+        let mut stream = vec![0x42, 0x13, 0x37, FRAME_DELIMITER, FRAME_DELIMITER];
+        stream.extend_from_slice(&encode_frame(&Message::AsservGotoA { a: 1.5 }));
+
+        let mut decoder = FrameDecoder::new();
+        let results = decode_all(&mut decoder, &stream);
+        assert!(matches!(results[..], [
+            Err(DecodeError::BadFrame | DecodeError::BadChecksum),
+            Ok(Message::AsservGotoA { a: 1.5 }),
+        ]));
+    }
+
+    #[test]
+    fn frame_bad_checksum() {
+        let mut frame = encode_frame(&Message::AsservGotoA { a: 1.5 }).into_vec();
+        // Alter message ID (first byte is a COBS code)
+        frame[1] ^= 0x01;
+        let mut decoder = FrameDecoder::new();
+        let results = decode_all(&mut decoder, &frame);
+        assert!(matches!(results[..], [Err(DecodeError::BadChecksum)]));
+    }
+
+    #[test]
+    fn frame_too_long() {
+        let mut stream = vec![0x01; MAX_FRAME_SIZE + 10];
+        stream.push(FRAME_DELIMITER);
+        stream.extend_from_slice(&encode_frame(&Message::AsservGotoA { a: 1.5 }));
+
+        let mut decoder = FrameDecoder::new();
+        let results = decode_all(&mut decoder, &stream);
+        assert!(matches!(results[..], [
+            Err(DecodeError::FrameTooLong),
+            Ok(Message::AsservGotoA { a: 1.5 }),
+        ]));
+    }
+}
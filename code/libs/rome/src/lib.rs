@@ -1,11 +1,30 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+// Messages are large, but boxing them would require allocations
+#![allow(clippy::large_enum_variant, clippy::result_large_err)]
+
 pub mod message;
+pub mod message_ref;
+pub mod framing;
+pub mod reliable;
+#[cfg(feature = "std")]
+pub mod registry;
+#[cfg(feature = "std")]
+pub mod capture;
+#[cfg(feature = "std")]
+pub mod telemetry;
+#[cfg(feature = "net")]
+pub mod net;
+pub mod text;
 mod deserialize;
 mod serialize;
 
 use deserialize::Reader;
 use serialize::Serialize;
 
-pub use message::{Message, MessageId, params};
+pub use message::{Message, MessageId, RomeHandler, MAX_ENCODED_SIZE, SCHEMA_HASH, params};
+pub use message_ref::{ArrayRef, MessageRef};
+pub use text::ParseError;
+pub use heapless;
 
 
 #[derive(Debug)]
@@ -14,23 +33,72 @@ pub enum DecodeError {
     EndOfData,
     /// Unknown message ID
     UnknownMessage(u8),
-    /// Some data has not been read, message is probably corrupted
+    /// Some data has not been read, see [Message::decode_exact()]
     UnparsedData(Message, usize),
     /// Invalid choice value
     BadChoiceValue(u8),
+    /// Invalid flags value, with unknown bits set
+    BadFlagsValue(u32),
+    /// Length prefix larger than field capacity
+    BadLength(u8),
+    /// Invalid UTF-8 string
+    BadString,
+    /// Invalid frame encoding
+    BadFrame,
+    /// Frame checksum mismatch
+    BadChecksum,
+    /// Frame is larger than any valid message
+    FrameTooLong,
+    /// Peer uses a different message schema, with given hash
+    SchemaMismatch(u32),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Not enough room in output buffer
+    BufferOverflow,
+    /// Variable-length value is too long to be encoded
+    LengthOverflow,
 }
 
 
 impl Message {
+    #[cfg(feature = "std")]
     pub fn encode(&self) -> Box<[u8]> {
         let buffer_size = self.serialized_size();
         let mut buffer = Vec::with_capacity(buffer_size);
-        self.serialize(&mut buffer);
+        self.serialize(&mut buffer).expect("encoding to a Vec cannot fail");
         assert_eq!(buffer.len(), buffer_size);
         buffer.into_boxed_slice()
     }
 
+    /// Encode message into given buffer, return the encoded size
+    ///
+    /// Buffer is guaranteed to be large enough if its size is at least [MAX_ENCODED_SIZE].
+    pub fn encode_into(&self, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+        let size = self.serialized_size();
+        let mut writer = buffer.get_mut(..size).ok_or(EncodeError::BufferOverflow)?;
+        self.serialize(&mut writer)?;
+        Ok(size)
+    }
+
+    /// Create a `RomeHello` message for the local schema
+    pub fn hello(device: params::RomeHelloDevice) -> Self {
+        Self::RomeHello { schema_hash: SCHEMA_HASH, device }
+    }
+
+    /// Decode a message
+    ///
+    /// Trailing data is ignored: it is assumed to be optional parameters unknown
+    /// to the local schema, sent by a more recent peer.
     pub fn decode(mut data: &[u8]) -> Result<Self, DecodeError> {
+        let mut buffer = [0u8; 1];
+        data.read(&mut buffer)?;
+        Self::deserialize_with_id(buffer[0], &mut data)
+    }
+
+    /// Decode a message, fail if there is trailing data
+    pub fn decode_exact(mut data: &[u8]) -> Result<Self, DecodeError> {
         let mut buffer = [0u8; 1];
         data.read(&mut buffer)?;
         let message = Self::deserialize_with_id(buffer[0], &mut data)?;
@@ -42,3 +110,226 @@ impl Message {
     }
 }
 
+/// Check the schema hash received from a peer (in a `RomeHello`)
+pub fn check_schema_hash(schema_hash: u32) -> Result<(), DecodeError> {
+    if schema_hash == SCHEMA_HASH {
+        Ok(())
+    } else {
+        Err(DecodeError::SchemaMismatch(schema_hash))
+    }
+}
+
+
+/// Result of a [RomeHandler] method
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handled {
+    Yes,
+    No,
+}
+
+impl From<bool> for Handled {
+    fn from(value: bool) -> Self {
+        if value { Self::Yes } else { Self::No }
+    }
+}
+
+/// Pass a message to handlers, in order, until one of them handles it
+///
+/// Return [Handled::No] if no handler handled the message.
+pub fn dispatch(message: &Message, handlers: &mut [&mut dyn RomeHandler]) -> Handled {
+    for handler in handlers {
+        if message.dispatch_to(*handler) == Handled::Yes {
+            return Handled::Yes;
+        }
+    }
+    Handled::No
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deserialize::Deserialize;
+
+    #[test]
+    fn encode_into_buffer() {
+        let message = Message::AsservGotoXy { x: 120.0, y: 300.0 };
+        let mut buffer = [0u8; MAX_ENCODED_SIZE];
+        let size = message.encode_into(&mut buffer).unwrap();
+        assert_eq!(size, 9);
+        assert_eq!(&buffer[..size], &*message.encode());
+        assert!(matches!(Message::decode(&buffer[..size]), Ok(Message::AsservGotoXy { x: 120.0, y: 300.0 })));
+    }
+
+    #[test]
+    fn encode_into_too_small_buffer() {
+        let message = Message::BatteryLevel { mv: 12000, percent: 80 };
+        let mut buffer = [0u8; 3];
+        assert_eq!(message.encode_into(&mut buffer), Err(EncodeError::BufferOverflow));
+    }
+
+    #[test]
+    fn hello_schema_hash() {
+        let Message::RomeHello { schema_hash, .. } = Message::hello(params::RomeHelloDevice::Pami) else {
+            panic!("unexpected message");
+        };
+        assert!(check_schema_hash(schema_hash).is_ok());
+        assert!(matches!(check_schema_hash(!schema_hash), Err(DecodeError::SchemaMismatch(_))));
+    }
+
+    #[test]
+    fn max_encoded_size() {
+        let message = Message::TopLidarTm {
+            chunk_index: 0,
+            num_chunks: 1,
+            angles: heapless::Vec::from_array([0; 80]),
+            distances: heapless::Vec::from_array([0; 80]),
+            intensities: heapless::Vec::from_array([0; 80]),
+        };
+        assert_eq!(message.encode().len(), MAX_ENCODED_SIZE);
+    }
+
+    #[test]
+    fn vec_roundtrip() {
+        let message = Message::TopLidarTm {
+            chunk_index: 2,
+            num_chunks: 7,
+            angles: heapless::Vec::from_slice(&[100, 200, 300]).unwrap(),
+            distances: heapless::Vec::from_slice(&[1000, 2000, 3000]).unwrap(),
+            intensities: heapless::Vec::from_slice(&[10, 20, 30]).unwrap(),
+        };
+        let data = message.encode();
+        assert_eq!(data.len(), 1 + 2 + (1 + 6) + (1 + 6) + (1 + 3));
+        match Message::decode(&data) {
+            Ok(Message::TopLidarTm { chunk_index, num_chunks, angles, distances, intensities }) => {
+                assert_eq!((chunk_index, num_chunks), (2, 7));
+                assert_eq!(&angles, &[100, 200, 300]);
+                assert_eq!(&distances, &[1000, 2000, 3000]);
+                assert_eq!(&intensities, &[10, 20, 30]);
+            }
+            result => panic!("unexpected decode result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn vec_bad_length() {
+        let mut data = [0u8; 8];
+        data[0] = Message::TopLidarTm {
+            chunk_index: 0,
+            num_chunks: 0,
+            angles: heapless::Vec::new(),
+            distances: heapless::Vec::new(),
+            intensities: heapless::Vec::new(),
+        }.message_id();
+        data[3] = 81;
+        assert!(matches!(Message::decode(&data), Err(DecodeError::BadLength(81))));
+    }
+
+    #[test]
+    fn str_roundtrip() {
+        let text: heapless::String<16> = heapless::String::try_from("héllo").unwrap();
+        let mut data = Vec::new();
+        text.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), text.serialized_size());
+        assert_eq!(data[0], 6);
+
+        let decoded = <heapless::String<16>>::deserialize(&mut &data[..]).unwrap();
+        assert_eq!(decoded, text);
+        assert!(matches!(<heapless::String<4>>::deserialize(&mut &data[..]), Err(DecodeError::BadLength(6))));
+        data[1] = 0xff;
+        assert!(matches!(<heapless::String<16>>::deserialize(&mut &data[..]), Err(DecodeError::BadString)));
+    }
+
+    #[test]
+    fn named_enum_roundtrip() {
+        let message = Message::MatchTm { team: params::Team::Left, time_ms: 3 };
+        let data = message.encode();
+        assert_eq!(&data[1..], &[1, 3, 0, 0, 0]);
+        assert!(matches!(Message::decode(&data), Ok(Message::MatchTm { team: params::Team::Left, time_ms: 3 })));
+    }
+
+    #[test]
+    fn struct_roundtrip() {
+        let value = params::XYA { x: 1.0, y: -2.0, a: 0.5 };
+        let mut data = Vec::new();
+        value.serialize(&mut data).unwrap();
+        assert_eq!(data.len(), value.serialized_size());
+        assert_eq!(data.len(), 12);
+        assert_eq!(params::XYA::deserialize(&mut &data[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn optional_param_default() {
+        let message = Message::AsservDiffSetPidConf { pid: params::AsservDiffSetPidConfPid::Angle, gain_p: 1, gain_i: 2, gain_d: 3, max_in: 4, max_i: 5, max_out: 6, out_shift: 7 };
+        let data = message.encode();
+        assert!(matches!(Message::decode(&data), Ok(Message::AsservDiffSetPidConf { out_shift: 7, .. })));
+        // Sent by a peer without `out_shift`
+        let decoded = Message::decode(&data[..data.len() - 1]);
+        assert!(matches!(decoded, Ok(Message::AsservDiffSetPidConf { max_out: 6, out_shift: 0, .. })));
+        // Required parameters cannot be omitted
+        assert!(matches!(Message::decode(&data[..data.len() - 2]), Err(DecodeError::EndOfData)));
+    }
+
+    #[test]
+    fn trailing_data_ignored() {
+        let mut data = Message::AsservGotoA { a: 1.5 }.encode().into_vec();
+        data.extend([1, 2]);
+        assert!(matches!(Message::decode(&data), Ok(Message::AsservGotoA { a: 1.5 })));
+        assert!(matches!(Message::decode_exact(&data), Err(DecodeError::UnparsedData(Message::AsservGotoA { a: 1.5 }, 2))));
+    }
+
+    #[derive(Default)]
+    struct GotoHandler {
+        goto_xy: Option<(f32, f32)>,
+    }
+
+    impl RomeHandler for GotoHandler {
+        fn on_asserv_goto_xy(&mut self, x: f32, y: f32) -> Handled {
+            self.goto_xy = Some((x, y));
+            Handled::Yes
+        }
+    }
+
+    #[derive(Default)]
+    struct LidarHandler {
+        goto_xy_count: usize,
+        angles: Vec<u16>,
+    }
+
+    impl RomeHandler for LidarHandler {
+        fn on_asserv_goto_xy(&mut self, _x: f32, _y: f32) -> Handled {
+            self.goto_xy_count += 1;
+            Handled::Yes
+        }
+
+        fn on_top_lidar_tm(&mut self, _chunk_index: u8, _num_chunks: u8, angles: &heapless::Vec<u16, 80>, _distances: &heapless::Vec<u16, 80>, _intensities: &heapless::Vec<u8, 80>) -> Handled {
+            self.angles.extend_from_slice(angles);
+            Handled::Yes
+        }
+    }
+
+    #[test]
+    fn dispatch_to_handlers() {
+        let mut goto = GotoHandler::default();
+        let mut lidar = LidarHandler::default();
+
+        // First handler wins
+        let message = Message::AsservGotoXy { x: 1.0, y: 2.0 };
+        assert_eq!(dispatch(&message, &mut [&mut goto, &mut lidar]), Handled::Yes);
+        assert_eq!(goto.goto_xy, Some((1.0, 2.0)));
+        assert_eq!(lidar.goto_xy_count, 0);
+
+        let message = Message::TopLidarTm {
+            chunk_index: 0,
+            num_chunks: 1,
+            angles: heapless::Vec::from_slice(&[10, 20]).unwrap(),
+            distances: heapless::Vec::new(),
+            intensities: heapless::Vec::new(),
+        };
+        assert_eq!(dispatch(&message, &mut [&mut goto, &mut lidar]), Handled::Yes);
+        assert_eq!(lidar.angles, [10, 20]);
+
+        let message = Message::AsservGotoA { a: 1.0 };
+        assert_eq!(dispatch(&message, &mut [&mut goto, &mut lidar]), Handled::No);
+    }
+}
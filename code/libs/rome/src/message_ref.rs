@@ -0,0 +1,224 @@
+//! Borrowed decoding of messages, without copying arrays and strings
+//!
+//! [MessageRef] mirrors [Message](crate::Message), but arrays of scalars are
+//! decoded as [ArrayRef], a view over the little-endian encoded data, and
+//! strings as `&str`. Array items are decoded when accessed.
+//! ```
+//! # use rome::{Message, MessageRef};
+//! # let data = Message::TopLidarTm {
+//! #     chunk_index: 0, num_chunks: 1,
+//! #     angles: [10, 20].as_slice().try_into().unwrap(),
+//! #     distances: [500, 600].as_slice().try_into().unwrap(),
+//! #     intensities: [1, 2].as_slice().try_into().unwrap(),
+//! # }.encode();
+//! if let Ok(MessageRef::TopLidarTm { angles, distances, .. }) = MessageRef::decode(&data) {
+//!     for (angle, distance) in angles.iter().zip(distances) {
+//!         println!("{angle}: {distance}");
+//!     }
+//! }
+//! ```
+
+use core::marker::PhantomData;
+use crate::DecodeError;
+use crate::deserialize::{Deserialize, Reader};
+
+pub use crate::message::MessageRef;
+
+
+impl<'a> MessageRef<'a> {
+    /// Decode a message, borrowing arrays and strings from `data`
+    ///
+    /// Like [Message::decode()](crate::Message::decode()), trailing data is ignored.
+    pub fn decode(mut data: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut buffer = [0u8; 1];
+        data.read(&mut buffer)?;
+        Self::deserialize_with_id(buffer[0], &mut data)
+    }
+}
+
+
+/// Scalar type that can be used as [ArrayRef] item
+pub trait ArrayItem: Copy {
+    /// Size of an encoded item
+    const SIZE: usize;
+    /// Decode an item from exactly `SIZE` bytes
+    fn from_le_slice(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_array_item {
+    ($($t:ty),*) => {
+        $(
+            impl ArrayItem for $t {
+                const SIZE: usize = core::mem::size_of::<Self>();
+
+                fn from_le_slice(bytes: &[u8]) -> Self {
+                    Self::from_le_bytes(bytes.try_into().expect("slice has the size of an item"))
+                }
+            }
+        )*
+    }
+}
+
+impl_array_item!(u8, i8, u16, i16, u32, i32, f32);
+
+impl ArrayItem for bool {
+    const SIZE: usize = 1;
+
+    fn from_le_slice(bytes: &[u8]) -> Self {
+        bytes[0] != 0
+    }
+}
+
+
+/// Array of scalars, borrowed from encoded data
+#[derive(Clone, Copy)]
+pub struct ArrayRef<'a, T: ArrayItem> {
+    data: &'a [u8],
+    _item: PhantomData<T>,
+}
+
+/// Iterator over [ArrayRef] items
+pub type ArrayRefIter<'a, T> = core::iter::Map<core::slice::ChunksExact<'a, u8>, fn(&[u8]) -> T>;
+
+impl<'a, T: ArrayItem> ArrayRef<'a, T> {
+    pub fn len(&self) -> usize {
+        self.data.len() / T::SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Decode the item at given index, `None` if out of bounds
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index < self.len() {
+            Some(T::from_le_slice(&self.data[index * T::SIZE..][..T::SIZE]))
+        } else {
+            None
+        }
+    }
+
+    /// Iterate on decoded items
+    pub fn iter(&self) -> ArrayRefIter<'a, T> {
+        self.data.chunks_exact(T::SIZE).map(T::from_le_slice)
+    }
+
+    /// Return encoded items
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Read a fixed-size array
+    pub(crate) fn deserialize_array(reader: &mut &'a [u8], count: usize) -> Result<Self, DecodeError> {
+        let (data, rest) = reader.split_at_checked(count * T::SIZE).ok_or(DecodeError::EndOfData)?;
+        *reader = rest;
+        Ok(Self { data, _item: PhantomData })
+    }
+
+    /// Read a bounded array, prefixed by its length
+    pub(crate) fn deserialize_vec(reader: &mut &'a [u8], capacity: usize) -> Result<Self, DecodeError> {
+        let len = u8::deserialize(reader)?;
+        if len as usize > capacity {
+            return Err(DecodeError::BadLength(len));
+        }
+        Self::deserialize_array(reader, len as usize)
+    }
+}
+
+impl<'a, T: ArrayItem> IntoIterator for ArrayRef<'a, T> {
+    type Item = T;
+    type IntoIter = ArrayRefIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T: ArrayItem + core::fmt::Debug> core::fmt::Debug for ArrayRef<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T: ArrayItem + PartialEq> PartialEq<[T]> for ArrayRef<'_, T> {
+    fn eq(&self, other: &[T]) -> bool {
+        self.iter().eq(other.iter().copied())
+    }
+}
+
+
+/// Read a bounded string, prefixed by its length
+pub(crate) fn deserialize_str<'a>(reader: &mut &'a [u8], capacity: usize) -> Result<&'a str, DecodeError> {
+    let len = u8::deserialize(reader)?;
+    if len as usize > capacity {
+        return Err(DecodeError::BadLength(len));
+    }
+    let (data, rest) = reader.split_at_checked(len as usize).ok_or(DecodeError::EndOfData)?;
+    *reader = rest;
+    core::str::from_utf8(data).map_err(|_| DecodeError::BadString)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    fn lidar_tm() -> Message {
+        Message::TopLidarTm {
+            chunk_index: 2,
+            num_chunks: 5,
+            angles: [100, 200, 65535].as_slice().try_into().unwrap(),
+            distances: [1500, 0, 42].as_slice().try_into().unwrap(),
+            intensities: [7, 8].as_slice().try_into().unwrap(),
+        }
+    }
+
+    #[test]
+    fn decode_arrays() {
+        let data = lidar_tm().encode();
+        let message = MessageRef::decode(&data).unwrap();
+        let MessageRef::TopLidarTm { chunk_index, num_chunks, angles, distances, intensities } = message else {
+            panic!("unexpected message: {message:?}");
+        };
+        assert_eq!((chunk_index, num_chunks), (2, 5));
+        assert_eq!(angles, *[100, 200, 65535].as_slice());
+        assert_eq!(distances.len(), 3);
+        assert_eq!(distances.get(0), Some(1500));
+        assert_eq!(distances.get(3), None);
+        assert_eq!(intensities.iter().collect::<Vec<_>>(), [7, 8]);
+        assert_eq!(intensities.as_bytes(), &[7, 8]);
+        assert_eq!(message.name(), "TopLidarTm");
+        assert_eq!(message.message_id(), lidar_tm().message_id());
+    }
+
+    #[test]
+    fn decode_fixed_array_and_string() {
+        let data = Message::ParamGet { name: "asserv.gain_p".try_into().unwrap() }.encode();
+        assert!(matches!(MessageRef::decode(&data), Ok(MessageRef::ParamGet { name: "asserv.gain_p" })));
+
+        let velocities_to_consigns = [1.0, -2.5, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 9.0];
+        let data = Message::AsservHoloSetMotorsConf { velocities_to_consigns, encoders_to_position: [0.0; 9] }.encode();
+        match MessageRef::decode(&data) {
+            Ok(MessageRef::AsservHoloSetMotorsConf { velocities_to_consigns: values, encoders_to_position }) => {
+                assert_eq!(values, velocities_to_consigns[..]);
+                assert_eq!(encoders_to_position.len(), 9);
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        let data = lidar_tm().encode();
+        assert!(matches!(MessageRef::decode(&data[..data.len() - 1]), Err(DecodeError::EndOfData)));
+
+        // Length of `angles` larger than its capacity
+        let mut data = data.into_vec();
+        data[3] = 81;
+        assert!(matches!(MessageRef::decode(&data), Err(DecodeError::BadLength(81))));
+
+        let data = [Message::ParamGet { name: Default::default() }.message_id(), 2, 0xff, 0xfe];
+        assert!(matches!(MessageRef::decode(&data), Err(DecodeError::BadString)));
+    }
+}
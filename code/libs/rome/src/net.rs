@@ -0,0 +1,223 @@
+//! ROME transport over TCP or UDP, for host builds
+//!
+//! Servers provide the same channels as the BLE transport: encoded messages,
+//! possibly in envelopes (see [crate::reliable]), are sent and received as
+//! `Box<[u8]>`.
+//!
+//! - TCP: data is framed (see [crate::framing]). Multiple clients can be
+//!   connected at once, sent data is written to all of them.
+//! - UDP: each datagram carries a single message, without framing. Sent data is
+//!   written to all peers that sent at least one datagram; peers can send an
+//!   empty datagram to register without sending a message.
+//!
+//! Invalid received data is silently dropped.
+//! ```no_run
+//! # use rome::Message;
+//! let (rome_tx, rome_rx) = rome::net::server("tcp:127.0.0.1:7001").unwrap();
+//! for data in rome_rx.iter() {
+//!     let (_seq, data) = rome::reliable::split_envelope(&data);
+//!     if let Ok(message) = Message::decode(data) {
+//!         println!("received {message}");
+//!     }
+//! }
+//! ```
+
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use flume::{Receiver, Sender};
+use crate::framing::{encode_data_frame, FrameDecoder};
+use crate::reliable::MAX_ENVELOPE_SIZE;
+
+/// Channels to send and receive encoded messages
+pub type RomeChannels = (Sender<Box<[u8]>>, Receiver<Box<[u8]>>);
+
+/// Environment variable used by [addr_from_env()]
+pub const ADDR_ENV_VAR: &str = "ROME_ADDR";
+
+
+/// Return the server address set in the environment, or `default_addr`
+pub fn addr_from_env(default_addr: &str) -> String {
+    std::env::var(ADDR_ENV_VAR).unwrap_or_else(|_| default_addr.to_owned())
+}
+
+/// Start a server on an address prefixed by its protocol
+///
+/// Address format is `tcp:HOST:PORT` or `udp:HOST:PORT`.
+/// The protocol defaults to TCP if omitted.
+pub fn server(addr: &str) -> io::Result<RomeChannels> {
+    if let Some(addr) = addr.strip_prefix("udp:") {
+        udp_server(addr)
+    } else {
+        tcp_server(addr.strip_prefix("tcp:").unwrap_or(addr))
+    }
+}
+
+
+/// Start a TCP server on given address
+pub fn tcp_server(addr: impl ToSocketAddrs) -> io::Result<RomeChannels> {
+    Ok(serve_tcp(TcpListener::bind(addr)?))
+}
+
+/// Serve ROME on a bound TCP listener
+pub fn serve_tcp(listener: TcpListener) -> RomeChannels {
+    let (rx_sender, receiver) = flume::unbounded();
+    let (sender, tx_receiver) = flume::unbounded::<Box<[u8]>>();
+    let clients: Arc<Mutex<Vec<TcpStream>>> = Default::default();
+
+    thread::spawn({
+        let clients = clients.clone();
+        move || {
+            for data in tx_receiver.iter() {
+                let Ok(frame) = encode_data_frame(&data) else { continue };
+                // Drop clients on error
+                clients.lock().unwrap().retain_mut(|stream| stream.write_all(&frame).is_ok());
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            if rx_sender.is_disconnected() {
+                break;
+            }
+            let Ok(stream) = stream else { continue };
+            let _ = stream.set_nodelay(true);
+            let Ok(writer) = stream.try_clone() else { continue };
+            clients.lock().unwrap().push(writer);
+            let rx_sender = rx_sender.clone();
+            thread::spawn(move || read_tcp_client(stream, rx_sender));
+        }
+    });
+
+    (sender, receiver)
+}
+
+fn read_tcp_client(mut stream: TcpStream, rx_sender: Sender<Box<[u8]>>) {
+    let mut decoder = FrameDecoder::new();
+    let mut buffer = [0u8; 256];
+    loop {
+        let size = match stream.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(size) => size,
+        };
+        for data in decoder.feed_data(&buffer[..size]).flatten() {
+            if rx_sender.send(data).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+
+/// Start a UDP server on given address
+pub fn udp_server(addr: impl ToSocketAddrs) -> io::Result<RomeChannels> {
+    serve_udp(UdpSocket::bind(addr)?)
+}
+
+/// Serve ROME on a bound UDP socket
+pub fn serve_udp(socket: UdpSocket) -> io::Result<RomeChannels> {
+    let (rx_sender, receiver) = flume::unbounded();
+    let (sender, tx_receiver) = flume::unbounded::<Box<[u8]>>();
+    let peers: Arc<Mutex<Vec<SocketAddr>>> = Default::default();
+
+    let tx_socket = socket.try_clone()?;
+    thread::spawn({
+        let peers = peers.clone();
+        move || {
+            for data in tx_receiver.iter() {
+                for peer in peers.lock().unwrap().iter() {
+                    let _ = tx_socket.send_to(&data, peer);
+                }
+            }
+        }
+    });
+
+    thread::spawn(move || {
+        // One more byte to detect (and drop) oversized datagrams
+        let mut buffer = [0u8; MAX_ENVELOPE_SIZE + 1];
+        loop {
+            let (size, peer) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                // Transient errors (ICMP port unreachable is reported as a reset on some platforms)
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted | io::ErrorKind::ConnectionReset) => continue,
+                Err(err) => {
+                    log::error!("ROME UDP receive error: {err}");
+                    break;
+                }
+            };
+            {
+                let mut peers = peers.lock().unwrap();
+                if !peers.contains(&peer) {
+                    peers.push(peer);
+                }
+            }
+            if size == 0 || size > MAX_ENVELOPE_SIZE {
+                continue;
+            }
+            if rx_sender.send(buffer[..size].into()).is_err() {
+                break;
+            }
+        }
+    });
+
+    Ok((sender, receiver))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use crate::Message;
+    use crate::framing::encode_frame;
+    use crate::reliable::encode_envelope;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn tcp_roundtrip() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (rome_tx, rome_rx) = serve_tcp(listener);
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let envelope = encode_envelope(42, &Message::AsservGotoA { a: 1.5 });
+        client.write_all(&encode_data_frame(&envelope).unwrap()).unwrap();
+        client.write_all(&[0x42, 0x13, 0x00]).unwrap();  // garbage, dropped
+        client.write_all(&encode_frame(&Message::RomeAck { seq: 3, status: crate::params::RomeAckStatus::Ok })).unwrap();
+
+        assert_eq!(rome_rx.recv_timeout(TIMEOUT).unwrap(), envelope);
+        let data = rome_rx.recv_timeout(TIMEOUT).unwrap();
+        assert!(matches!(Message::decode(&data), Ok(Message::RomeAck { seq: 3, .. })));
+
+        rome_tx.send(Message::BatteryLevel { mv: 12000, percent: 80 }.encode()).unwrap();
+        let mut decoder = FrameDecoder::new();
+        let mut buffer = [0u8; 64];
+        let size = client.read(&mut buffer).unwrap();
+        let results: Vec<_> = decoder.feed(&buffer[..size]).collect();
+        assert!(matches!(results[..], [Ok(Message::BatteryLevel { mv: 12000, percent: 80 })]));
+    }
+
+    #[test]
+    fn udp_roundtrip() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let (rome_tx, rome_rx) = serve_udp(socket).unwrap();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(TIMEOUT)).unwrap();
+        let envelope = encode_envelope(42, &Message::AsservGotoA { a: 1.5 });
+        client.send_to(&envelope, addr).unwrap();
+        assert_eq!(rome_rx.recv_timeout(TIMEOUT).unwrap(), envelope);
+
+        // Peer is registered, it receives sent data
+        let data = Message::BatteryLevel { mv: 12000, percent: 80 }.encode();
+        rome_tx.send(data.clone()).unwrap();
+        let mut buffer = [0u8; 64];
+        let size = client.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..size], &*data);
+    }
+}
@@ -0,0 +1,377 @@
+//! Runtime parameters, readable and writable by peers
+//!
+//! A [ParamRegistry] holds named parameters, each with a type, a valid range
+//! and callbacks to read and write its value from a context (e.g. the asserv).
+//! Parameters are exposed to peers with the following messages:
+//!  - `ParamList`: answered with a `ParamValue` for each parameter
+//!  - `ParamGet`: answered with a `ParamValue` for the requested parameter
+//!  - `ParamSet`: set the value, answered with a `ParamValue` with the new value
+//!
+//! Values are transmitted as `f32`. Integer and boolean values must be integral.
+//! ```
+//! # use rome::Message;
+//! # use rome::registry::ParamRegistry;
+//! struct Conf { speed: f32, retries: u8 }
+//!
+//! let mut registry = ParamRegistry::new();
+//! registry.add("speed", 0.0..=2000.0, |conf: &Conf| conf.speed, |conf, value| conf.speed = value);
+//! registry.add_read_only("retries", |conf: &Conf| conf.retries);
+//!
+//! let mut conf = Conf { speed: 500.0, retries: 3 };
+//! let message = Message::ParamList;
+//! message.dispatch_to(&mut registry.handler(&mut conf, |response| println!("{response}")));
+//! ```
+
+use std::ops::RangeInclusive;
+use crate::{Handled, Message, RomeHandler};
+use crate::params::{ParamKind, ParamValueStatus};
+
+/// Maximum length of a parameter name
+pub const PARAM_NAME_MAX_LEN: usize = 32;
+
+/// Error returned when reading or writing a parameter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamError {
+    UnknownName,
+    /// Value is out of range, or not valid for the parameter type
+    InvalidValue,
+    ReadOnly,
+}
+
+impl From<ParamError> for ParamValueStatus {
+    fn from(value: ParamError) -> Self {
+        match value {
+            ParamError::UnknownName => Self::UnknownName,
+            ParamError::InvalidValue => Self::InvalidValue,
+            ParamError::ReadOnly => Self::ReadOnly,
+        }
+    }
+}
+
+
+/// Type of parameter values
+pub trait ParamType: Copy + 'static {
+    const KIND: ParamKind;
+    /// Range of values, when no range is provided
+    const FULL_RANGE: RangeInclusive<Self>;
+
+    fn to_f32(self) -> f32;
+    /// Convert a value, it has already been checked against the parameter range
+    fn from_f32(value: f32) -> Self;
+}
+
+impl ParamType for bool {
+    const KIND: ParamKind = ParamKind::Bool;
+    const FULL_RANGE: RangeInclusive<Self> = false..=true;
+
+    fn to_f32(self) -> f32 {
+        if self { 1.0 } else { 0.0 }
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value != 0.0
+    }
+}
+
+impl ParamType for f32 {
+    const KIND: ParamKind = ParamKind::Float;
+    const FULL_RANGE: RangeInclusive<Self> = f32::MIN..=f32::MAX;
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+macro_rules! impl_param_type_int {
+    ($($t:ty),*) => {
+        $(
+            impl ParamType for $t {
+                const KIND: ParamKind = ParamKind::Int;
+                const FULL_RANGE: RangeInclusive<Self> = <$t>::MIN..=<$t>::MAX;
+
+                fn to_f32(self) -> f32 {
+                    self as f32
+                }
+
+                fn from_f32(value: f32) -> Self {
+                    value as $t
+                }
+            }
+        )*
+    }
+}
+
+impl_param_type_int!(u8, i8, u16, i16, u32, i32);
+
+
+type ReadFn<C> = Box<dyn Fn(&C) -> f32 + Send>;
+type WriteFn<C> = Box<dyn Fn(&mut C, f32) + Send>;
+
+struct Param<C> {
+    name: String,
+    kind: ParamKind,
+    min: f32,
+    max: f32,
+    read: ReadFn<C>,
+    write: Option<WriteFn<C>>,
+}
+
+impl<C> Param<C> {
+    fn check_value(&self, value: f32) -> Result<(), ParamError> {
+        let integral = self.kind == ParamKind::Float || value.fract() == 0.0;
+        if integral && (self.min..=self.max).contains(&value) {
+            Ok(())
+        } else {
+            Err(ParamError::InvalidValue)
+        }
+    }
+}
+
+/// Registry of named parameters, read and written from a context of type `C`
+pub struct ParamRegistry<C> {
+    params: Vec<Param<C>>,
+}
+
+impl<C> Default for ParamRegistry<C> {
+    fn default() -> Self {
+        Self { params: Vec::new() }
+    }
+}
+
+impl<C> ParamRegistry<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a writable parameter, values outside of `range` are rejected
+    ///
+    /// Panic if the name is too long, already used or if there are too many parameters.
+    pub fn add<T: ParamType>(
+        &mut self,
+        name: &str,
+        range: RangeInclusive<T>,
+        read: impl Fn(&C) -> T + Send + 'static,
+        write: impl Fn(&mut C, T) + Send + 'static,
+    ) {
+        let write: WriteFn<C> = Box::new(move |context, value| write(context, T::from_f32(value)));
+        self.push::<T>(name, range, Box::new(move |context| read(context).to_f32()), Some(write));
+    }
+
+    /// Register a read-only parameter
+    ///
+    /// Panic if the name is too long, already used or if there are too many parameters.
+    pub fn add_read_only<T: ParamType>(&mut self, name: &str, read: impl Fn(&C) -> T + Send + 'static) {
+        self.push::<T>(name, T::FULL_RANGE, Box::new(move |context| read(context).to_f32()), None);
+    }
+
+    fn push<T: ParamType>(&mut self, name: &str, range: RangeInclusive<T>, read: ReadFn<C>, write: Option<WriteFn<C>>) {
+        assert!(name.len() <= PARAM_NAME_MAX_LEN, "parameter name is too long: {name}");
+        assert!(self.find(name).is_none(), "duplicate parameter name: {name}");
+        assert!(self.params.len() < u8::MAX as usize, "too many parameters");
+        self.params.push(Param {
+            name: name.into(),
+            kind: T::KIND,
+            min: range.start().to_f32(),
+            max: range.end().to_f32(),
+            read,
+            write,
+        });
+    }
+
+    /// Return the number of parameters
+    pub fn len(&self) -> usize {
+        self.params.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.params.is_empty()
+    }
+
+    /// Iterate on parameter names, in registration order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.params.iter().map(|param| param.name.as_str())
+    }
+
+    /// Read a parameter value
+    pub fn get(&self, context: &C, name: &str) -> Result<f32, ParamError> {
+        let (_, param) = self.find(name).ok_or(ParamError::UnknownName)?;
+        Ok((param.read)(context))
+    }
+
+    /// Write a parameter value
+    pub fn set(&self, context: &mut C, name: &str, value: f32) -> Result<(), ParamError> {
+        let (_, param) = self.find(name).ok_or(ParamError::UnknownName)?;
+        let write = param.write.as_ref().ok_or(ParamError::ReadOnly)?;
+        param.check_value(value)?;
+        write(context, value);
+        Ok(())
+    }
+
+    /// Return a handler for parameter messages, responses are passed to `send`
+    pub fn handler<'a, F: FnMut(Message)>(&'a self, context: &'a mut C, send: F) -> ParamHandler<'a, C, F> {
+        ParamHandler { registry: self, context, send }
+    }
+
+    fn find(&self, name: &str) -> Option<(usize, &Param<C>)> {
+        self.params.iter().enumerate().find(|(_, param)| param.name == name)
+    }
+
+    /// Create a `ParamValue` message for a parameter
+    fn param_value(&self, context: &C, index: usize, status: ParamValueStatus) -> Message {
+        let param = &self.params[index];
+        Message::ParamValue {
+            index: index as u8,
+            count: self.params.len() as u8,
+            // Name length has been checked on registration
+            name: param.name.as_str().try_into().unwrap(),
+            kind: param.kind,
+            value: (param.read)(context),
+            min: param.min,
+            max: param.max,
+            writable: param.write.is_some(),
+            status,
+        }
+    }
+
+    /// Create a `ParamValue` message for an unknown parameter
+    fn unknown_param_value(&self, name: &heapless::String<PARAM_NAME_MAX_LEN>) -> Message {
+        Message::ParamValue {
+            index: 0,
+            count: self.params.len() as u8,
+            name: name.clone(),
+            kind: ParamKind::Float,
+            value: 0.0,
+            min: 0.0,
+            max: 0.0,
+            writable: false,
+            status: ParamValueStatus::UnknownName,
+        }
+    }
+}
+
+
+/// Handle parameter messages for a [ParamRegistry], see [ParamRegistry::handler()]
+pub struct ParamHandler<'a, C, F: FnMut(Message)> {
+    registry: &'a ParamRegistry<C>,
+    context: &'a mut C,
+    send: F,
+}
+
+impl<C, F: FnMut(Message)> RomeHandler for ParamHandler<'_, C, F> {
+    fn on_param_list(&mut self) -> Handled {
+        for index in 0..self.registry.len() {
+            (self.send)(self.registry.param_value(self.context, index, ParamValueStatus::Ok));
+        }
+        Handled::Yes
+    }
+
+    fn on_param_get(&mut self, name: &heapless::String<PARAM_NAME_MAX_LEN>) -> Handled {
+        let response = match self.registry.find(name) {
+            Some((index, _)) => self.registry.param_value(self.context, index, ParamValueStatus::Ok),
+            None => self.registry.unknown_param_value(name),
+        };
+        (self.send)(response);
+        Handled::Yes
+    }
+
+    fn on_param_set(&mut self, name: &heapless::String<PARAM_NAME_MAX_LEN>, value: f32) -> Handled {
+        let response = match self.registry.find(name) {
+            Some((index, _)) => {
+                let status = match self.registry.set(self.context, name, value) {
+                    Ok(()) => ParamValueStatus::Ok,
+                    Err(err) => err.into(),
+                };
+                self.registry.param_value(self.context, index, status)
+            }
+            None => self.registry.unknown_param_value(name),
+        };
+        (self.send)(response);
+        Handled::Yes
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Conf {
+        speed: f32,
+        gain: i16,
+        enabled: bool,
+        version: u8,
+    }
+
+    fn registry() -> ParamRegistry<Conf> {
+        let mut registry = ParamRegistry::new();
+        registry.add("speed", 0.0..=100.0, |conf: &Conf| conf.speed, |conf, value| conf.speed = value);
+        registry.add("gain", i16::FULL_RANGE, |conf: &Conf| conf.gain, |conf, value| conf.gain = value);
+        registry.add("enabled", bool::FULL_RANGE, |conf: &Conf| conf.enabled, |conf, value| conf.enabled = value);
+        registry.add_read_only("version", |conf: &Conf| conf.version);
+        registry
+    }
+
+    fn handle(registry: &ParamRegistry<Conf>, conf: &mut Conf, message: Message) -> Vec<Message> {
+        let mut responses = Vec::new();
+        assert_eq!(message.dispatch_to(&mut registry.handler(conf, |response| responses.push(response))), Handled::Yes);
+        responses
+    }
+
+    #[test]
+    fn get_set() {
+        let registry = registry();
+        let mut conf = Conf { version: 3, ..Default::default() };
+        assert_eq!(registry.names().collect::<Vec<_>>(), ["speed", "gain", "enabled", "version"]);
+
+        assert_eq!(registry.set(&mut conf, "speed", 12.5), Ok(()));
+        assert_eq!(registry.get(&conf, "speed"), Ok(12.5));
+        assert_eq!(registry.set(&mut conf, "gain", -300.0), Ok(()));
+        assert_eq!(conf.gain, -300);
+        assert_eq!(registry.set(&mut conf, "enabled", 1.0), Ok(()));
+        assert!(conf.enabled);
+        assert_eq!(registry.get(&conf, "version"), Ok(3.0));
+
+        assert_eq!(registry.set(&mut conf, "speed", 100.5), Err(ParamError::InvalidValue));
+        assert_eq!(registry.set(&mut conf, "gain", 1.5), Err(ParamError::InvalidValue));
+        assert_eq!(registry.set(&mut conf, "gain", 40000.0), Err(ParamError::InvalidValue));
+        assert_eq!(registry.set(&mut conf, "enabled", 2.0), Err(ParamError::InvalidValue));
+        assert_eq!(registry.set(&mut conf, "version", 4.0), Err(ParamError::ReadOnly));
+        assert_eq!(registry.get(&conf, "unknown"), Err(ParamError::UnknownName));
+        assert_eq!(conf.version, 3);
+    }
+
+    #[test]
+    fn handle_messages() {
+        let registry = registry();
+        let mut conf = Conf::default();
+
+        let responses = handle(&registry, &mut conf, Message::ParamList);
+        assert_eq!(responses.len(), 4);
+        assert!(matches!(&responses[3], Message::ParamValue { index: 3, count: 4, name, kind: ParamKind::Int, writable: false, .. } if name == "version"));
+
+        let responses = handle(&registry, &mut conf, Message::ParamSet { name: "speed".try_into().unwrap(), value: 42.0 });
+        assert!(matches!(responses[..], [Message::ParamValue { index: 0, value: 42.0, min: 0.0, max: 100.0, status: ParamValueStatus::Ok, .. }]));
+        assert_eq!(conf.speed, 42.0);
+
+        let responses = handle(&registry, &mut conf, Message::ParamSet { name: "speed".try_into().unwrap(), value: -1.0 });
+        assert!(matches!(responses[..], [Message::ParamValue { value: 42.0, status: ParamValueStatus::InvalidValue, .. }]));
+
+        let responses = handle(&registry, &mut conf, Message::ParamGet { name: "gain".try_into().unwrap() });
+        assert!(matches!(responses[..], [Message::ParamValue { index: 1, kind: ParamKind::Int, status: ParamValueStatus::Ok, .. }]));
+
+        let responses = handle(&registry, &mut conf, Message::ParamGet { name: "other".try_into().unwrap() });
+        assert!(matches!(responses[..], [Message::ParamValue { status: ParamValueStatus::UnknownName, .. }]));
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate parameter name")]
+    fn duplicate_name() {
+        let mut registry = registry();
+        registry.add_read_only("speed", |conf: &Conf| conf.speed);
+    }
+}
@@ -0,0 +1,289 @@
+//! Reliable orders, with sequence numbers and acknowledgements
+//!
+//! An order can be sent in an envelope carrying a sequence number:
+//! ```text
+//! 0xFF | seq (u16, little endian) | message
+//! ```
+//! The receiver handles the message as usual, then answers with a `RomeAck`
+//! message using the same sequence number. Messages sent without envelope are
+//! not acknowledged.
+//!
+//! [OrderSender] tracks orders waiting for an acknowledgement and resends them
+//! after a timeout. [OrderReceiver] detects orders received again because their
+//! acknowledgement was lost, so that they are not handled twice.
+
+use crate::{EncodeError, Handled, Message, MAX_ENCODED_SIZE, params};
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Message ID of sequenced envelopes (reserved, not used by any message)
+pub const ENVELOPE_ID: u8 = 0xFF;
+
+/// Size of the envelope header: ID and sequence number
+const ENVELOPE_HEADER_SIZE: usize = 3;
+
+/// Maximum size of an encoded envelope, in bytes
+pub const MAX_ENVELOPE_SIZE: usize = MAX_ENCODED_SIZE + ENVELOPE_HEADER_SIZE;
+
+
+/// Encode a message in an envelope into given buffer, return the encoded size
+///
+/// Buffer is guaranteed to be large enough if its size is at least [MAX_ENVELOPE_SIZE].
+pub fn encode_envelope_into(seq: u16, message: &Message, buffer: &mut [u8]) -> Result<usize, EncodeError> {
+    let header = buffer.get_mut(..ENVELOPE_HEADER_SIZE).ok_or(EncodeError::BufferOverflow)?;
+    header[0] = ENVELOPE_ID;
+    header[1..].copy_from_slice(&seq.to_le_bytes());
+    let size = message.encode_into(&mut buffer[ENVELOPE_HEADER_SIZE..])?;
+    Ok(ENVELOPE_HEADER_SIZE + size)
+}
+
+/// Encode a message in an envelope
+#[cfg(feature = "std")]
+pub fn encode_envelope(seq: u16, message: &Message) -> Box<[u8]> {
+    let mut buffer = [0u8; MAX_ENVELOPE_SIZE];
+    let size = encode_envelope_into(seq, message, &mut buffer).expect("buffer is large enough for any message");
+    buffer[..size].into()
+}
+
+/// Split received data into an optional sequence number and the encoded message
+///
+/// Data without envelope is returned as is, without sequence number.
+/// The returned data is intended to be decoded with [Message::decode()].
+pub fn split_envelope(data: &[u8]) -> (Option<u16>, &[u8]) {
+    match data {
+        [ENVELOPE_ID, seq0, seq1, message @ ..] => (Some(u16::from_le_bytes([*seq0, *seq1])), message),
+        _ => (None, data),
+    }
+}
+
+impl From<Handled> for params::RomeAckStatus {
+    fn from(value: Handled) -> Self {
+        match value {
+            Handled::Yes => Self::Ok,
+            Handled::No => Self::Unhandled,
+        }
+    }
+}
+
+
+/// Number of handled orders remembered by [OrderReceiver]
+const RECEIVER_WINDOW: usize = 16;
+
+/// Detect orders resent because their acknowledgement was lost
+///
+/// Such orders must not be handled again (a relative move would be done twice),
+/// they are only acknowledged again, with the same status.
+///
+/// The last handled orders of a single peer are remembered.
+/// [reset()](Self::reset()) must be called when a new peer connects, since it
+/// restarts its sequence numbers.
+pub struct OrderReceiver {
+    handled: [Option<(u16, params::RomeAckStatus)>; RECEIVER_WINDOW],
+    next: usize,
+}
+
+impl OrderReceiver {
+    pub const fn new() -> Self {
+        Self {
+            handled: [None; RECEIVER_WINDOW],
+            next: 0,
+        }
+    }
+
+    /// Return the status of an already handled order, `None` if the order is new
+    pub fn handled_status(&self, seq: u16) -> Option<params::RomeAckStatus> {
+        self.handled.iter().flatten().find(|(handled_seq, _)| *handled_seq == seq).map(|(_, status)| *status)
+    }
+
+    /// Remember a handled order, forget the oldest one
+    pub fn record(&mut self, seq: u16, status: params::RomeAckStatus) {
+        self.handled[self.next] = Some((seq, status));
+        self.next = (self.next + 1) % RECEIVER_WINDOW;
+    }
+
+    /// Forget all handled orders
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for OrderReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Event returned by [OrderSender::poll()]
+#[cfg(feature = "std")]
+#[derive(Debug, PartialEq, Eq)]
+pub enum OrderEvent {
+    /// Order has not been acknowledged in time, data must be sent again
+    Retry(u16, Box<[u8]>),
+    /// Order has not been acknowledged after all retries, it has been dropped
+    Expired(u16),
+}
+
+#[cfg(feature = "std")]
+struct PendingOrder {
+    seq: u16,
+    data: Box<[u8]>,
+    deadline: Instant,
+    retries: u8,
+}
+
+/// Track orders sent with a sequence number, until they are acknowledged
+///
+/// The sender does not do any I/O: encoded orders are returned to the caller
+/// which is in charge of sending them.
+#[cfg(feature = "std")]
+pub struct OrderSender {
+    next_seq: u16,
+    timeout: Duration,
+    max_retries: u8,
+    pending: Vec<PendingOrder>,
+}
+
+#[cfg(feature = "std")]
+impl OrderSender {
+    /// Create a sender, orders are sent at most `max_retries + 1` times
+    pub fn new(timeout: Duration, max_retries: u8) -> Self {
+        Self {
+            next_seq: 0,
+            timeout,
+            max_retries,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Register a new order, return its sequence number and the data to send
+    pub fn send(&mut self, message: &Message, now: Instant) -> (u16, Box<[u8]>) {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let data = encode_envelope(seq, message);
+        self.pending.push(PendingOrder {
+            seq,
+            data: data.clone(),
+            deadline: now + self.timeout,
+            retries: 0,
+        });
+        (seq, data)
+    }
+
+    /// Process a received acknowledgement, return true if the order was pending
+    pub fn on_ack(&mut self, seq: u16) -> bool {
+        let count = self.pending.len();
+        self.pending.retain(|order| order.seq != seq);
+        self.pending.len() != count
+    }
+
+    /// Check for timed out orders
+    pub fn poll(&mut self, now: Instant) -> Vec<OrderEvent> {
+        let mut events = Vec::new();
+        self.pending.retain_mut(|order| {
+            if now < order.deadline {
+                true
+            } else if order.retries < self.max_retries {
+                order.retries += 1;
+                order.deadline = now + self.timeout;
+                events.push(OrderEvent::Retry(order.seq, order.data.clone()));
+                true
+            } else {
+                events.push(OrderEvent::Expired(order.seq));
+                false
+            }
+        });
+        events
+    }
+
+    /// Return the number of orders waiting for an acknowledgement
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_roundtrip() {
+        let message = Message::AsservGotoXy { x: 0.0, y: 300.0 };
+        let data = encode_envelope(0x1234, &message);
+        assert_eq!(&data[..3], &[ENVELOPE_ID, 0x34, 0x12]);
+        assert_eq!(&data[3..], &*message.encode());
+
+        let (seq, payload) = split_envelope(&data);
+        assert_eq!(seq, Some(0x1234));
+        assert!(matches!(Message::decode(payload), Ok(Message::AsservGotoXy { x: 0.0, y: 300.0 })));
+    }
+
+    #[test]
+    fn split_without_envelope() {
+        let data = Message::AsservGotoA { a: 1.5 }.encode();
+        assert_eq!(split_envelope(&data), (None, &*data));
+    }
+
+    #[test]
+    fn sender_ack() {
+        let now = Instant::now();
+        let mut sender = OrderSender::new(Duration::from_millis(100), 2);
+        let (seq0, _) = sender.send(&Message::AsservGotoA { a: 1.5 }, now);
+        let (seq1, _) = sender.send(&Message::AsservGotoA { a: 0.5 }, now);
+        assert_ne!(seq0, seq1);
+        assert_eq!(sender.pending_count(), 2);
+
+        assert!(sender.on_ack(seq0));
+        assert!(!sender.on_ack(seq0));
+        assert_eq!(sender.pending_count(), 1);
+        assert_eq!(sender.poll(now + Duration::from_millis(50)), []);
+    }
+
+    #[test]
+    fn sender_retry_and_expire() {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let mut sender = OrderSender::new(timeout, 1);
+        let (seq, data) = sender.send(&Message::AsservGotoA { a: 1.5 }, now);
+
+        assert_eq!(sender.poll(now + timeout), [OrderEvent::Retry(seq, data)]);
+        assert_eq!(sender.poll(now + timeout + timeout / 2), []);
+        assert_eq!(sender.poll(now + timeout * 2), [OrderEvent::Expired(seq)]);
+        assert_eq!(sender.pending_count(), 0);
+    }
+
+    #[test]
+    fn receiver_duplicates() {
+        let now = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let mut sender = OrderSender::new(timeout, 1);
+        let mut receiver = OrderReceiver::new();
+
+        // Order is handled, but its acknowledgement is lost
+        let (seq, data) = sender.send(&Message::AsservGotoARel { da: 0.5 }, now);
+        let (received_seq, _) = split_envelope(&data);
+        assert_eq!(received_seq, Some(seq));
+        assert_eq!(receiver.handled_status(seq), None);
+        receiver.record(seq, params::RomeAckStatus::Ok);
+
+        // Resent order is detected, with its status
+        let [OrderEvent::Retry(_, data)] = &sender.poll(now + timeout)[..] else {
+            panic!("retry expected");
+        };
+        let (received_seq, _) = split_envelope(data);
+        assert_eq!(receiver.handled_status(received_seq.unwrap()), Some(params::RomeAckStatus::Ok));
+
+        // Oldest orders are forgotten
+        for i in 1..=RECEIVER_WINDOW as u16 {
+            assert_eq!(receiver.handled_status(seq + i), None);
+            receiver.record(seq + i, params::RomeAckStatus::Unhandled);
+        }
+        assert_eq!(receiver.handled_status(seq), None);
+        assert_eq!(receiver.handled_status(seq + 1), Some(params::RomeAckStatus::Unhandled));
+
+        // A new peer restarts its sequence numbers
+        receiver.reset();
+        assert_eq!(receiver.handled_status(seq + 1), None);
+    }
+}
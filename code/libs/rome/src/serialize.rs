@@ -1,19 +1,36 @@
+use crate::EncodeError;
+
 
 pub trait Writer {
-    fn write(&mut self, data: &[u8]);
+    /// Write data, fail if there is not enough room to write it
+    fn write(&mut self, data: &[u8]) -> Result<(), EncodeError>;
 }
 
 
+#[cfg(feature = "std")]
 impl Writer for Vec<u8> {
-    fn write(&mut self, data: &[u8]) {
-        self.extend_from_slice(data)
+    fn write(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+impl Writer for &mut [u8] {
+    fn write(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        if data.len() > self.len() {
+            return Err(EncodeError::BufferOverflow);
+        }
+        let (a, b) = core::mem::take(self).split_at_mut(data.len());
+        a.copy_from_slice(data);
+        *self = b;
+        Ok(())
     }
 }
 
 
 pub trait Serialize {
     fn serialized_size(&self) -> usize;
-    fn serialize<W: Writer>(&self, encoder: &mut W);
+    fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError>;
 }
 
 macro_rules! impl_serialize_le_bytes {
@@ -21,7 +38,7 @@ macro_rules! impl_serialize_le_bytes {
         impl Serialize for $name {
             fn serialized_size(&self) -> usize { core::mem::size_of::<Self>() }
 
-            fn serialize<W: Writer>(&self, encoder: &mut W) {
+            fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
                 encoder.write(&self.to_le_bytes())
             }
         }
@@ -39,7 +56,7 @@ impl_serialize_le_bytes!(f32);
 impl Serialize for bool {
     fn serialized_size(&self) -> usize { core::mem::size_of::<Self>() }
 
-    fn serialize<W: Writer>(&self, encoder: &mut W) {
+    fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
         u8::from(*self).serialize(encoder)
     }
 }
@@ -47,10 +64,35 @@ impl Serialize for bool {
 impl<T: Serialize, const N: usize> Serialize for [T; N] {
     fn serialized_size(&self) -> usize { core::mem::size_of::<Self>() }
 
-    fn serialize<W: Writer>(&self, encoder: &mut W) {
+    fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
         for item in self {
-            item.serialize(encoder);
+            item.serialize(encoder)?;
         }
+        Ok(())
     }
 }
 
+impl<const N: usize> Serialize for heapless::String<N> {
+    fn serialized_size(&self) -> usize { 1 + self.len() }
+
+    fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
+        let len = u8::try_from(self.len()).map_err(|_| EncodeError::LengthOverflow)?;
+        len.serialize(encoder)?;
+        encoder.write(self.as_bytes())
+    }
+}
+
+impl<T: Serialize, const N: usize> Serialize for heapless::Vec<T, N> {
+    fn serialized_size(&self) -> usize {
+        1 + self.iter().map(|item| item.serialized_size()).sum::<usize>()
+    }
+
+    fn serialize<W: Writer>(&self, encoder: &mut W) -> Result<(), EncodeError> {
+        let len = u8::try_from(self.len()).map_err(|_| EncodeError::LengthOverflow)?;
+        len.serialize(encoder)?;
+        for item in self {
+            item.serialize(encoder)?;
+        }
+        Ok(())
+    }
+}
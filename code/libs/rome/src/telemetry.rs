@@ -0,0 +1,195 @@
+//! Telemetry scheduling, with periods set by peers
+//!
+//! [TmScheduler] tracks telemetry messages sent by a device, each with its own
+//! period. Peers change periods with `TmSubscribe` orders, which are handled by
+//! the scheduler through [RomeHandler]; a `message_id` of [ALL_TELEMETRY]
+//! applies to all telemetry messages.
+//!
+//! The scheduler does not create nor send messages: it is polled regularly and
+//! returns the IDs of messages to send.
+//! ```
+//! # use std::time::{Duration, Instant};
+//! # use rome::{Message, MessageId, params};
+//! # use rome::telemetry::TmScheduler;
+//! let mut scheduler = TmScheduler::new();
+//! scheduler.register(MessageId::BatteryLevel, Some(Duration::from_millis(2000)));
+//!
+//! for message_id in scheduler.poll(Instant::now()) {
+//!     let message = match message_id {
+//!         MessageId::BatteryLevel => Message::BatteryLevel { mv: 12000, percent: 80 },
+//!         _ => continue,
+//!     };
+//!     // Send message...
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+use crate::{Handled, MessageId, RomeHandler};
+
+/// Value of `TmSubscribe.message_id` to apply to all telemetry messages
+pub const ALL_TELEMETRY: u8 = 0;
+
+
+struct TmEntry {
+    message_id: MessageId,
+    default_period: Option<Duration>,
+    period: Option<Duration>,
+    /// Next instant the message is due, `None` if due on next poll
+    next: Option<Instant>,
+}
+
+/// Schedule telemetry messages, each with its own period
+#[derive(Default)]
+pub struct TmScheduler {
+    entries: Vec<TmEntry>,
+}
+
+impl TmScheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a telemetry message, with its default period (`None` to disable it)
+    ///
+    /// Only registered messages can be subscribed to.
+    /// If the message is already registered, its period is reset to the new default.
+    pub fn register(&mut self, message_id: MessageId, default_period: Option<Duration>) {
+        let entry = TmEntry { message_id, default_period, period: default_period, next: None };
+        match self.entries.iter_mut().find(|entry| entry.message_id == message_id) {
+            Some(old_entry) => *old_entry = entry,
+            None => self.entries.push(entry),
+        }
+    }
+
+    /// Set the period of a telemetry message, `None` to disable it
+    ///
+    /// Return `false` if the message is not registered.
+    pub fn set_period(&mut self, message_id: MessageId, period: Option<Duration>) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.message_id == message_id) {
+            Some(entry) => {
+                entry.set_period(period);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Set the period of all telemetry messages, `None` to disable them
+    pub fn set_all_periods(&mut self, period: Option<Duration>) {
+        for entry in &mut self.entries {
+            entry.set_period(period);
+        }
+    }
+
+    /// Restore default periods of all telemetry messages
+    pub fn reset(&mut self) {
+        for entry in &mut self.entries {
+            entry.set_period(entry.default_period);
+        }
+    }
+
+    /// Return the current period of a telemetry message, `None` if disabled or not registered
+    pub fn period(&self, message_id: MessageId) -> Option<Duration> {
+        self.entries.iter().find(|entry| entry.message_id == message_id)?.period
+    }
+
+    /// Return IDs of messages to send now
+    ///
+    /// A message whose period has just been changed is due immediately.
+    pub fn poll(&mut self, now: Instant) -> impl Iterator<Item = MessageId> + '_ {
+        self.entries.iter_mut().filter_map(move |entry| {
+            let period = entry.period?;
+            if entry.next.is_some_and(|next| now < next) {
+                return None;
+            }
+            entry.next = Some(now + period);
+            Some(entry.message_id)
+        })
+    }
+}
+
+impl TmEntry {
+    fn set_period(&mut self, period: Option<Duration>) {
+        self.period = period;
+        self.next = None;
+    }
+}
+
+impl RomeHandler for TmScheduler {
+    fn on_tm_subscribe(&mut self, message_id: u8, period_ms: u16) -> Handled {
+        let period = (period_ms != 0).then(|| Duration::from_millis(period_ms as u64));
+        if message_id == ALL_TELEMETRY {
+            self.set_all_periods(period);
+            Handled::Yes
+        } else {
+            match MessageId::try_from(message_id) {
+                Ok(message_id) => self.set_period(message_id, period).into(),
+                Err(_) => Handled::No,
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Message;
+
+    fn scheduler() -> TmScheduler {
+        let mut scheduler = TmScheduler::new();
+        scheduler.register(MessageId::AsservTmStatus, Some(Duration::from_millis(100)));
+        scheduler.register(MessageId::BatteryLevel, Some(Duration::from_millis(1000)));
+        scheduler.register(MessageId::AsservTmVelocity, None);
+        scheduler
+    }
+
+    fn poll(scheduler: &mut TmScheduler, now: Instant) -> Vec<MessageId> {
+        scheduler.poll(now).collect()
+    }
+
+    #[test]
+    fn default_periods() {
+        let now = Instant::now();
+        let mut scheduler = scheduler();
+        assert_eq!(poll(&mut scheduler, now), [MessageId::AsservTmStatus, MessageId::BatteryLevel]);
+        assert_eq!(poll(&mut scheduler, now + Duration::from_millis(50)), []);
+        assert_eq!(poll(&mut scheduler, now + Duration::from_millis(100)), [MessageId::AsservTmStatus]);
+        assert_eq!(poll(&mut scheduler, now + Duration::from_millis(1000)), [MessageId::AsservTmStatus, MessageId::BatteryLevel]);
+    }
+
+    #[test]
+    fn subscribe() {
+        let now = Instant::now();
+        let mut scheduler = scheduler();
+        assert_eq!(poll(&mut scheduler, now).len(), 2);
+
+        let message = Message::TmSubscribe { message_id: MessageId::AsservTmVelocity as u8, period_ms: 10 };
+        assert_eq!(message.dispatch_to(&mut scheduler), Handled::Yes);
+        assert_eq!(scheduler.period(MessageId::AsservTmVelocity), Some(Duration::from_millis(10)));
+        assert_eq!(poll(&mut scheduler, now), [MessageId::AsservTmVelocity]);
+        assert_eq!(poll(&mut scheduler, now + Duration::from_millis(10)), [MessageId::AsservTmVelocity]);
+
+        let message = Message::TmSubscribe { message_id: MessageId::BatteryLevel as u8, period_ms: 0 };
+        assert_eq!(message.dispatch_to(&mut scheduler), Handled::Yes);
+        assert_eq!(scheduler.period(MessageId::BatteryLevel), None);
+
+        // Not registered, or not a valid ID
+        let message = Message::TmSubscribe { message_id: MessageId::AsservGotoXy as u8, period_ms: 10 };
+        assert_eq!(message.dispatch_to(&mut scheduler), Handled::No);
+        let message = Message::TmSubscribe { message_id: 0xFE, period_ms: 10 };
+        assert_eq!(message.dispatch_to(&mut scheduler), Handled::No);
+    }
+
+    #[test]
+    fn subscribe_all() {
+        let now = Instant::now();
+        let mut scheduler = scheduler();
+        let message = Message::TmSubscribe { message_id: ALL_TELEMETRY, period_ms: 0 };
+        assert_eq!(message.dispatch_to(&mut scheduler), Handled::Yes);
+        assert_eq!(poll(&mut scheduler, now), []);
+
+        scheduler.reset();
+        assert_eq!(poll(&mut scheduler, now), [MessageId::AsservTmStatus, MessageId::BatteryLevel]);
+    }
+}
@@ -0,0 +1,334 @@
+//! Text representation of messages
+//!
+//! Messages are formatted as their name followed by parameters, separated by spaces:
+//! named parameters as `name=value`, positional parameters as values only.
+//! ```text
+//! AsservGotoXy x=120.0 y=300.0
+//! AsservActivate true
+//! ```
+//! Values are formatted as follows:
+//!  - scalars: using Rust syntax, floats always have a decimal point
+//!  - choices and enums: value name, as declared in YAML (`left`)
+//!  - flags: names separated by `|` (`ground|top`), `0` if empty
+//!  - arrays: `[1,2,3]`
+//!  - strings: double-quoted, with `"` and `\` escaped by a `\`
+//!  - structs: `{x=1.0,y=2.0}`
+//!
+//! Parsing accepts the same syntax. Parameters must be provided in declaration order.
+//! Optional parameters can be omitted, they are then set to their default value.
+//! Extra spaces are allowed between tokens.
+
+use core::fmt;
+
+/// Error returned when parsing a message from text
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    /// Unknown message name
+    UnknownMessage,
+    /// Text ends before the message is complete
+    UnexpectedEnd,
+    /// Unexpected character at given position
+    Unexpected(usize),
+    /// Invalid value at given position
+    BadValue(usize),
+}
+
+
+/// Value that can be formatted to and parsed from text
+pub(crate) trait TextValue: Sized {
+    fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result;
+    fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError>;
+}
+
+/// Parse text, track position for error reporting
+pub(crate) struct TextParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> TextParser<'a> {
+    pub fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    fn remaining(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let remaining = self.remaining();
+        self.pos += remaining.len() - remaining.trim_start().len();
+    }
+
+    /// Return the next non-space character, without consuming it
+    pub fn peek(&mut self) -> Option<char> {
+        self.skip_spaces();
+        self.remaining().chars().next()
+    }
+
+    /// Consume given character, fail if it is not the next one
+    pub fn expect(&mut self, c: char) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += c.len_utf8();
+                Ok(())
+            }
+            Some(_) => Err(ParseError::Unexpected(self.pos)),
+            None => Err(ParseError::UnexpectedEnd),
+        }
+    }
+
+    /// Consume a word: name, number, boolean...
+    ///
+    /// Return the word and its position.
+    pub fn word(&mut self) -> Result<(&'a str, usize), ParseError> {
+        self.skip_spaces();
+        let remaining = self.remaining();
+        let size = remaining
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_.+-".contains(c)))
+            .unwrap_or(remaining.len());
+        if size == 0 {
+            return Err(if remaining.is_empty() { ParseError::UnexpectedEnd } else { ParseError::Unexpected(self.pos) });
+        }
+        let pos = self.pos;
+        self.pos += size;
+        Ok((&remaining[..size], pos))
+    }
+
+    /// Consume a word, fail if it is not the expected one
+    pub fn keyword(&mut self, keyword: &str) -> Result<(), ParseError> {
+        match self.word()? {
+            (word, _) if word == keyword => Ok(()),
+            (_, pos) => Err(ParseError::Unexpected(pos)),
+        }
+    }
+
+    /// Parse a named parameter: `name=value`
+    pub fn named<T: TextValue>(&mut self, name: &str) -> Result<T, ParseError> {
+        self.keyword(name)?;
+        self.expect('=')?;
+        T::parse_text(self)
+    }
+
+    /// Parse a positional parameter
+    pub fn positional<T: TextValue>(&mut self) -> Result<T, ParseError> {
+        T::parse_text(self)
+    }
+
+    /// Parse an optional named parameter, return `default` if there is no text left
+    pub fn named_or<T: TextValue>(&mut self, name: &str, default: T) -> Result<T, ParseError> {
+        if self.peek().is_none() { Ok(default) } else { self.named(name) }
+    }
+
+    /// Parse an optional positional parameter, return `default` if there is no text left
+    #[allow(dead_code)]  // Unused if no message has optional positional parameters
+    pub fn positional_or<T: TextValue>(&mut self, default: T) -> Result<T, ParseError> {
+        if self.peek().is_none() { Ok(default) } else { self.positional() }
+    }
+
+    /// Parse a comma-separated list of items between delimiters
+    ///
+    /// `parse_item` is called for each item.
+    pub fn list(&mut self, start: char, end: char, mut parse_item: impl FnMut(&mut Self) -> Result<(), ParseError>) -> Result<(), ParseError> {
+        self.expect(start)?;
+        if self.peek() == Some(end) {
+            return self.expect(end);
+        }
+        loop {
+            parse_item(self)?;
+            if self.peek() == Some(',') {
+                self.expect(',')?;
+            } else {
+                return self.expect(end);
+            }
+        }
+    }
+
+    /// Check that all text has been parsed
+    pub fn end(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(ParseError::Unexpected(self.pos)),
+        }
+    }
+
+    /// Return an error for a bad value at the current position
+    pub fn bad_value(&self) -> ParseError {
+        ParseError::BadValue(self.pos)
+    }
+}
+
+
+macro_rules! impl_text_value_scalar {
+    ($($t:ty),*) => {
+        $(
+            impl TextValue for $t {
+                fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "{self:?}")
+                }
+
+                fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+                    let (word, pos) = parser.word()?;
+                    word.parse().map_err(|_| ParseError::BadValue(pos))
+                }
+            }
+        )*
+    }
+}
+
+impl_text_value_scalar!(bool, u8, i8, u16, i16, u32, i32, f32);
+
+impl<T: TextValue, const N: usize> TextValue for [T; N] {
+    fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_list(self, f)
+    }
+
+    fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+        let mut values = heapless::Vec::<T, N>::new();
+        let pos = parser.pos;
+        parser.list('[', ']', |parser| {
+            let value = T::parse_text(parser)?;
+            values.push(value).map_err(|_| parser.bad_value())
+        })?;
+        values.into_array().map_err(|_| ParseError::BadValue(pos))
+    }
+}
+
+impl<T: TextValue, const N: usize> TextValue for heapless::Vec<T, N> {
+    fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_list(self, f)
+    }
+
+    fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+        let mut values = Self::new();
+        parser.list('[', ']', |parser| {
+            let value = T::parse_text(parser)?;
+            values.push(value).map_err(|_| parser.bad_value())
+        })?;
+        Ok(values)
+    }
+}
+
+impl<const N: usize> TextValue for heapless::String<N> {
+    fn fmt_text(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in self.chars() {
+            if c == '"' || c == '\\' {
+                f.write_str("\\")?;
+            }
+            write!(f, "{c}")?;
+        }
+        f.write_str("\"")
+    }
+
+    fn parse_text(parser: &mut TextParser) -> Result<Self, ParseError> {
+        parser.expect('"')?;
+        let mut value = Self::new();
+        let mut escaped = false;
+        for (i, c) in parser.remaining().char_indices() {
+            if !escaped && c == '"' {
+                parser.pos += i + 1;
+                return Ok(value);
+            } else if !escaped && c == '\\' {
+                escaped = true;
+            } else {
+                escaped = false;
+                value.push(c).map_err(|_| ParseError::BadValue(parser.pos + i))?;
+            }
+        }
+        Err(ParseError::UnexpectedEnd)
+    }
+}
+
+fn fmt_list<T: TextValue>(values: &[T], f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("[")?;
+    for (i, value) in values.iter().enumerate() {
+        if i != 0 {
+            f.write_str(",")?;
+        }
+        value.fmt_text(f)?;
+    }
+    f.write_str("]")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, params};
+
+    fn roundtrip(text: &str) -> Message {
+        let message: Message = text.parse().unwrap();
+        assert_eq!(message.to_string(), text);
+        message
+    }
+
+    #[test]
+    fn message_name() {
+        assert_eq!(Message::AsservGotoXy { x: 120.0, y: 300.0 }.name(), "AsservGotoXy");
+        assert_eq!(Message::AsservActivate(true).name(), "AsservActivate");
+    }
+
+    #[test]
+    fn text_roundtrip() {
+        assert!(matches!(roundtrip("AsservGotoXy x=120.0 y=-300.5"), Message::AsservGotoXy { x: 120.0, y: -300.5 }));
+        assert!(matches!(roundtrip("AsservActivate true"), Message::AsservActivate(true)));
+        assert!(matches!(roundtrip("MatchTm team=left time_ms=1200"), Message::MatchTm { team: params::Team::Left, time_ms: 1200 }));
+        let message = roundtrip("AsservHoloSetMotorsConf velocities_to_consigns=[1.0,0.0,0.0,0.0,1.0,0.0,0.0,0.0,1.0] encoders_to_position=[0.5,0.0,0.0,0.0,0.5,0.0,0.0,0.0,0.5]");
+        assert!(matches!(message, Message::AsservHoloSetMotorsConf { velocities_to_consigns: [1.0, ..], encoders_to_position: [0.5, ..] }));
+        let message = roundtrip("TopLidarTm chunk_index=1 num_chunks=7 angles=[10,20] distances=[] intensities=[3]");
+        assert!(matches!(message, Message::TopLidarTm { ref angles, .. } if angles == &[10, 20]));
+    }
+
+    #[test]
+    fn text_optional_param() {
+        let message = roundtrip("AsservHoloSetPidConf pid=y gain_p=1 gain_i=0 gain_d=2 max_in=0 max_i=100 max_out=0 out_shift=4");
+        assert!(matches!(message, Message::AsservHoloSetPidConf { out_shift: 4, .. }));
+        let message: Message = "AsservHoloSetPidConf pid=y gain_p=1 gain_i=0 gain_d=2 max_in=0 max_i=100 max_out=0".parse().unwrap();
+        assert!(matches!(message, Message::AsservHoloSetPidConf { max_i: 100, out_shift: 0, .. }));
+    }
+
+    #[test]
+    fn text_parse_spaces() {
+        let message: Message = "  AsservGotoXy  x = 1  y=2.5 ".parse().unwrap();
+        assert!(matches!(message, Message::AsservGotoXy { x: 1.0, y: 2.5 }));
+    }
+
+    #[test]
+    fn text_parse_errors() {
+        assert_eq!("Unknown".parse::<Message>().unwrap_err(), ParseError::UnknownMessage);
+        assert_eq!("AsservGotoXy x=1.0".parse::<Message>().unwrap_err(), ParseError::UnexpectedEnd);
+        assert_eq!("AsservGotoXy y=1.0 x=2.0".parse::<Message>().unwrap_err(), ParseError::Unexpected(13));
+        assert_eq!("AsservGotoXy x=abc y=1.0".parse::<Message>().unwrap_err(), ParseError::BadValue(15));
+        assert_eq!("AsservGotoA a=1.0 extra".parse::<Message>().unwrap_err(), ParseError::Unexpected(18));
+        assert_eq!("MatchTm team=up time_ms=0".parse::<Message>().unwrap_err(), ParseError::BadValue(13));
+        assert!(matches!("AsservHoloSetMotorsConf velocities_to_consigns=[1.0] encoders_to_position=[]".parse::<Message>(), Err(ParseError::BadValue(_))));
+    }
+
+    #[test]
+    fn text_string() {
+        let value: heapless::String<8> = "a \"b\\".try_into().unwrap();
+        let text = format!("{}", Text(&value));
+        assert_eq!(text, r#""a \"b\\""#);
+        let parsed = heapless::String::<8>::parse_text(&mut TextParser::new(&text)).unwrap();
+        assert_eq!(parsed, value);
+        assert!(heapless::String::<2>::parse_text(&mut TextParser::new(&text)).is_err());
+    }
+
+    #[test]
+    fn text_struct() {
+        let value = params::XYA { x: 1.0, y: 2.0, a: -0.5 };
+        let text = format!("{}", Text(&value));
+        assert_eq!(text, "{x=1.0,y=2.0,a=-0.5}");
+        assert_eq!(params::XYA::parse_text(&mut TextParser::new(&text)).unwrap(), value);
+    }
+
+    /// Format any text value
+    struct Text<'a, T: TextValue>(&'a T);
+
+    impl<T: TextValue> fmt::Display for Text<'_, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            self.0.fmt_text(f)
+        }
+    }
+}
@@ -53,17 +53,19 @@ fn main() {
             gain_p: 10,
             gain_i: 1,
             .. Default::default()
-        },
+        }.into(),
         pid_angle: PidConf {
             gain_p: 200,
             gain_i: 5,
             .. Default::default()
-        },
+        }.into(),
         trajectory: TrajectoryConf {
             a_speed: 30.0,
             a_acc: 100.0,
+            a_jerk: 0.0,
             xy_speed: 2000.0,
             xy_acc: 1000.0,
+            xy_jerk: 0.0,
             xy_stop_window: 20.0,
             xy_aim_angle_window: 0.05,
             xy_cruise_angle_window: 1.5,
@@ -71,6 +73,11 @@ fn main() {
             a_stop_window: 0.03,
             xy_idle_speed: 0.01,
             a_idle_speed: 0.01,
+            xy_path_lookahead: 100.0,
+            xy_path_lateral_acc: 2000.0,
+            autoset_speed: 100.0,
+            autoset_backoff: 50.0,
+            autoset_timeout: 5.0,
         },
         motors: MotorsConf::from_dimensions(75.0, 30.0, 256),
     });
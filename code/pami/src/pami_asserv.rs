@@ -4,6 +4,8 @@ use board_pami::{Encoder, PamiBoard, PamiMotor, PamiMotors};
 use embedded_hal::pwm::SetDutyCycle;
 
 pub const ASSERV_PERIOD: Duration = Duration::from_millis(15);
+/// Number of asserv updates kept in the trace (about 4.5 seconds)
+pub const ASSERV_TRACE_CAPACITY: usize = 300;
 
 
 type PamiMotorImpl<B> = PamiMotor<<B as PamiBoard>::MotorEncoder, <B as PamiBoard>::MotorPwm>;
@@ -1,12 +1,16 @@
 use std::time::{Duration, Instant};
 use asserv::differential::{conf::*, Asserv, rome::AsservDiffRome};
-use asserv::rome::AsservRome;
+use asserv::rome::{AsservRome, TraceStream};
 use board_common::{Color, Periodicity};
 use board_pami::{BatteryLevel, BatteryReader, PamiBoard, PamiButtons, PamiLeds, PamiPwmController};
 use embedded_hal::digital::StatefulOutputPin;
 use flume::{Receiver, Sender};
+use rome::{Handled, MessageId, RomeHandler};
+use rome::registry::ParamRegistry;
+use rome::reliable::OrderReceiver;
+use rome::telemetry::TmScheduler;
 use vlx::VlxSensor;
-use crate::pami_asserv::{ASSERV_PERIOD, PamiAsservHardware};
+use crate::pami_asserv::{ASSERV_PERIOD, ASSERV_TRACE_CAPACITY, PamiAsservHardware};
 use crate::events::*;
 
 
@@ -36,13 +40,21 @@ pub struct PamiRoutines<B: PamiBoard> {
     pub ui_events: Sender<UiEvent>,
     pub ui_triggers: Receiver<UiTrigger>,
 
+    // Telemetry periods, can be changed by ROME peers
+    tm_scheduler: TmScheduler,
+    // Runtime parameters, can be read and changed by ROME peers
+    params: ParamRegistry<Asserv<PamiAsservHardware<B>>>,
+    // Asserv trace dump, requested by ROME peers
+    trace_stream: TraceStream,
+    // Orders already handled, to not handle resent ones twice
+    order_receiver: OrderReceiver,
+
     // Periodicity states
     asserv_periodicity: Periodicity,
-    asserv_tm_periodicity: Periodicity,
     battery_level_periodicity: Periodicity,
 }
 
-impl<B: PamiBoard> PamiRoutines<B> {
+impl<B: PamiBoard + 'static> PamiRoutines<B> {
     const EMERGENCY_STOP_COLOR: Color = Color::RED;
 
     /// Initialize with default state values and peripherals from board
@@ -63,8 +75,21 @@ impl<B: PamiBoard> PamiRoutines<B> {
         let mut pwm_controller = board.pwm_controller().unwrap();
         pwm_controller.init().unwrap();
 
+        let mut tm_scheduler = TmScheduler::new();
+        tm_scheduler.register(MessageId::AsservTmStatus, Some(Duration::from_millis(100)));
+        tm_scheduler.register(MessageId::AsservDiffTmStatus, Some(Duration::from_millis(100)));
+        tm_scheduler.register(MessageId::AsservDiffTmPath, Some(Duration::from_millis(100)));
+        tm_scheduler.register(MessageId::BatteryLevel, Some(Duration::from_millis(2000)));
+
+        let mut params = ParamRegistry::new();
+        asserv::rome::register_pid_params(&mut params, "asserv.dist", Asserv::dist_pid_conf, Asserv::set_dist_pid_conf);
+        asserv::rome::register_pid_params(&mut params, "asserv.angle", Asserv::angle_pid_conf, Asserv::set_angle_pid_conf);
+
+        let mut asserv = Asserv::new(PamiAsservHardware::new(board), ASSERV_PERIOD);
+        asserv.cs.enable_trace(ASSERV_TRACE_CAPACITY);
+
         Self {
-            asserv: Asserv::new(PamiAsservHardware::new(board), ASSERV_PERIOD),
+            asserv,
             emergency_stop: false,
             battery_level: Default::default(),
 
@@ -80,8 +105,12 @@ impl<B: PamiBoard> PamiRoutines<B> {
             ui_events,
             ui_triggers,
 
+            tm_scheduler,
+            params,
+            trace_stream: TraceStream::new(),
+            order_receiver: OrderReceiver::new(),
+
             asserv_periodicity: Periodicity::new(ASSERV_PERIOD),
-            asserv_tm_periodicity: Periodicity::new(Duration::from_millis(100)),
             battery_level_periodicity: Periodicity::new(Duration::from_millis(2000)),
         }
     }
@@ -228,46 +257,86 @@ impl<B: PamiBoard> PamiRoutines<B> {
         // Process ROME input messages
         for data in self.rome_rx.try_iter() {
             let _ = self.pami_leds.com.toggle();
-            match rome::Message::decode(&data) {
-                Err(err) => log::error!("ROME RX error: {err:?}"),
+            let (seq, data) = rome::reliable::split_envelope(&data);
+            // Order resent because its acknowledgement was lost: acknowledge it again, don't handle it twice
+            let handled_status = seq.and_then(|seq| self.order_receiver.handled_status(seq));
+            if let (Some(seq), Some(status)) = (seq, handled_status) {
+                log::debug!("ROME: order {seq} already handled");
+                if let Err(err) = self.rome_tx.send(rome::Message::RomeAck { seq, status }.encode()) {
+                    log::error!("ROME send error: {:?}", err);
+                }
+                continue;
+            }
+            let status = match rome::Message::decode(data) {
+                Err(err) => {
+                    log::error!("ROME RX error: {err:?}");
+                    rome::params::RomeAckStatus::Invalid
+                }
                 Ok(message) => {
-                    if !self.asserv.on_rome_message(&message) {
-                        log::warn!("ROME: ignored message: {}", message.message_id());
+                    let mut handler = PamiRomeHandler { rome_tx: &self.rome_tx, order_receiver: &mut self.order_receiver };
+                    let mut handled = rome::dispatch(&message, &mut [&mut self.asserv, &mut self.tm_scheduler, &mut self.trace_stream, &mut handler]);
+                    if handled == Handled::No {
+                        let rome_tx = &self.rome_tx;
+                        handled = message.dispatch_to(&mut self.params.handler(&mut self.asserv, |response| {
+                            if let Err(err) = rome_tx.send(response.encode()) {
+                                log::error!("ROME send error: {:?}", err);
+                            }
+                        }));
                     }
+                    if handled == Handled::No {
+                        log::warn!("ROME: ignored message: {message}");
+                    }
+                    handled.into()
                 },
+            };
+            // Acknowledge orders sent with a sequence number
+            if let Some(seq) = seq {
+                self.order_receiver.record(seq, status);
+                if let Err(err) = self.rome_tx.send(rome::Message::RomeAck { seq, status }.encode()) {
+                    log::error!("ROME send error: {:?}", err);
+                }
             }
         }
 
-        // Update asserv, send asserv telemetry
+        // Update asserv
         if self.asserv_periodicity.update(now) {
             self.asserv.update(self.asserv_periodicity.period());
         }
-        if self.asserv_tm_periodicity.update(now) {
-            if let Err(err) = self.rome_tx.send(self.asserv.asserv_tm_status().encode()) {
-                log::error!("ROME send error: {:?}", err);
-            }
-            if let Err(err) = self.rome_tx.send(self.asserv.asserv_diff_tm_status().encode()) {
-                log::error!("ROME send error: {:?}", err);
-            }
-        }
 
-        // Battery level, send update to ROME and UI
+        // Battery level, update UI (sent to ROME as telemetry)
         if self.battery_level_periodicity.update(now) {
             let level = self.battery_reader.read_vbatt();
-
-            let message = rome::Message::BatteryLevel { mv: level.mv, percent: level.percent };
-            if let Err(err) = self.rome_tx.send(message.encode()) {
-                log::error!("BLE send error: {:?}", err);
-            }
-
             if level.percent != self.battery_level.percent {
                 log::info!("Battery: {} mV, {}%", level.mv, level.percent);
                 self.ui_events.send(UiEvent::Battery { percent: level.percent }).unwrap();
                 self.pwm_controller.set_battery_rgb(&Self::battery_led_color(level.percent));
             }
-
             self.battery_level = level;
         }
+
+        // Send telemetry
+        for message_id in self.tm_scheduler.poll(*now) {
+            let message = match message_id {
+                MessageId::AsservTmStatus => self.asserv.asserv_tm_status(),
+                MessageId::AsservDiffTmStatus => self.asserv.asserv_diff_tm_status(),
+                MessageId::AsservDiffTmPath => match self.asserv.asserv_diff_tm_path() {
+                    Some(message) => message,
+                    None => continue,
+                },
+                MessageId::BatteryLevel => rome::Message::BatteryLevel { mv: self.battery_level.mv, percent: self.battery_level.percent },
+                _ => continue,
+            };
+            if let Err(err) = self.rome_tx.send(message.encode()) {
+                log::error!("ROME send error: {:?}", err);
+            }
+        }
+
+        // Send trace dump, one message at a time
+        if let Some(message) = self.trace_stream.poll(self.asserv.cs.trace_mut()) {
+            if let Err(err) = self.rome_tx.send(message.encode()) {
+                log::error!("ROME send error: {:?}", err);
+            }
+        }
     }
 
     /// Wait for the next asserv step, the run `idle()` and return the associated instant
@@ -333,3 +402,26 @@ impl BlinkingColor {
     }
 }
 
+
+/// Handle ROME messages not handled by the asserv
+struct PamiRomeHandler<'a> {
+    rome_tx: &'a Sender<Box<[u8]>>,
+    order_receiver: &'a mut OrderReceiver,
+}
+
+impl RomeHandler for PamiRomeHandler<'_> {
+    /// Check peer's schema, answer with our own `RomeHello`
+    fn on_rome_hello(&mut self, schema_hash: u32, device: rome::params::RomeHelloDevice) -> Handled {
+        match rome::check_schema_hash(schema_hash) {
+            Ok(()) => log::info!("ROME: hello from {device:?}"),
+            Err(err) => log::warn!("ROME: hello from {device:?} with incompatible messages: {err:?}"),
+        }
+        // New peer, its sequence numbers restart
+        self.order_receiver.reset();
+        let message = rome::Message::hello(rome::params::RomeHelloDevice::Pami);
+        if let Err(err) = self.rome_tx.send(message.encode()) {
+            log::error!("ROME send error: {:?}", err);
+        }
+        Handled::Yes
+    }
+}